@@ -28,7 +28,7 @@ fi
 mkdir -p "${PROJECT}/data/tmp/wave"
 (
     cd "${PROJECT}"
-    rm -f data/tmp/wave/*.{pgm,png}
+    rm -f data/tmp/wave/*.{pgm,png,gif}
     ls -al data/tmp/wave
     export RUST_LOG="${LOG_LEVEL}"
     export RUST_BACKTRACE=1
@@ -0,0 +1,106 @@
+//! A rule that is not built into the crate: each cell takes the value of the majority of itself and its effectors.
+//!
+//! The same rule runs on a `CellTorus` and on a `PatchTorus`, and both report the same number of live cells.
+//!
+//! ```text
+//! cargo run --example custom_state
+//! ```
+
+use std::fmt::{Display, Write};
+
+use anyhow::{Result, anyhow};
+use quantized_interactions::prelude::*;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Majority(bool);
+
+impl<Gen: Generation> State<Gen> for Majority {
+    type Parameters = ();
+
+    fn update<Spc: Space<Self, Gen>>(
+        space: &Spc,
+        region: &Spc::Reg,
+        location: &Spc::Loc,
+        _context: &UpdateContext<(), Gen>,
+    ) -> Result<Self> {
+        let this_state = (region.state(location) as Option<Self>)
+            .ok_or_else(|| anyhow!("Cell without state: [{}]", location.id(space)))?;
+        let mut votes = if this_state.0 { 1 } else { -1 };
+        for effector in location.effectors(space)? {
+            if let Some(Majority(alive)) = region.state(&effector) {
+                votes += if alive { 1 } else { -1 };
+            }
+        }
+        Ok(Majority(votes > 0))
+    }
+}
+
+impl Display for Majority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_char(if self.0 { '#' } else { '.' })
+    }
+}
+
+impl GrayScale for Majority {
+    type Context = ();
+
+    fn gray_value(&self, _context: &()) -> u8 {
+        if self.0 { 255 } else { 0 }
+    }
+}
+
+const WIDTH: usize = 40;
+const HEIGHT: usize = 30;
+const GENERATIONS: usize = 10;
+
+/// A fixed scatter of live cells, so that both tori start from the same pattern.
+fn initial(x: usize, y: usize) -> Majority {
+    Majority((x * 7 + y * 13 + x * y) % 9 < 5)
+}
+
+fn run<T: Torus<Majority, usize>>(torus: T) -> Result<usize> {
+    let mut simulation = Simulation::<T, Majority, usize>::new(torus, 0).observe(
+        Cadence::end(),
+        |torus: &T, generation: &usize, _step: usize| {
+            torus.info(generation);
+            Ok(())
+        },
+    );
+    simulation.run(GENERATIONS)?;
+    let generation = *simulation.generation();
+    let torus = simulation.finish()?;
+    let alive = torus
+        .space()
+        .reduce(&generation, 0, |region, location, alive| {
+            match region.state(location) {
+                Some(Majority(true)) => alive + 1,
+                _ => alive,
+            }
+        });
+    Ok(alive)
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let cell_torus = new_cell_torus(
+        Tiling::Hexagons,
+        &[HEIGHT, WIDTH],
+        0usize,
+        |v: &[usize]| initial(v[1], v[0]),
+    )?;
+    let on_cells = run(cell_torus)?;
+
+    let mut patch_torus = new_hexagonal_torus(Majority(false), 0usize, WIDTH, HEIGHT)?;
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            patch_torus.adjust(&0, x, y, initial(x, y))?;
+        }
+    }
+    let on_patches = run(patch_torus)?;
+
+    println!(
+        "Live cells after {GENERATIONS} generations: cell torus: {on_cells}, patch torus: {on_patches}"
+    );
+    Ok(())
+}
@@ -0,0 +1,371 @@
+//! # Emergent geometry
+//!
+//! If geometry emerges from the way cells are connected, it should be possible to measure it from the connections
+//! alone. This module explores a space with a breadth-first search over `Location::effectors` and derives:
+//!
+//! * the number of cells *N(r)* within graph distance *r* of an origin. If *N(r) ~ r<sup>d</sup>*, then *d* is the
+//!   Hausdorff dimension;
+//! * the probability *P(t)* that a lazy random walk is back at the origin after *t* steps. If
+//!   *P(t) ~ t<sup>-d<sub>s</sub>/2</sup>*, then *d<sub>s</sub>* is the spectral dimension;
+//! * for a torus, the ratio between the graph distance and the Euclidean distance in the plane in which the torus is
+//!   drawn. A ratio that varies with the direction means that the space is not isotropic at that scale.
+//!
+//! Both dimensions are estimated by a least-squares fit on a log-log scale, over radii that are small compared to the
+//! size of the space.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    hash::Hash,
+    ops::RangeInclusive,
+};
+
+use anyhow::{Result, anyhow};
+use log::info;
+
+use crate::{
+    cell::new_cell_torus,
+    patch::{CsrEffectors, new_patch_torus},
+    structure::{Generation, Location, Space, State, UpdateContext},
+    torus::{Tiling, Torus, geometry::Geometry},
+};
+
+/// The part of a space that can be reached from an origin. Nodes are numbered in the order in which they were found, so
+/// the origin is node `0`.
+pub struct Network<L> {
+    locations: Vec<L>,
+    neighbours: Vec<Vec<usize>>,
+}
+
+/// Collects all locations that can be reached from the origin through effectors.
+/// Copies of locations are replaced by their originals (see `Location::canonical`).
+pub fn explore<S, Gen, Spc>(space: &Spc, origin: &Spc::Loc) -> Result<Network<Spc::Loc>>
+where
+    S: State<Gen>,
+    Gen: Generation,
+    Spc: Space<S, Gen>,
+    Spc::Loc: Clone + Eq + Hash,
+{
+    let origin = origin.canonical(space);
+    let mut index = HashMap::new();
+    index.insert(origin.clone(), 0usize);
+    let mut locations = vec![origin];
+    let mut neighbours = Vec::new();
+    while neighbours.len() < locations.len() {
+        let i = neighbours.len();
+        let mut links = Vec::new();
+        let effectors = locations[i]
+            .effectors(space)?
+            .into_iter()
+            .map(|e| e.canonical(space))
+            .collect::<Vec<_>>();
+        for effector in effectors {
+            let j = *index.entry(effector.clone()).or_insert_with(|| {
+                locations.push(effector);
+                locations.len() - 1
+            });
+            if j != i && !links.contains(&j) {
+                links.push(j);
+            }
+        }
+        neighbours.push(links);
+    }
+    Ok(Network {
+        locations,
+        neighbours,
+    })
+}
+
+impl<L> Network<L> {
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    /// The graph distance from the origin to each node.
+    pub fn distances(&self) -> Vec<usize> {
+        let mut distances = vec![usize::MAX; self.len()];
+        let mut queue = VecDeque::new();
+        if !distances.is_empty() {
+            distances[0] = 0;
+            queue.push_back(0);
+        }
+        while let Some(i) = queue.pop_front() {
+            for j in self.neighbours[i].iter() {
+                if distances[*j] == usize::MAX {
+                    distances[*j] = distances[i] + 1;
+                    queue.push_back(*j);
+                }
+            }
+        }
+        distances
+    }
+
+    /// The number of nodes within distance *r* of the origin, for *r* from zero up to the eccentricity of the origin.
+    pub fn ball_sizes(&self) -> Vec<usize> {
+        let distances = self.distances();
+        let eccentricity = distances.iter().max().copied().unwrap_or_default();
+        let mut sizes = vec![0; eccentricity + 1];
+        for d in distances {
+            sizes[d] += 1;
+        }
+        for r in 1..sizes.len() {
+            sizes[r] += sizes[r - 1];
+        }
+        sizes
+    }
+
+    /// The probability that a lazy random walk that starts at the origin is back at the origin after *t* steps, for *t*
+    /// up to `steps`. In each step the walker stays where it is with probability ½ and otherwise moves to a random
+    /// neighbour.
+    pub fn return_probabilities(&self, steps: usize) -> Vec<f64> {
+        let mut probabilities = vec![0.0; self.len()];
+        let mut next = vec![0.0; self.len()];
+        let mut result = Vec::with_capacity(steps + 1);
+        if self.len() == 0 {
+            return result;
+        }
+        probabilities[0] = 1.0;
+        result.push(1.0);
+        for _ in 0..steps {
+            next.fill(0.0);
+            for (i, p) in probabilities.iter().enumerate() {
+                if *p == 0.0 {
+                    continue;
+                }
+                let links = &self.neighbours[i];
+                if links.is_empty() {
+                    next[i] += p;
+                    continue;
+                }
+                next[i] += p / 2.0;
+                let share = p / 2.0 / links.len() as f64;
+                for j in links {
+                    next[*j] += share;
+                }
+            }
+            std::mem::swap(&mut probabilities, &mut next);
+            result.push(probabilities[0]);
+        }
+        result
+    }
+}
+
+/// Estimates *d* in *N(r) ~ r<sup>d</sup>* from the ball sizes for the given radii.
+pub fn hausdorff_dimension(ball_sizes: &[usize], radii: RangeInclusive<usize>) -> Option<f64> {
+    let points = radii
+        .filter(|r| *r > 0 && *r < ball_sizes.len())
+        .map(|r| ((r as f64).ln(), (ball_sizes[r] as f64).ln()))
+        .collect::<Vec<_>>();
+    slope(&points)
+}
+
+/// Estimates *d<sub>s</sub>* in *P(t) ~ t<sup>-d<sub>s</sub>/2</sup>* from the return probabilities for the given
+/// numbers of steps.
+pub fn spectral_dimension(
+    return_probabilities: &[f64],
+    steps: RangeInclusive<usize>,
+) -> Option<f64> {
+    let points = steps
+        .filter(|t| *t > 0 && *t < return_probabilities.len() && return_probabilities[*t] > 0.0)
+        .map(|t| ((t as f64).ln(), return_probabilities[t].ln()))
+        .collect::<Vec<_>>();
+    slope(&points).map(|s| -2.0 * s)
+}
+
+/// The slope of the least-squares line through the given points.
+fn slope(points: &[(f64, f64)]) -> Option<f64> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let sxy = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>();
+    let sxx = points
+        .iter()
+        .map(|(x, _)| (x - mean_x).powi(2))
+        .sum::<f64>();
+    if sxx > 0.0 { Some(sxy / sxx) } else { None }
+}
+
+/// Statistics of the ratio between graph distance and Euclidean distance.
+#[derive(Clone, Copy, Debug)]
+pub struct DistanceComparison {
+    pub samples: usize,
+    pub min_ratio: f64,
+    pub mean_ratio: f64,
+    pub max_ratio: f64,
+}
+
+impl Display for DistanceComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "graph / Euclidean distance: min: {:.3}, mean: {:.3}, max: {:.3} ({} cells)",
+            self.min_ratio, self.mean_ratio, self.max_ratio, self.samples
+        )
+    }
+}
+
+/// Compares the graph distance from the origin with the given Euclidean distance from the origin, for all nodes that
+/// are at least `min_distance` away.
+pub fn compare_distances<L, F>(
+    network: &Network<L>,
+    euclidean: F,
+    min_distance: f64,
+) -> Option<DistanceComparison>
+where
+    F: Fn(&L) -> f64,
+{
+    let mut samples = 0;
+    let mut min_ratio = f64::MAX;
+    let mut max_ratio = 0.0f64;
+    let mut total = 0.0;
+    for (location, d) in network.locations.iter().zip(network.distances()) {
+        let e = euclidean(location);
+        if e < min_distance {
+            continue;
+        }
+        let ratio = d as f64 / e;
+        samples += 1;
+        total += ratio;
+        min_ratio = min_ratio.min(ratio);
+        max_ratio = max_ratio.max(ratio);
+    }
+    (samples > 0).then(|| DistanceComparison {
+        samples,
+        min_ratio,
+        mean_ratio: total / samples as f64,
+        max_ratio,
+    })
+}
+
+/// A state without content, for measurements that only depend on the connections.
+#[derive(Clone, Copy, Debug, Default)]
+struct Blank;
+
+impl<Gen: Generation> State<Gen> for Blank {
+    type Parameters = ();
+
+    fn update<Spc: Space<Self, Gen>>(
+        _space: &Spc,
+        _region: &Spc::Reg,
+        _location: &Spc::Loc,
+        _context: &UpdateContext<(), Gen>,
+    ) -> Result<Self> {
+        Ok(Blank)
+    }
+}
+
+impl Display for Blank {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(".")
+    }
+}
+
+/// Reports the emergent geometry of a torus for each of the given tilings.
+pub fn example(
+    tilings: &[Tiling],
+    cell_torus: bool,
+    size: usize,
+    height: Option<usize>,
+) -> Result<()> {
+    let width = size;
+    let height = height.unwrap_or(size);
+    let generation = 0usize;
+    for tiling in tilings {
+        let triangles = matches!(
+            tiling,
+            Tiling::AdjacentTriangles | Tiling::TouchingTriangles
+        );
+        if triangles && !cell_torus {
+            info!("{tiling:?}: PatchTorus has no triangles, using CellTorus");
+        }
+        if cell_torus || triangles {
+            let torus = new_cell_torus(*tiling, &[height, width], generation, |_: &[usize]| Blank)?;
+            report_torus(&torus, &generation)?;
+        } else {
+            let torus =
+                new_patch_torus::<_, _, CsrEffectors>(*tiling, Blank, generation, width, height)?;
+            report_torus(&torus, &generation)?;
+        }
+    }
+    Ok(())
+}
+
+/// Measures the geometry of a torus around the cell in its center and reports the results.
+pub fn report_torus<S, Gen, T>(torus: &T, generation: &Gen) -> Result<()>
+where
+    S: State<Gen>,
+    Gen: Generation,
+    T: Torus<S, Gen>,
+    <T::Spc as Space<S, Gen>>::Loc: Clone + Eq + Hash,
+{
+    let tiling = torus.tiling();
+    let dimensions = torus.dimensions();
+    let (width, height) = (dimensions[0], dimensions[1]);
+    let space = torus.space();
+    let center = (width / 2, height / 2);
+    let coordinates = |location: &<T::Spc as Space<S, Gen>>::Loc| {
+        space
+            .region(generation, location)
+            .map(|region| torus.coordinates(&region, location))
+    };
+    let origin = space
+        .reduce(generation, None, |region, location, found| {
+            found.or_else(|| {
+                (torus.coordinates(region, location) == center).then(|| location.clone())
+            })
+        })
+        .ok_or_else(|| anyhow!("No cell at the center of the torus: {center:?}"))?;
+
+    let network = explore(space, &origin)?;
+    let ball_sizes = network.ball_sizes();
+    let eccentricity = ball_sizes.len() - 1;
+    info!(
+        "{tiling:?}: ({width} x {height}): reachable cells: {}, eccentricity of the center: {eccentricity}",
+        network.len()
+    );
+    let mut r = 1;
+    let mut balls = Vec::new();
+    while r <= eccentricity {
+        balls.push(format!("N({r}) = {}", ball_sizes[r]));
+        r *= 2;
+    }
+    info!("{tiling:?}: ball sizes: {}", balls.join(", "));
+
+    // Balls that reach around the short side of the torus no longer grow like balls in the plane.
+    let max_radius = (eccentricity / 2).min(width.min(height) / 2);
+    let radii = (max_radius / 4).max(1)..=max_radius;
+    match hausdorff_dimension(&ball_sizes, radii.clone()) {
+        Some(d) => info!("{tiling:?}: Hausdorff dimension for radii {radii:?}: {d:.3}"),
+        None => info!("{tiling:?}: Hausdorff dimension: torus too small"),
+    }
+
+    let max_steps = (max_radius * max_radius).min(4096);
+    let steps = (max_steps / 8).max(1)..=max_steps;
+    let return_probabilities = network.return_probabilities(max_steps);
+    match spectral_dimension(&return_probabilities, steps.clone()) {
+        Some(d) => info!("{tiling:?}: spectral dimension for steps {steps:?}: {d:.3}"),
+        None => info!("{tiling:?}: spectral dimension: torus too small"),
+    }
+
+    let geometry = Geometry::new(tiling, width, height);
+    let origin_center = geometry.center(center.0, center.1);
+    let euclidean = |location: &<T::Spc as Space<S, Gen>>::Loc| {
+        coordinates(location)
+            .map(|(x, y)| {
+                let (dx, dy) = geometry.delta(origin_center, geometry.center(x, y));
+                dx.hypot(dy)
+            })
+            .unwrap_or_default()
+    };
+    match compare_distances(&network, euclidean, (max_radius / 4).max(2) as f64) {
+        Some(comparison) => info!("{tiling:?}: {comparison}"),
+        None => info!("{tiling:?}: graph / Euclidean distance: torus too small"),
+    }
+    Ok(())
+}
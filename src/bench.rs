@@ -0,0 +1,95 @@
+//! Compares the throughput of the different representations of a hexagonal torus.
+//!
+//! Throughput is measured in cell updates per second: the number of cells times the number of generations, divided by
+//! the time it took to compute them. Each variant runs the same wave simulation, so the numbers are comparable.
+
+use crate::{
+    cell::new_cell_torus,
+    patch::{
+        AtMostSixEffectors, Large, Medium, PatchSize, PatchSizeChoice, Small, new_patch_torus,
+        plan_patch_size,
+    },
+    structure::{Generation, Run, Space},
+    torus::{Tiling, Torus},
+    wave::{MAX_EFFECTORS, Wave},
+};
+use anyhow::Result;
+use log::info;
+use std::time::{Duration, Instant};
+
+pub fn example(
+    size: usize,
+    height: Option<usize>,
+    generations: usize,
+    cell_torus: bool,
+) -> Result<()> {
+    let width = size;
+    let height = height.unwrap_or(size);
+    info!(
+        "Benchmark: hexagonal torus of ({width} x {height}) cells, {generations} generations, planned patch size: [{:?}]",
+        plan_patch_size::<Wave>(width, height, MAX_EFFECTORS)
+    );
+    if cell_torus {
+        let torus = new_cell_torus(
+            Tiling::Hexagons,
+            &[height, width],
+            0usize,
+            |_: &[usize]| Wave::new(0.0, false),
+        )?;
+        report(
+            "cell torus",
+            width * height,
+            generations,
+            measure(torus, generations)?,
+        );
+    }
+    bench_patch_size::<Small>(PatchSizeChoice::Small, width, height, generations)?;
+    bench_patch_size::<Medium>(PatchSizeChoice::Medium, width, height, generations)?;
+    bench_patch_size::<Large>(PatchSizeChoice::Large, width, height, generations)?;
+    Ok(())
+}
+
+fn bench_patch_size<P: PatchSize>(
+    choice: PatchSizeChoice,
+    width: usize,
+    height: usize,
+    generations: usize,
+) -> Result<()> {
+    let torus = new_patch_torus::<_, _, AtMostSixEffectors<P>>(
+        Tiling::Hexagons,
+        Wave::new(0.0, false),
+        0usize,
+        width,
+        height,
+    )?;
+    let label = format!("patch torus ({choice:?}, {} cells per patch)", P::CAPACITY);
+    report(
+        &label,
+        width * height,
+        generations,
+        measure(torus, generations)?,
+    );
+    Ok(())
+}
+
+/// Runs the wave simulation on the given torus and returns the time it took, excluding the construction of the torus.
+fn measure<T: Torus<Wave, usize>>(torus: T, generations: usize) -> Result<Duration> {
+    let mut torus = torus;
+    let mut generation = 0usize;
+    let width = torus.dimensions()[0];
+    let height = torus.dimensions()[1];
+    torus.adjust(&generation, width / 2, height / 2, Wave::new(0.0, true))?;
+    let start = Instant::now();
+    for _ in 0..generations {
+        torus.space_mut().update_all(&generation, &Run::default())?;
+        torus.space_mut().free(&generation)?;
+        generation = generation.successor();
+    }
+    Ok(start.elapsed())
+}
+
+fn report(label: &str, cells: usize, generations: usize, elapsed: Duration) {
+    let seconds = elapsed.as_secs_f64();
+    let throughput = (cells * generations) as f64 / seconds;
+    info!("{label}: {seconds:.3}s: {throughput:.0} cell updates per second");
+}
@@ -0,0 +1,494 @@
+//! # Block-partitioned updates
+//!
+//! A block rule does not compute each cell from its effectors, but replaces the states of a whole block of cells at
+//! once (see `BlockRule`). The blocks of a generation form a partition of the space, and the partition shifts from one
+//! generation to the next, so that information can cross the boundaries of the blocks. This is the Margolus
+//! neighbourhood; rules that conserve particles and rules that are reversible are easy to write this way, *e.g.*, the
+//! billiard-ball model and critters.
+//!
+//! The shape of the blocks depends on the tiling:
+//!
+//! * Squares (orthogonal tilings): blocks of two by two cells, in two phases that are one cell apart diagonally. Both
+//!   dimensions must be even. The cells of a block are ordered top left, top right, bottom left, bottom right.
+//! * Triangles: hexagons of the six triangles around a corner, in three phases. The corners are coloured with three
+//!   colours, such that each triangle has a corner of each colour, and phase *p* uses the corners of colour *p*. The
+//!   width must be a multiple of six and the height must be even. The cells of a block go around the corner.
+//! * Hexagons: triangles of three hexagons that touch each other, in three phases, with the axial co-ordinates that
+//!   `ising::sublattice` uses as well. The width must be a multiple of six and the height must be even.
+//!
+//! The `billiard` command runs a gas of particles with a `BlockTable`.
+
+use std::{
+    collections::HashMap,
+    fmt::{Display, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use log::info;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    cell::new_cell_torus,
+    patch::{CsrEffectors, new_patch_torus},
+    simulation::{Cadence, Simulation},
+    structure::{BlockRule, Generation, GrayScale, Location, Region, Space, State, UpdateContext},
+    torus::{GrayScaleTorus, Tiling, Torus, render::View},
+};
+
+/// The blocks of each phase of a block-partitioned update. Each block lists its locations in a fixed order.
+#[derive(Clone, Debug)]
+pub struct Partition<L> {
+    phases: Vec<Vec<Vec<L>>>,
+}
+
+impl<L> Partition<L> {
+    pub fn new(phases: Vec<Vec<Vec<L>>>) -> Result<Partition<L>> {
+        if phases.is_empty() {
+            return Err(anyhow!("A partition needs at least one phase"));
+        }
+        Ok(Partition { phases })
+    }
+
+    pub fn phase_count(&self) -> usize {
+        self.phases.len()
+    }
+
+    /// The blocks of the phase of the given step.
+    pub fn blocks(&self, step: usize) -> &[Vec<L>] {
+        &self.phases[step % self.phases.len()]
+    }
+}
+
+/// The number of cells of a block on the given tiling.
+pub fn block_size(tiling: Tiling) -> usize {
+    match tiling {
+        Tiling::Orthogonal | Tiling::OrthogonalAndDiagonal => 4,
+        Tiling::AdjacentTriangles | Tiling::TouchingTriangles => 6,
+        Tiling::Hexagons => 3,
+    }
+}
+
+/// Divides a two-dimensional torus into the blocks of its tiling (see the module documentation).
+pub fn partition<T, S, Gen>(
+    torus: &T,
+    generation: &Gen,
+) -> Result<Partition<<T::Spc as Space<S, Gen>>::Loc>>
+where
+    T: Torus<S, Gen>,
+    S: State<Gen>,
+    Gen: Generation,
+{
+    let dimensions = torus.dimensions();
+    let &[width, height] = dimensions.as_slice() else {
+        return Err(anyhow!("Blocks are only possible in 2-D: [{dimensions:?}]"));
+    };
+    let locations = torus.space().reduce(
+        generation,
+        HashMap::new(),
+        |region, location, mut locations| {
+            locations.insert(torus.coordinates(region, location), location.clone());
+            locations
+        },
+    );
+    let tiling = torus.tiling();
+    let phases = match tiling {
+        Tiling::Orthogonal | Tiling::OrthogonalAndDiagonal => 2,
+        _ => 3,
+    };
+    let mut result = Vec::with_capacity(phases);
+    for phase in 0..phases {
+        let blocks = block_co_ordinates(tiling, width, height, phase);
+        let mut covered = vec![false; width * height];
+        for &(x, y) in blocks.iter().flatten() {
+            if std::mem::replace(&mut covered[y * width + x], true) {
+                return Err(anyhow!(
+                    "Blocks overlap at ({x}, {y}): phase {phase}: the torus ({width} x {height}) does not fit the blocks of [{tiling:?}]"
+                ));
+            }
+        }
+        if let Some(i) = covered.iter().position(|c| !c) {
+            return Err(anyhow!(
+                "No block covers ({}, {}): phase {phase}: the torus ({width} x {height}) does not fit the blocks of [{tiling:?}]",
+                i % width,
+                i / width
+            ));
+        }
+        let blocks = blocks
+            .into_iter()
+            .map(|block| {
+                block
+                    .into_iter()
+                    .map(|position| {
+                        locations
+                            .get(&position)
+                            .cloned()
+                            .ok_or_else(|| anyhow!("No cell at: {position:?}"))
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        result.push(blocks);
+    }
+    Partition::new(result)
+}
+
+/// The co-ordinates of the cells of the blocks of one phase.
+fn block_co_ordinates(
+    tiling: Tiling,
+    width: usize,
+    height: usize,
+    phase: usize,
+) -> Vec<Vec<(usize, usize)>> {
+    let at = |x: usize, dx: isize, y: usize, dy: isize| {
+        (
+            (x as isize + dx).rem_euclid(width as isize) as usize,
+            (y as isize + dy).rem_euclid(height as isize) as usize,
+        )
+    };
+    let mut blocks = Vec::new();
+    match tiling {
+        Tiling::Orthogonal | Tiling::OrthogonalAndDiagonal => {
+            for y in (phase..height + phase).step_by(2) {
+                for x in (phase..width + phase).step_by(2) {
+                    blocks.push(vec![
+                        at(x, 0, y, 0),
+                        at(x, 1, y, 0),
+                        at(x, 0, y, 1),
+                        at(x, 1, y, 1),
+                    ]);
+                }
+            }
+        }
+        // Line y is the top of row y. Corner u of line y is the top of triangle u of row y if that triangle points up,
+        // so corners exist where u + y is even. In the co-ordinates ((u + y) / 2, y) neighbouring corners differ by
+        // (±1, 0), ±(0, 1) or ±(1, 1), so the sum of the co-ordinates modulo three differs.
+        Tiling::AdjacentTriangles | Tiling::TouchingTriangles => {
+            for y in 0..height {
+                for u in (y % 2..width).step_by(2) {
+                    if ((u + y) / 2 + y) % 3 != phase {
+                        continue;
+                    }
+                    blocks.push(vec![
+                        at(u, -1, y, -1),
+                        at(u, 0, y, -1),
+                        at(u, 1, y, -1),
+                        at(u, 1, y, 0),
+                        at(u, 0, y, 0),
+                        at(u, -1, y, 0),
+                    ]);
+                }
+            }
+        }
+        // A block consists of a hexagon, its neighbour on the right and their common neighbour in the next row.
+        Tiling::Hexagons => {
+            for y in 0..height {
+                for x in 0..width {
+                    if (x + 2 * y - y.div_ceil(2)) % 3 != phase {
+                        continue;
+                    }
+                    let below = if y % 2 == 0 { 1 } else { 0 };
+                    blocks.push(vec![at(x, 0, y, 0), at(x, 1, y, 0), at(x, below, y, 1)]);
+                }
+            }
+        }
+    }
+    blocks
+}
+
+/// The successor of each pattern of a block of particles. Bit *i* of a pattern tells whether cell *i* of the block holds
+/// a particle.
+#[derive(Clone, Debug)]
+pub struct BlockTable {
+    size: usize,
+    table: Vec<u8>,
+}
+
+impl BlockTable {
+    /// A table for blocks of the given number of cells, at most eight.
+    pub fn from_fn(size: usize, successor: impl Fn(u8) -> u8) -> BlockTable {
+        let size = size.min(8);
+        let table = (0..1usize << size)
+            .map(|pattern| successor(pattern as u8))
+            .collect();
+        BlockTable { size, table }
+    }
+
+    /// The billiard-ball model of Fredkin and Toffoli on two by two blocks: a particle alone crosses its block
+    /// diagonally, two particles that approach each other along a diagonal leave along the other diagonal, and any
+    /// other pattern stays where it is.
+    pub fn billiard_ball() -> BlockTable {
+        BlockTable::from_fn(4, |pattern| match pattern {
+            0b0001 => 0b1000,
+            0b1000 => 0b0001,
+            0b0010 => 0b0100,
+            0b0100 => 0b0010,
+            0b1001 => 0b0110,
+            0b0110 => 0b1001,
+            other => other,
+        })
+    }
+
+    /// Critters by Margolus on two by two blocks: a block with two particles stays, any other block is inverted, and an
+    /// inverted block that had three particles is turned half a turn.
+    pub fn critters() -> BlockTable {
+        BlockTable::from_fn(4, |pattern| match pattern.count_ones() {
+            2 => pattern,
+            3 => half_turn(!pattern & 0b1111),
+            _ => !pattern & 0b1111,
+        })
+    }
+
+    /// Turns each pattern one cell further around the block. On hexagonal and triangular blocks a particle circles
+    /// around the corner of its block and moves on to the next block in the next phase.
+    pub fn rotation(size: usize) -> BlockTable {
+        let size = size.clamp(1, 8);
+        let mask = ((1u16 << size) - 1) as u8;
+        BlockTable::from_fn(size, |pattern| {
+            ((pattern << 1) | (pattern >> (size - 1))) & mask
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Tells whether every pattern keeps its number of particles.
+    pub fn conserves_particles(&self) -> bool {
+        self.table
+            .iter()
+            .enumerate()
+            .all(|(pattern, next)| (pattern as u8).count_ones() == next.count_ones())
+    }
+
+    /// Tells whether no two patterns have the same successor, so that the rule can be run backwards.
+    pub fn is_reversible(&self) -> bool {
+        let mut seen = vec![false; self.table.len()];
+        self.table
+            .iter()
+            .all(|next| !std::mem::replace(&mut seen[*next as usize], true))
+    }
+}
+
+impl Default for BlockTable {
+    fn default() -> BlockTable {
+        BlockTable::billiard_ball()
+    }
+}
+
+/// Swaps top left with bottom right and top right with bottom left.
+fn half_turn(pattern: u8) -> u8 {
+    (0..4).fold(0, |turned, i| turned | (((pattern >> i) & 1) << (3 - i)))
+}
+
+/// A cell that holds a particle or not. Particles only move with the blocks of a `BlockTable`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Particle {
+    pub present: bool,
+}
+
+impl Particle {
+    pub fn new(present: bool) -> Particle {
+        Particle { present }
+    }
+}
+
+impl State<usize> for Particle {
+    type Parameters = BlockTable;
+
+    fn update<Spc: Space<Self, usize>>(
+        space: &Spc,
+        _region: &Spc::Reg,
+        location: &Spc::Loc,
+        _context: &UpdateContext<BlockTable, usize>,
+    ) -> Result<Self> {
+        Err(anyhow!(
+            "Particles only move with their block: use Space::update_blocks: [{}]",
+            location.id(space)
+        ))
+    }
+}
+
+impl BlockRule<usize> for Particle {
+    fn update_block(states: &mut [Self], context: &UpdateContext<BlockTable, usize>) -> Result<()> {
+        let table = context.parameters();
+        if states.len() != table.size {
+            return Err(anyhow!(
+                "Block of {} cells for a table of blocks of {} cells",
+                states.len(),
+                table.size
+            ));
+        }
+        let pattern = states
+            .iter()
+            .enumerate()
+            .fold(0u8, |pattern, (i, s)| pattern | (u8::from(s.present) << i));
+        let next = table.table[pattern as usize];
+        for (i, state) in states.iter_mut().enumerate() {
+            state.present = (next >> i) & 1 == 1;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Particle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_char(if self.present { '#' } else { '.' })
+    }
+}
+
+impl GrayScale for Particle {
+    type Context = ();
+
+    fn gray_value(&self, _context: &()) -> u8 {
+        if self.present { 255 } else { 0 }
+    }
+}
+
+/// The block rules that can be chosen on the command line.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+#[non_exhaustive]
+pub enum Preset {
+    /// The billiard-ball model (two by two blocks).
+    #[default]
+    BilliardBall,
+    /// Critters (two by two blocks).
+    Critters,
+    /// Turn each block one cell further (any blocks).
+    Rotation,
+}
+
+impl Preset {
+    pub fn table(self, tiling: Tiling) -> BlockTable {
+        match self {
+            Preset::BilliardBall => BlockTable::billiard_ball(),
+            Preset::Critters => BlockTable::critters(),
+            Preset::Rotation => BlockTable::rotation(block_size(tiling)),
+        }
+    }
+}
+
+/// The settings of the `billiard` command.
+pub(crate) struct Demo<'a> {
+    pub tiling: Tiling,
+    pub preset: Preset,
+    /// Use a `PatchTorus` instead of a `CellTorus`.
+    pub patched: bool,
+    pub width: usize,
+    pub height: usize,
+    /// The probability that a cell in the middle of the torus holds a particle. The rest of the torus starts empty.
+    pub density: f64,
+    pub seed: u64,
+    pub generations: usize,
+    pub export_dir: Option<&'a PathBuf>,
+    /// Number of generations between exported images.
+    pub export_every: usize,
+}
+
+/// Lets a gas in the middle of the torus expand into the empty space around it, and checks that the block rule keeps
+/// the number of particles if it should.
+pub(crate) fn example(demo: &Demo) -> Result<()> {
+    let table = demo.preset.table(demo.tiling);
+    if table.size() != block_size(demo.tiling) {
+        return Err(anyhow!(
+            "Rule [{:?}] is for blocks of {} cells; [{:?}] has blocks of {} cells",
+            demo.preset,
+            table.size(),
+            demo.tiling,
+            block_size(demo.tiling)
+        ));
+    }
+    if !(0.0..=1.0).contains(&demo.density) {
+        return Err(anyhow!(
+            "Density should be between 0 and 1: [{}]",
+            demo.density
+        ));
+    }
+    info!(
+        "Rule: [{:?}]: tiling: [{:?}]: conserves particles: {}: reversible: {}",
+        demo.preset,
+        demo.tiling,
+        table.conserves_particles(),
+        table.is_reversible()
+    );
+    let (width, height) = (demo.width, demo.height);
+    let mut rng = StdRng::seed_from_u64(demo.seed);
+    let mut present = vec![false; width * height];
+    for y in height / 4..height - height / 4 {
+        for x in width / 4..width - width / 4 {
+            present[y * width + x] = rng.random_bool(demo.density);
+        }
+    }
+    let generation = 0usize;
+    if demo.patched {
+        let mut torus = new_patch_torus::<_, _, CsrEffectors>(
+            demo.tiling,
+            Particle::default(),
+            generation,
+            width,
+            height,
+        )?;
+        for (i, _) in present.iter().enumerate().filter(|(_, p)| **p) {
+            torus.adjust(&generation, i % width, i / width, Particle::new(true))?;
+        }
+        run(torus, table, demo)
+    } else {
+        let torus = new_cell_torus(
+            demo.tiling,
+            &[height, width],
+            generation,
+            |v: &[usize]| Particle::new(present[v[0] * width + v[1]]),
+        )?;
+        run(torus, table, demo)
+    }
+}
+
+fn run<T: Torus<Particle, usize>>(torus: T, table: BlockTable, demo: &Demo) -> Result<()> {
+    let generation = 0usize;
+    let partition = partition(&torus, &generation)?;
+    let initial = count(&torus, &generation);
+    info!(
+        "Particles: {initial}: phases: {}: blocks per phase: {}",
+        partition.phase_count(),
+        partition.blocks(0).len()
+    );
+    let conserves = table.conserves_particles();
+    let export = |torus: &T, generation: &usize, _step: usize| {
+        torus.export(generation, &(), &View::Plane, demo.export_dir)
+    };
+    let check = |torus: &T, generation: &usize, _step: usize| {
+        let particles = count(torus, generation);
+        if conserves && particles != initial {
+            return Err(anyhow!(
+                "Generation: [{generation}]: {particles} particles instead of {initial}"
+            ));
+        }
+        Ok(())
+    };
+    let mut simulation = Simulation::new(torus, generation)
+        .with_parameters(table)
+        .with_partition(partition)
+        .observe(Cadence::every(demo.export_every), export)
+        .observe(Cadence::every(1).skip_start(), check);
+    simulation.run(demo.generations)?;
+    let torus = simulation.finish()?;
+    let generation = demo.generations;
+    info!(
+        "Generation: [{generation}]: particles: {}",
+        count(&torus, &generation)
+    );
+    Ok(())
+}
+
+/// The number of particles in the given generation.
+fn count<T: Torus<Particle, usize>>(torus: &T, generation: &usize) -> usize {
+    torus
+        .space()
+        .reduce(generation, 0, |region, location, count| {
+            match region.state(location) as Option<Particle> {
+                Some(state) if state.present => count + 1,
+                _ => count,
+            }
+        })
+}
@@ -1,6 +1,8 @@
+mod network;
 mod torus;
 
-pub use torus::new_cell_torus;
+pub use network::{CellNetwork, NetworkModel, new_cell_network};
+pub use torus::{CellIndex, CellTorus, Layer, LayerRef, new_cell_torus};
 
 use anyhow::{Result, anyhow};
 // use log::debug;
@@ -14,9 +16,25 @@ use std::{
     rc::Rc,
     sync::RwLock,
 };
-use uuid::Uuid;
 
-use crate::structure::{Generation, Location, Region, Space, State};
+use crate::structure::{
+    Generation, Location, Neighbourhood, Region, Run, Space, State, Weight, find_neighbourhood,
+};
+
+/// Counts the changes to the links between the cells of one space. The cells of a space share one counter. A cached
+/// neighbourhood that was found before the last change is found anew.
+#[derive(Clone, Debug, Default)]
+pub struct LinkChanges(Rc<std::cell::Cell<usize>>);
+
+impl LinkChanges {
+    fn count(&self) -> usize {
+        self.0.get()
+    }
+
+    fn record(&self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
 
 pub struct CellRegion<Spc, S, Gen>
 where
@@ -73,7 +91,7 @@ impl<S: State<Gen>, Gen: Generation> Space<S, Gen> for CellSpace {
         Some(Cow::Owned(CellRegion::new(generation.clone())))
     }
 
-    fn update_all(&mut self, _generation: &Gen) -> Result<()> {
+    fn update_all(&mut self, _generation: &Gen, _run: &Run<S::Parameters>) -> Result<()> {
         Ok(())
     }
 
@@ -149,7 +167,21 @@ where
     Gen: Generation,
 {
     fn effectors(&self, _space: &Spc) -> Result<impl IntoIterator<Item = Self>> {
-        self.0.effectors.read().map(|s| s.clone()).map_err(|e| {
+        self.0
+            .effectors
+            .read()
+            .map(|m| m.iter().map(|(c, _)| c.clone()).collect::<Vec<_>>())
+            .map_err(|e| {
+                anyhow!(
+                    "Could not get read lock for effectors of: {:?}: {:?}",
+                    self.0.id,
+                    e
+                )
+            })
+    }
+
+    fn weighted_effectors(&self, _space: &Spc) -> Result<impl IntoIterator<Item = (Self, Weight)>> {
+        self.0.effectors.read().map(|m| m.clone()).map_err(|e| {
             anyhow!(
                 "Could not get read lock for effectors of: {:?}: {:?}",
                 self.0.id,
@@ -161,17 +193,34 @@ where
     fn id(&self, _space: &Spc) -> String {
         self.id()
     }
-}
 
-impl<S: State<Gen>, Gen: Generation> Cell<S, Gen> {
-    pub fn new(generation: Gen, state: S) -> Self {
-        Cell(Rc::new(InnerCell::new(generation, state)))
+    fn neighbourhood(&self, space: &Spc, radius: usize) -> Result<Neighbourhood<Self>> {
+        let changes = self.0.links.count();
+        if let Some((found, neighbourhood)) = self
+            .0
+            .neighbourhoods
+            .read()
+            .ok()
+            .and_then(|m| m.get(&radius).cloned())
+            && found == changes
+        {
+            return Ok(neighbourhood);
+        }
+        let neighbourhood = find_neighbourhood(self, space, radius)?;
+        self.0
+            .neighbourhoods
+            .write()
+            .map_err(|e| anyhow!("Could not get write lock: {e}"))?
+            .insert(radius, (changes, neighbourhood.clone()));
+        Ok(neighbourhood)
     }
+}
 
-    pub fn new_with_index(generation: Gen, state: S, index: usize) -> Self {
-        let mut inner = InnerCell::new(generation, state);
-        inner.index = index;
-        Cell(Rc::new(inner))
+impl<S: State<Gen>, Gen: Generation> Cell<S, Gen> {
+    /// Creates a cell with the given index as its id. The index is unique within the space of the cell, so that cells
+    /// hash the same in every run. The cells of one space share their `LinkChanges`.
+    pub fn new(generation: Gen, state: S, index: usize, links: &LinkChanges) -> Self {
+        Cell(Rc::new(InnerCell::new(generation, state, index, links)))
     }
 
     pub fn has_state(&self, generation: &Gen) -> bool {
@@ -179,19 +228,34 @@ impl<S: State<Gen>, Gen: Generation> Cell<S, Gen> {
         guard.map(|m| m.get(generation).is_some()).unwrap_or(false)
     }
 
-    pub fn join(&self, other: &Self) -> Result<()> {
-        connect_cells(self, other)?;
-        connect_cells(other, self)?;
-        trace!("Joined: [{:?}] <=> [{:?}]", self.0.id, other.0.id);
+    /// Makes both cells effectors of each other, with the given weight on both links.
+    /// Joining cells that are already joined replaces the weight. Effectors keep the order in which they were joined.
+    pub fn join_weighted(&self, other: &Self, weight: Weight) -> Result<()> {
+        connect_cells(self, other, weight)?;
+        connect_cells(other, self, weight)?;
+        trace!("Joined: [{:?}] <=> [{:?}]: {weight}", self.0.id, other.0.id);
+        Ok(())
+    }
+
+    /// Replaces the state of the cell in the given generation, if the cell has a state in that generation.
+    pub fn adjust(&self, generation: &Gen, state: S) -> Result<()> {
+        let mut write_lock = self
+            .0
+            .state_map
+            .write()
+            .map_err(|e| anyhow!("Could not get write lock: {e}"))?;
+        if let Some(s) = write_lock.get_mut(generation) {
+            *s = state;
+        }
         Ok(())
     }
 
     pub fn state<Spc: Space<S, Gen>>(&self, _region: &Spc::Reg, generation: &Gen) -> Option<S> {
         let guard = self.0.state_map.read().ok();
-        guard.map(|m| m.get(generation).map(Clone::clone)).flatten()
+        guard.and_then(|m| m.get(generation).cloned())
     }
 
-    pub fn update<Spc>(&self, space: &Spc, generation: &Gen) -> Result<()>
+    pub fn update<Spc>(&self, space: &Spc, generation: &Gen, run: &Run<S::Parameters>) -> Result<()>
     where
         Spc: Space<S, Gen, Reg = CellRegion<Spc, S, Gen>, Loc = Cell<S, Gen>>,
     {
@@ -200,7 +264,7 @@ impl<S: State<Gen>, Gen: Generation> Cell<S, Gen> {
             return Ok(());
         }
         let region = CellRegion::new(generation.clone());
-        let new_state = S::update(space, &region, self)?;
+        let new_state = S::update(space, &region, self, &run.context(generation).at(self))?;
         let mut guard = self
             .0
             .state_map
@@ -211,7 +275,36 @@ impl<S: State<Gen>, Gen: Generation> Cell<S, Gen> {
     }
 }
 
-fn connect_cells<S, Gen>(this: &Cell<S, Gen>, that: &Cell<S, Gen>) -> Result<()>
+/// Computes the next generation of all cells that do not have a state in the next generation yet.
+fn update_cells<S: State<Gen>, Gen: Generation>(
+    cells: &[Cell<S, Gen>],
+    generation: &Gen,
+    run: &Run<S::Parameters>,
+) -> Result<()> {
+    for cell in cells {
+        trace!("Update: [{:?}]", cell.id());
+        let space = CellSpace;
+        cell.update(&space, generation, run)?;
+    }
+    Ok(())
+}
+
+/// Forgets the state of all cells in the given generation.
+fn free_cells<S: State<Gen>, Gen: Generation>(
+    cells: &[Cell<S, Gen>],
+    generation: &Gen,
+) -> Result<()> {
+    for cell in cells {
+        cell.0
+            .state_map
+            .write()
+            .map_err(|e| anyhow!("Can't get write lock on cells: {e:?}"))?
+            .remove(generation);
+    }
+    Ok(())
+}
+
+fn connect_cells<S, Gen>(this: &Cell<S, Gen>, that: &Cell<S, Gen>, weight: Weight) -> Result<()>
 where
     S: State<Gen>,
     Gen: Generation,
@@ -221,34 +314,42 @@ where
         .effectors
         .write()
         .map_err(|e| anyhow!("Could not get write lock: {e}"))?;
-    effectors_lock.insert(that.clone());
+    match effectors_lock.iter_mut().find(|(c, _)| c == that) {
+        Some((_, w)) => *w = weight,
+        None => effectors_lock.push((that.clone(), weight)),
+    }
+    this.0.links.record();
     trace!("Connected {} => {}", this.id(), that.id());
     Ok(())
 }
 
+/// Cached neighbourhoods by radius, with the number of link changes when they were found.
+type CachedNeighbourhoods<S, Gen> = HashMap<usize, (usize, Neighbourhood<Cell<S, Gen>>)>;
+
 struct InnerCell<S: State<Gen>, Gen: Generation> {
-    id: Uuid,
-    index: usize,
+    id: usize,
+    links: LinkChanges,
     state_map: RwLock<HashMap<Gen, S>>,
-    effectors: RwLock<HashSet<Cell<S, Gen>>>,
+    effectors: RwLock<Vec<(Cell<S, Gen>, Weight)>>,
+    neighbourhoods: RwLock<CachedNeighbourhoods<S, Gen>>,
 }
 
 impl<S: State<Gen>, Gen: Generation> InnerCell<S, Gen> {
-    pub fn new(generation: Gen, state: S) -> Self {
-        let id = Uuid::new_v4();
+    pub fn new(generation: Gen, state: S, id: usize, links: &LinkChanges) -> Self {
         let mut state_map = HashMap::new();
         state_map.insert(generation, state);
         let state_map = RwLock::new(state_map);
-        let effectors = RwLock::new(HashSet::new());
+        let effectors = RwLock::new(Vec::new());
         InnerCell {
             id,
-            index: 0,
+            links: links.clone(),
             state_map,
             effectors,
+            neighbourhoods: RwLock::new(HashMap::new()),
         }
     }
 
-    fn id(&self) -> Uuid {
+    fn id(&self) -> usize {
         self.id
     }
 }
@@ -259,12 +360,8 @@ impl<S: State<Gen>, Gen: Generation> Debug for InnerCell<S, Gen> {
             .effectors
             .read()
             .ok()
-            .map(|n| {
-                n.iter()
-                    .map(|c| c.0.id().clone())
-                    .collect::<HashSet<Uuid>>()
-            })
-            .unwrap_or_else(|| HashSet::<Uuid>::new());
+            .map(|n| n.iter().map(|(c, w)| (c.0.id(), *w)).collect::<Vec<_>>())
+            .unwrap_or_default();
         f.debug_struct("InnerCell")
             .field("id", &self.id)
             .field("state_map", &self.state_map.read().ok())
@@ -272,3 +369,29 @@ impl<S: State<Gen>, Gen: Generation> Debug for InnerCell<S, Gen> {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conway::Conway;
+
+    #[test]
+    fn effectors_keep_join_order() -> Result<()> {
+        let links = LinkChanges::default();
+        let cells = (0..5)
+            .map(|i| Cell::new(0usize, Conway::new(false), i, &links))
+            .collect::<Vec<_>>();
+        for (other, weight) in [(3, 1.0), (1, 0.5), (4, 2.0), (2, 1.5)] {
+            cells[0].join_weighted(&cells[other], weight)?;
+        }
+        // Joining again replaces the weight, but keeps the place.
+        cells[0].join_weighted(&cells[1], 0.25)?;
+        let effectors =
+            Location::<CellSpace, Conway, usize>::weighted_effectors(&cells[0], &CellSpace)?
+                .into_iter()
+                .map(|(cell, weight)| (cell.0.id, weight))
+                .collect::<Vec<_>>();
+        assert_eq!(effectors, [(3, 1.0), (1, 0.25), (4, 2.0), (2, 1.5)]);
+        Ok(())
+    }
+}
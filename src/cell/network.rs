@@ -0,0 +1,485 @@
+//! # Networks of cells
+//!
+//! A `CellNetwork` joins cells according to an arbitrary graph instead of a regular tiling.
+//! The graph is either generated by a random model or loaded from a file.
+//! Random models take a seed, so that a network can be reproduced.
+//!
+//! Links are symmetric: if cell *a* is an effector of cell *b*, then *b* is an effector of *a*, with the same weight.
+//!
+//! Each cell keeps its states by generation. Freeing a generation is put off until the rule no longer looks back at it
+//! (see `State::LOOKBACK`).
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::PathBuf,
+};
+
+use anyhow::{Result, anyhow};
+use log::{debug, info};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    cell::{Cell, CellRegion, Generation, LinkChanges, State, free_cells, update_cells},
+    structure::{Location, Run, Space, Weight},
+};
+
+/// The ways to create the graph of a `CellNetwork`.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum NetworkModel {
+    /// Every pair of nodes is joined with the given probability.
+    ErdosRenyi { nodes: usize, probability: f64 },
+    /// A ring in which each node is joined to `neighbours / 2` nodes on either side.
+    /// Each of these links is then rewired to a random node with the given probability.
+    WattsStrogatz {
+        nodes: usize,
+        neighbours: usize,
+        rewiring: f64,
+    },
+    /// Nodes are placed at random in the unit square and joined if they are at most `radius` apart.
+    RandomGeometric { nodes: usize, radius: f64 },
+    /// A text file with one link per line: two node labels and an optional weight, separated by white space.
+    /// Empty lines and lines that start with `#` are ignored.
+    EdgeList(PathBuf),
+    /// A GraphML file. An edge attribute named `weight` is used as the weight of the link. An edge without it gets
+    /// the default of the attribute, or else weight one.
+    /// Node attributes named `x` and `y` are used as positions.
+    GraphMl(PathBuf),
+}
+
+pub struct CellNetwork<S: State<Gen>, Gen: Generation> {
+    cells: Vec<Cell<S, Gen>>,
+    labels: Vec<String>,
+    positions: Option<Vec<(f64, f64)>>,
+    /// The generations that were freed but that the rule may still look back at, oldest first.
+    freed: VecDeque<Gen>,
+}
+
+/// The nodes and links of a network before it is turned into cells.
+#[derive(Default)]
+struct Graph {
+    labels: Vec<String>,
+    positions: Option<Vec<(f64, f64)>>,
+    links: Vec<(usize, usize, Weight)>,
+}
+
+impl Graph {
+    fn unlabeled(nodes: usize) -> Self {
+        Graph {
+            labels: (0..nodes).map(|i| i.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn node(&mut self, label: &str, index_by_label: &mut HashMap<String, usize>) -> usize {
+        *index_by_label.entry(label.to_string()).or_insert_with(|| {
+            self.labels.push(label.to_string());
+            self.labels.len() - 1
+        })
+    }
+}
+
+pub fn new_cell_network<S: State<Gen>, Gen: Generation, F>(
+    model: &NetworkModel,
+    seed: u64,
+    initial_gen: Gen,
+    initial_state: F,
+) -> Result<CellNetwork<S, Gen>>
+where
+    F: Fn(usize) -> S,
+{
+    let mut rng = StdRng::seed_from_u64(seed);
+    let graph = match model {
+        NetworkModel::ErdosRenyi { nodes, probability } => {
+            erdos_renyi(*nodes, *probability, &mut rng)?
+        }
+        NetworkModel::WattsStrogatz {
+            nodes,
+            neighbours,
+            rewiring,
+        } => watts_strogatz(*nodes, *neighbours, *rewiring, &mut rng)?,
+        NetworkModel::RandomGeometric { nodes, radius } => {
+            random_geometric(*nodes, *radius, &mut rng)
+        }
+        NetworkModel::EdgeList(path) => load_edge_list(path)?,
+        NetworkModel::GraphMl(path) => load_graphml(path)?,
+    };
+
+    let links = LinkChanges::default();
+    let cells = (0..graph.labels.len())
+        .map(|i| Cell::new(initial_gen.clone(), initial_state(i), i, &links))
+        .collect::<Vec<_>>();
+    for (a, b, weight) in graph.links.iter() {
+        if a != b {
+            cells[*a].join_weighted(&cells[*b], *weight)?;
+        }
+    }
+    debug!(
+        "Network: Number of cells: [{}]: Number of links: [{}]",
+        cells.len(),
+        graph.links.len()
+    );
+
+    Ok(CellNetwork {
+        cells,
+        labels: graph.labels,
+        positions: graph.positions,
+        freed: VecDeque::new(),
+    })
+}
+
+impl<S: State<Gen>, Gen: Generation> CellNetwork<S, Gen> {
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn label(&self, index: usize) -> Option<&str> {
+        self.labels.get(index).map(|l| l.as_str())
+    }
+
+    /// The position of a node, if the model places nodes in space (see `RandomGeometric` and `GraphMl`).
+    pub fn position(&self, index: usize) -> Option<(f64, f64)> {
+        self.positions.as_ref().and_then(|p| p.get(index).copied())
+    }
+
+    pub fn adjust(&mut self, generation: &Gen, index: usize, state: S) -> Result<()> {
+        self.cells
+            .get(index)
+            .ok_or_else(|| anyhow!("No such node: [{index}]"))?
+            .adjust(generation, state)
+    }
+
+    pub fn info(&self) -> Result<()> {
+        let mut degrees = Vec::with_capacity(self.cells.len());
+        for cell in self.cells.iter() {
+            degrees.push(cell.effectors(self)?.into_iter().count());
+        }
+        let links = degrees.iter().sum::<usize>() / 2;
+        let isolated = degrees.iter().filter(|d| **d == 0).count();
+        let max_degree = degrees.iter().max().copied().unwrap_or_default();
+        let mean_degree = if degrees.is_empty() {
+            0.0
+        } else {
+            (2 * links) as f64 / degrees.len() as f64
+        };
+        info!(
+            "Network: nodes: {}, links: {links}, mean degree: {mean_degree:.2}, max degree: {max_degree}, isolated: {isolated}",
+            self.cells.len()
+        );
+        Ok(())
+    }
+}
+
+impl<S, Gen> Space<S, Gen> for CellNetwork<S, Gen>
+where
+    S: State<Gen>,
+    Gen: Generation,
+{
+    type Reg = CellRegion<Self, S, Gen>;
+    type Loc = Cell<S, Gen>;
+
+    fn regions(&self, generation: &Gen) -> impl IntoIterator<Item = Self::Reg> {
+        let region = CellRegion::new(generation.clone());
+        [region]
+    }
+
+    fn region<'a>(&'a self, generation: &Gen, _location: &Self::Loc) -> Option<Cow<'a, Self::Reg>> {
+        Some(Cow::Owned(CellRegion::new(generation.clone())))
+    }
+
+    fn update_all(&mut self, generation: &Gen, run: &Run<S::Parameters>) -> Result<()> {
+        update_cells(&self.cells, generation, run)
+    }
+
+    fn locations(&self, _region: &Self::Reg) -> impl IntoIterator<Item = Self::Loc> {
+        self.cells.clone()
+    }
+
+    fn free(&mut self, generation: &Gen) -> Result<()> {
+        self.freed.push_back(generation.clone());
+        while self.freed.len() > S::LOOKBACK {
+            if let Some(oldest) = self.freed.pop_front() {
+                free_cells(&self.cells, &oldest)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_probability(name: &str, p: f64) -> Result<()> {
+    if (0.0..=1.0).contains(&p) {
+        Ok(())
+    } else {
+        Err(anyhow!("The {name} must be between 0 and 1: [{p}]"))
+    }
+}
+
+fn erdos_renyi(nodes: usize, probability: f64, rng: &mut StdRng) -> Result<Graph> {
+    check_probability("probability", probability)?;
+    let mut graph = Graph::unlabeled(nodes);
+    for a in 0..nodes {
+        for b in (a + 1)..nodes {
+            if rng.random_bool(probability) {
+                graph.links.push((a, b, 1.0));
+            }
+        }
+    }
+    Ok(graph)
+}
+
+fn watts_strogatz(
+    nodes: usize,
+    neighbours: usize,
+    rewiring: f64,
+    rng: &mut StdRng,
+) -> Result<Graph> {
+    check_probability("rewiring probability", rewiring)?;
+    if !neighbours.is_multiple_of(2) || neighbours >= nodes {
+        return Err(anyhow!(
+            "The number of neighbours must be even and less than the number of nodes: [{neighbours}]: [{nodes}]"
+        ));
+    }
+    let mut linked = vec![HashSet::new(); nodes];
+    for (a, neighbours_of_a) in linked.iter_mut().enumerate() {
+        for j in 1..=(neighbours / 2) {
+            neighbours_of_a.insert((a + j) % nodes);
+            neighbours_of_a.insert((a + nodes - j) % nodes);
+        }
+    }
+    for j in 1..=(neighbours / 2) {
+        for a in 0..nodes {
+            let b = (a + j) % nodes;
+            if linked[a].len() + 1 < nodes && rng.random_bool(rewiring) {
+                let mut c = rng.random_range(0..nodes);
+                while c == a || linked[a].contains(&c) {
+                    c = rng.random_range(0..nodes);
+                }
+                linked[a].remove(&b);
+                linked[b].remove(&a);
+                linked[a].insert(c);
+                linked[c].insert(a);
+            }
+        }
+    }
+    let mut graph = Graph::unlabeled(nodes);
+    for (a, neighbours_of_a) in linked.iter().enumerate() {
+        let mut later = neighbours_of_a
+            .iter()
+            .filter(|b| **b > a)
+            .copied()
+            .collect::<Vec<_>>();
+        later.sort();
+        graph.links.extend(later.into_iter().map(|b| (a, b, 1.0)));
+    }
+    Ok(graph)
+}
+
+fn random_geometric(nodes: usize, radius: f64, rng: &mut StdRng) -> Graph {
+    let positions = (0..nodes)
+        .map(|_| (rng.random::<f64>(), rng.random::<f64>()))
+        .collect::<Vec<_>>();
+    let mut graph = Graph::unlabeled(nodes);
+    for (a, (xa, ya)) in positions.iter().enumerate() {
+        for (b, (xb, yb)) in positions.iter().enumerate().skip(a + 1) {
+            if (xa - xb).hypot(ya - yb) <= radius {
+                graph.links.push((a, b, 1.0));
+            }
+        }
+    }
+    graph.positions = Some(positions);
+    graph
+}
+
+fn load_edge_list(path: &PathBuf) -> Result<Graph> {
+    let text =
+        fs::read_to_string(path).map_err(|e| anyhow!("Could not read edge list: {path:?}: {e}"))?;
+    let mut graph = Graph::default();
+    let mut index_by_label = HashMap::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let weight = match fields.len() {
+            2 => 1.0,
+            3 => fields[2]
+                .parse::<Weight>()
+                .map_err(|e| anyhow!("Invalid weight on line {}: [{line}]: {e}", n + 1))?,
+            _ => return Err(anyhow!("Invalid link on line {}: [{line}]", n + 1)),
+        };
+        let a = graph.node(fields[0], &mut index_by_label);
+        let b = graph.node(fields[1], &mut index_by_label);
+        graph.links.push((a, b, weight));
+    }
+    Ok(graph)
+}
+
+fn load_graphml(path: &PathBuf) -> Result<Graph> {
+    let text =
+        fs::read_to_string(path).map_err(|e| anyhow!("Could not read GraphML: {path:?}: {e}"))?;
+    let document = roxmltree::Document::parse(&text)
+        .map_err(|e| anyhow!("Could not parse GraphML: {path:?}: {e}"))?;
+    let key = |domain: &str, name: &str| {
+        document
+            .descendants()
+            .filter(|n| n.has_tag_name("key"))
+            .find(|n| {
+                n.attribute("attr.name") == Some(name)
+                    && matches!(n.attribute("for"), Some(d) if d == domain || d == "all")
+            })
+    };
+    // The value of an attribute of a node or an edge, or else the default of its key.
+    let data = |node: roxmltree::Node, key: Option<roxmltree::Node>| -> Result<Option<f64>> {
+        let Some(key) = key else {
+            return Ok(None);
+        };
+        let id = key.attribute("id");
+        node.children()
+            .find(|c| c.has_tag_name("data") && c.attribute("key") == id)
+            .or_else(|| key.children().find(|c| c.has_tag_name("default")))
+            .and_then(|c| c.text())
+            .map(|t| {
+                t.trim().parse::<f64>().map_err(|e| {
+                    anyhow!("Invalid value for [{}]: [{t}]: {e}", id.unwrap_or_default())
+                })
+            })
+            .transpose()
+    };
+    let weight_key = key("edge", "weight");
+    let x_key = key("node", "x");
+    let y_key = key("node", "y");
+
+    let mut graph = Graph::default();
+    let mut index_by_label = HashMap::new();
+    let mut positions = Vec::new();
+    for node in document.descendants().filter(|n| n.has_tag_name("node")) {
+        let id = node
+            .attribute("id")
+            .ok_or_else(|| anyhow!("Node without id in GraphML: {path:?}"))?;
+        if index_by_label.contains_key(id) {
+            return Err(anyhow!("Duplicate node id in GraphML: {path:?}: [{id}]"));
+        }
+        graph.node(id, &mut index_by_label);
+        positions.push(data(node, x_key)?.zip(data(node, y_key)?));
+    }
+    for edge in document.descendants().filter(|n| n.has_tag_name("edge")) {
+        let end = |name: &str| {
+            edge.attribute(name)
+                .ok_or_else(|| anyhow!("Edge without {name} in GraphML: {path:?}"))
+        };
+        let a = graph.node(end("source")?, &mut index_by_label);
+        let b = graph.node(end("target")?, &mut index_by_label);
+        let weight = data(edge, weight_key)?.unwrap_or(1.0);
+        graph.links.push((a, b, weight));
+    }
+    if positions.len() == graph.labels.len() {
+        graph.positions = positions.into_iter().collect();
+    }
+    Ok(graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{structure::Region, wave::Wave};
+    use std::env::temp_dir;
+
+    /// Writes a GraphML file with the given nodes and edges and loads it.
+    fn graphml(name: &str, weight_default: &str, body: &str) -> Result<Graph> {
+        let path = temp_dir().join(format!("{name}-{}.graphml", std::process::id()));
+        fs::write(
+            &path,
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+  <key id="w" for="edge" attr.name="weight" attr.type="double">{weight_default}</key>
+  <key id="x" for="node" attr.name="x" attr.type="double"/>
+  <key id="y" for="node" attr.name="y" attr.type="double"/>
+  <graph edgedefault="undirected">{body}</graph>
+</graphml>"#
+            ),
+        )?;
+        let graph = load_graphml(&path);
+        fs::remove_file(&path)?;
+        graph
+    }
+
+    /// Runs a wave from the first node of a network and returns the bits of the amplitudes of the last generation.
+    fn wave_amplitudes(model: &NetworkModel, generations: usize) -> Result<Vec<u64>> {
+        let mut network = new_cell_network(model, 11, 0usize, |i| Wave::new(0.0, i == 0))?;
+        let run = Run::default();
+        for generation in 0..generations {
+            network.update_all(&generation, &run)?;
+            network.free(&generation)?;
+        }
+        Ok(
+            network.reduce(&generations, Vec::new(), |region, location, mut bits| {
+                if let Some(wave) = region.state(location) as Option<Wave> {
+                    bits.push(wave.amplitude().to_bits());
+                }
+                bits
+            }),
+        )
+    }
+
+    #[test]
+    fn same_network_runs_the_same() -> Result<()> {
+        let model = NetworkModel::RandomGeometric {
+            nodes: 200,
+            radius: 0.15,
+        };
+        let first = wave_amplitudes(&model, 30)?;
+        // Cells that were created in between must not change the second run.
+        wave_amplitudes(
+            &NetworkModel::ErdosRenyi {
+                nodes: 50,
+                probability: 0.1,
+            },
+            1,
+        )?;
+        let second = wave_amplitudes(&model, 30)?;
+        assert_eq!(first.len(), 200);
+        assert!(first.iter().any(|bits| f64::from_bits(*bits) != 0.0));
+        assert_eq!(first, second);
+        Ok(())
+    }
+
+    #[test]
+    fn graphml_weights_fall_back_to_the_default() -> Result<()> {
+        let body = r#"
+    <node id="a"><data key="x">0.0</data><data key="y">1.0</data></node>
+    <node id="b"><data key="x">2.0</data><data key="y">3.0</data></node>
+    <node id="c"><data key="x">4.0</data><data key="y">5.0</data></node>
+    <edge source="a" target="b"/>
+    <edge source="b" target="c"><data key="w">0.5</data></edge>"#;
+        let graph = graphml("default-weight", "<default>2.5</default>", body)?;
+        assert_eq!(graph.links, vec![(0, 1, 2.5), (1, 2, 0.5)]);
+        assert_eq!(
+            graph.positions,
+            Some(vec![(0.0, 1.0), (2.0, 3.0), (4.0, 5.0)])
+        );
+        let graph = graphml("no-default-weight", "", body)?;
+        assert_eq!(graph.links, vec![(0, 1, 1.0), (1, 2, 0.5)]);
+        Ok(())
+    }
+
+    #[test]
+    fn graphml_rejects_duplicate_nodes() {
+        let body = r#"
+    <node id="a"><data key="x">0.0</data><data key="y">1.0</data></node>
+    <node id="a"><data key="x">2.0</data><data key="y">3.0</data></node>"#;
+        let error = graphml("duplicate-node", "", body).err().unwrap();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Duplicate node id in GraphML")
+        );
+    }
+}
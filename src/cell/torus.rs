@@ -1,21 +1,73 @@
-use std::borrow::Cow;
+//! # Tori of cells
+//!
+//! A `CellTorus` stores the states of all cells of a generation in a single vector, indexed by the index of the cell.
+//! The cells are numbered with the last co-ordinate varying fastest.
+//! The effectors of all cells are stored back to back in one array (compressed sparse row): the effectors of cell *i*
+//! are `effectors[offsets[i]..offsets[i + 1]]`.
+//!
+//! Like a `Crystal`, a `CellTorus` keeps layers of states: the front holds the current generation and the past layers
+//! the previous ones, as many as the rule looks back (see `State::LOOKBACK`) plus one. `update_all` computes the next
+//! generation into the oldest layer and makes it the front, so a step allocates nothing. The generation that the oldest
+//! layer held before is overwritten, unless it was kept with `Space::retain`.
+//! `Space::reverse` reverses the order of the front and the layers that the rule looks back at, to run a `Reversible`
+//! rule backwards.
+
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fmt::{Debug, Display},
+    ops::Range,
+    rc::Rc,
+};
 
 use anyhow::{Result, anyhow};
 use log::{debug, info, trace};
 
 use crate::{
-    cell::{Cell, CellRegion, CellSpace, Generation, Region, State},
-    structure::Space,
+    cell::{Generation, Region, State},
+    structure::{
+        BlockRule, Location, Neighbourhood, Reversible, Run, Space, UpdateContext,
+        find_neighbourhood,
+    },
     torus::{
         Tiling, Torus,
         utils::{get_index, next_co_ordinates},
     },
 };
 
+/// The states of all cells of a torus in one generation.
+pub struct Layer<S: State<Gen>, Gen: Generation> {
+    states: Vec<S>,
+    generation: Gen,
+}
+
+pub type LayerRef<S, Gen> = Rc<RefCell<Layer<S, Gen>>>;
+
+/// A cell of a `CellTorus`, identified by its index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CellIndex(usize);
+
+/// Cached neighbourhoods, by location and radius.
+type Neighbourhoods = HashMap<(CellIndex, usize), Neighbourhood<CellIndex>>;
+
 pub struct CellTorus<S: State<Gen>, Gen: Generation> {
     tiling: Tiling,
     dimensions: Vec<usize>,
-    cells: Vec<Cell<S, Gen>>,
+    /// The start of the effectors of each cell in `effectors`, followed by the total number of effectors.
+    offsets: Vec<usize>,
+    effectors: Vec<usize>,
+    /// The states of the current generation.
+    front: LayerRef<S, Gen>,
+    front_generation: Gen,
+    /// The states of the previous generations, the most recent first, with their generation if it was not freed yet.
+    /// The oldest layer is room for the next generation.
+    past: VecDeque<(LayerRef<S, Gen>, Option<Gen>)>,
+    /// Copies of past generations that were kept on request.
+    retained: HashMap<Gen, LayerRef<S, Gen>>,
+    /// The generations that were freed while the rule still looked back at them.
+    freed: Vec<Gen>,
+    neighbourhoods: RefCell<Neighbourhoods>,
 }
 
 pub fn new_cell_torus<S: State<Gen>, Gen: Generation, F>(
@@ -27,36 +79,150 @@ pub fn new_cell_torus<S: State<Gen>, Gen: Generation, F>(
 where
     F: Fn(&[usize]) -> S,
 {
-    let mut cardinality = 1usize;
-    for i in 0..dimensions.len() {
-        cardinality *= dimensions[i];
-    }
-    let mut cells = Vec::with_capacity(cardinality);
-    let mut co_ordinates = Vec::new();
-    create_cells(
-        &mut co_ordinates,
-        &dimensions,
-        &mut cells,
-        &initial_gen,
-        &initial_state,
-        0,
-    );
-    debug!("Torus: Number of cells: [{}]", cells.len());
-
-    let torus = CellTorus {
+    let cardinality: usize = dimensions.iter().product();
+    let mut states = Vec::with_capacity(cardinality);
+    let mut co_ordinates = vec![0usize; dimensions.len()];
+    for _ in 0..cardinality {
+        states.push(initial_state(&co_ordinates));
+        next_co_ordinates(&mut co_ordinates, dimensions);
+    }
+    debug!("Torus: Number of cells: [{}]", states.len());
+
+    let mut links = Links::new(cardinality);
+    match tiling {
+        Tiling::Orthogonal => connect_orthogonally(&mut links, dimensions)?,
+        Tiling::OrthogonalAndDiagonal => {
+            connect_orthogonally_and_diagonally(&mut links, dimensions)?
+        }
+        Tiling::Hexagons => connect_hexagons(&mut links, dimensions)?,
+        Tiling::AdjacentTriangles => {
+            connect_triangles(&mut links, dimensions, &ADJACENT_TRIANGLES)?
+        }
+        Tiling::TouchingTriangles => {
+            connect_triangles(&mut links, dimensions, &TOUCHING_TRIANGLES)?
+        }
+    }
+    let (offsets, effectors) = links.compress();
+
+    let layer = |states| {
+        Rc::new(RefCell::new(Layer {
+            states,
+            generation: initial_gen.clone(),
+        }))
+    };
+    Ok(CellTorus {
         tiling,
         dimensions: dimensions.into(),
-        cells,
-    };
+        offsets,
+        effectors,
+        past: (0..=S::LOOKBACK)
+            .map(|_| (layer(states.clone()), None))
+            .collect(),
+        front: layer(states),
+        front_generation: initial_gen.clone(),
+        retained: HashMap::new(),
+        freed: Vec::new(),
+        neighbourhoods: RefCell::new(HashMap::new()),
+    })
+}
 
-    match tiling {
-        Tiling::Orthogonal => connect_orthogonally(&torus)?,
-        Tiling::OrthogonalAndDiagonal => connect_orthogonally_and_diagonally(&torus)?,
-        Tiling::Hexagons => connect_hexagons(&torus)?,
-        _ => todo!(),
+impl<S: State<Gen>, Gen: Generation> CellTorus<S, Gen> {
+    /// The states of the given generation: the current one, a previous one until it is freed, or a retained one.
+    fn layer(&self, generation: &Gen) -> Option<&LayerRef<S, Gen>> {
+        if *generation == self.front_generation {
+            return Some(&self.front);
+        }
+        self.past
+            .iter()
+            .find(|(_, past_generation)| past_generation.as_ref() == Some(generation))
+            .map(|(layer, _)| layer)
+            .or_else(|| self.retained.get(generation))
     }
 
-    Ok(torus)
+    /// Computes the successor of the current generation into the oldest layer and makes that the current layer.
+    fn advance<F>(&mut self, generation: &Gen, compute: F) -> Result<()>
+    where
+        F: FnOnce(&Self, &mut Layer<S, Gen>) -> Result<()>,
+    {
+        if *generation != self.front_generation {
+            return Err(anyhow!(
+                "Not the current generation: [{generation:?}]: current: [{:?}]",
+                self.front_generation
+            ));
+        }
+        let next_generation = generation.successor();
+        let oldest = self
+            .past
+            .back_mut()
+            .ok_or_else(|| anyhow!("No room for the next generation"))?;
+        // The oldest generation is overwritten, so rules cannot see it anymore.
+        oldest.1 = None;
+        let next = oldest.0.clone();
+        {
+            let mut layer = next.borrow_mut();
+            layer.generation = next_generation.clone();
+            compute(self, &mut layer)?;
+        }
+        self.past.pop_back();
+        let previous = std::mem::replace(&mut self.front, next);
+        let previous_generation = std::mem::replace(&mut self.front_generation, next_generation);
+        self.past.push_front((previous, Some(previous_generation)));
+        self.forget_freed();
+        Ok(())
+    }
+
+    /// Forgets the generation that the rule no longer looks back at, if it was freed before.
+    fn forget_freed(&mut self) {
+        if let Some((_, Some(generation))) = self.past.get(S::LOOKBACK)
+            && let Some(i) = self.freed.iter().position(|freed| freed == generation)
+        {
+            self.freed.swap_remove(i);
+            self.past[S::LOOKBACK].1 = None;
+        }
+    }
+
+    /// Computes the next generation cell by cell with the given rule.
+    fn update_cells<F>(&mut self, generation: &Gen, run: &Run<S::Parameters>, rule: F) -> Result<()>
+    where
+        F: Fn(
+            &Self,
+            &LayerRef<S, Gen>,
+            &CellIndex,
+            &UpdateContext<S::Parameters, Gen>,
+        ) -> Result<S>,
+    {
+        let context = run.context(generation);
+        self.advance(generation, |torus, next| {
+            for index in 0..next.states.len() {
+                trace!("Update: [{index}]");
+                let location = CellIndex(index);
+                next.states[index] = rule(torus, &torus.front, &location, &context.at(&location))?;
+            }
+            Ok(())
+        })
+    }
+
+    fn effector_range(&self, index: usize) -> Range<usize> {
+        self.offsets[index]..self.offsets[index + 1]
+    }
+
+    fn to_strings(&self, generation: &Gen) -> Vec<String> {
+        let mut lines = Vec::new();
+        let Some(layer) = self.layer(generation) else {
+            return lines;
+        };
+        let states = &layer.borrow().states;
+        match self.tiling {
+            Tiling::Hexagons => hexagons_to_strings(states, &self.dimensions, &mut lines),
+            Tiling::Orthogonal
+            | Tiling::OrthogonalAndDiagonal
+            | Tiling::AdjacentTriangles
+            | Tiling::TouchingTriangles => {
+                orthogonal_to_strings(states, &self.dimensions, &mut lines)
+            }
+        };
+        lines
+    }
 }
 
 impl<S: State<Gen>, Gen: Generation> Torus<S, Gen> for CellTorus<S, Gen> {
@@ -72,55 +238,64 @@ impl<S: State<Gen>, Gen: Generation> Torus<S, Gen> for CellTorus<S, Gen> {
 
     fn info(&self, generation: &Gen) {
         info!("Generation: {generation:?}");
-        let mut lines = Vec::new();
-        match self.tiling {
-            Tiling::Orthogonal => {
-                orthogonal_to_strings(&self.cells, &self.dimensions, generation, &mut lines)
-            }
-            Tiling::OrthogonalAndDiagonal => {
-                orthogonal_to_strings(&self.cells, &self.dimensions, generation, &mut lines)
-            }
-            Tiling::Hexagons => {
-                hexagons_to_strings(&self.cells, &self.dimensions, generation, &mut lines);
-            }
-            _ => todo!(),
-        };
-        for line in lines {
+        for line in self.to_strings(generation) {
             info!("Line: [{line}]")
         }
     }
 
-    fn update_all_cells(&mut self, generation: &Gen) -> Result<()> {
-        self.update_all(generation)
+    fn update_all_cells(&mut self, generation: &Gen, run: &Run<S::Parameters>) -> Result<()> {
+        self.update_all(generation, run)
     }
 
     fn tiling(&self) -> Tiling {
         self.tiling
     }
 
+    /// The cells are stored with the last co-ordinate varying fastest, so the axes are reported in reverse order.
     fn dimensions(&self) -> Vec<usize> {
-        self.dimensions.clone()
+        self.dimensions.iter().rev().copied().collect()
     }
 
     fn adjust(&mut self, generation: &Gen, x: usize, y: usize, state: S) -> Result<()> {
-        let index = y * self.dimensions[1] + x;
-        let mut write_lock = self.cells[index]
-            .0
-            .state_map
-            .write()
-            .map_err(|e| anyhow!("Could not get write lock: {e}"))?;
-        write_lock.get_mut(generation).map(|s| *s = state);
+        let &[height, width] = self.dimensions.as_slice() else {
+            return Err(anyhow!(
+                "Only a 2-D torus can be adjusted at (x, y): {:?}",
+                self.dimensions()
+            ));
+        };
+        if x >= width || y >= height {
+            return Err(anyhow!(
+                "Coordinates outside the torus: ({x}, {y}): [{width}, {height}]"
+            ));
+        }
+        self.layer(generation)
+            .ok_or_else(|| anyhow!("Generation not available: [{generation:?}]"))?
+            .borrow_mut()
+            .states[y * width + x] = state;
         Ok(())
     }
 
     fn coordinates(
         &self,
-        _region: &<Self::Spc as Space<S, Gen>>::Reg,
+        region: &<Self::Spc as Space<S, Gen>>::Reg,
         location: &<Self::Spc as Space<S, Gen>>::Loc,
     ) -> (usize, usize) {
-        let width = self.dimensions[0];
-        let index = location.0.index;
-        (index % width, index / width)
+        let co_ordinates = self.co_ordinates(region, location);
+        (co_ordinates[0], co_ordinates.get(1).copied().unwrap_or(0))
+    }
+
+    fn co_ordinates(
+        &self,
+        _region: &<Self::Spc as Space<S, Gen>>::Reg,
+        location: &<Self::Spc as Space<S, Gen>>::Loc,
+    ) -> Vec<usize> {
+        let mut index = location.0;
+        let mut result = Vec::with_capacity(self.dimensions.len());
+        for dimension in self.dimensions.iter().rev() {
+            result.push(index % dimension);
+            index /= dimension;
+        }
+        result
     }
 }
 
@@ -129,12 +304,11 @@ where
     S: State<Gen>,
     Gen: Generation,
 {
-    type Reg = CellRegion<Self, S, Gen>;
-    type Loc = Cell<S, Gen>;
+    type Reg = LayerRef<S, Gen>;
+    type Loc = CellIndex;
 
     fn regions(&self, generation: &Gen) -> impl IntoIterator<Item = Self::Reg> {
-        let region = CellRegion::new(generation.clone());
-        [region]
+        self.layer(generation).cloned()
     }
 
     fn region<'a>(
@@ -142,206 +316,324 @@ where
         generation: &Gen,
         _location: &Self::Loc,
     ) -> Option<std::borrow::Cow<'a, Self::Reg>> {
-        Some(Cow::Owned(CellRegion::new(generation.clone())))
+        self.layer(generation).map(Cow::Borrowed::<'a>)
     }
 
-    fn update_all(&mut self, generation: &Gen) -> Result<()> {
-        for cell in &self.cells {
-            trace!("Update: [{:?}]", cell.id());
-            let space = CellSpace;
-            cell.update(&space, generation)?;
-        }
-        Ok(())
+    fn update_all(&mut self, generation: &Gen, run: &Run<S::Parameters>) -> Result<()> {
+        self.update_cells(generation, run, S::update)
     }
 
-    fn locations(&self, _region: &Self::Reg) -> impl IntoIterator<Item = Self::Loc> {
-        self.cells.clone()
+    fn update_all_inverse(&mut self, generation: &Gen, run: &Run<S::Parameters>) -> Result<()>
+    where
+        S: Reversible<Gen>,
+    {
+        self.update_cells(generation, run, S::update_inverse)
     }
 
+    /// Copies the current generation into the next one and replaces the cells of each block.
+    fn update_blocks(
+        &mut self,
+        generation: &Gen,
+        run: &Run<S::Parameters>,
+        blocks: &[Vec<CellIndex>],
+    ) -> Result<()>
+    where
+        S: BlockRule<Gen>,
+    {
+        let context = run.context(generation);
+        self.advance(generation, |torus, next| {
+            let front = torus.front.borrow();
+            next.states.clone_from_slice(&front.states);
+            let mut states = Vec::new();
+            for block in blocks {
+                let Some(first) = block.first() else {
+                    continue;
+                };
+                trace!("Update block: [{first:?}]");
+                states.clear();
+                states.extend(
+                    block
+                        .iter()
+                        .map(|location| front.states[location.0].clone()),
+                );
+                S::update_block(&mut states, &context.at(first))?;
+                for (location, state) in block.iter().zip(states.drain(..)) {
+                    next.states[location.0] = state;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn reverse(&mut self, generation: &Gen) -> Result<()> {
+        if *generation != self.front_generation {
+            return Err(anyhow!(
+                "Not the current generation: [{generation:?}]: current: [{:?}]",
+                self.front_generation
+            ));
+        }
+        if self
+            .past
+            .iter()
+            .take(S::LOOKBACK)
+            .any(|(_, past)| past.is_none())
+        {
+            return Err(anyhow!(
+                "Not enough generations to reverse: [{generation:?}]: need {}",
+                S::LOOKBACK
+            ));
+        }
+        let mut layers = std::iter::once(self.front.clone())
+            .chain(
+                self.past
+                    .iter()
+                    .take(S::LOOKBACK)
+                    .map(|(layer, _)| layer.clone()),
+            )
+            .collect::<Vec<_>>();
+        let generations = std::iter::once(self.front_generation.clone())
+            .chain(
+                self.past
+                    .iter()
+                    .take(S::LOOKBACK)
+                    .flat_map(|(_, past)| past.clone()),
+            )
+            .collect::<Vec<_>>();
+        layers.reverse();
+        for (layer, generation) in layers.iter().zip(generations.iter()) {
+            layer.borrow_mut().generation = generation.clone();
+            self.retained.remove(generation);
+        }
+        let mut layers = layers.into_iter();
+        if let Some(front) = layers.next() {
+            self.front = front;
+        }
+        for ((past, _), layer) in self.past.iter_mut().zip(layers) {
+            *past = layer;
+        }
+        Ok(())
+    }
+
+    /// Forgets the given generation, unless the rule still looks back at it (see `State::LOOKBACK`).
     fn free(&mut self, generation: &Gen) -> Result<()> {
-        for cell in &self.cells {
-            cell.0
-                .state_map
-                .write()
-                .map_err(|e| anyhow!("Can't get write lock on cells: {e:?}"))?
-                .remove(generation);
+        if *generation == self.front_generation {
+            return Err(anyhow!(
+                "Cannot free the current generation: [{generation:?}]"
+            ));
         }
+        for (i, (_, past_generation)) in self.past.iter_mut().enumerate() {
+            if past_generation.as_ref() == Some(generation) {
+                if i < S::LOOKBACK {
+                    self.freed.push(generation.clone());
+                } else {
+                    *past_generation = None;
+                }
+            }
+        }
+        self.retained.remove(generation);
+        Ok(())
+    }
+
+    /// Keeps a copy of the given generation, so that it survives later updates until it is freed.
+    /// Only the current generation and the previous ones that were not freed yet can be retained.
+    fn retain(&mut self, generation: &Gen) -> Result<()> {
+        let layer = self
+            .layer(generation)
+            .ok_or_else(|| anyhow!("Generation not available: [{generation:?}]"))?
+            .borrow();
+        let copy = Layer {
+            states: layer.states.clone(),
+            generation: layer.generation.clone(),
+        };
+        drop(layer);
+        self.retained
+            .insert(generation.clone(), Rc::new(RefCell::new(copy)));
         Ok(())
     }
 }
 
-fn create_cells<S: State<Gen>, Gen: Generation, F>(
-    co_ordinates: &mut Vec<usize>,
-    dimensions: &[usize],
-    cells: &mut Vec<Cell<S, Gen>>,
-    initial_gen: &Gen,
-    initial_state: &F,
-    start_index: usize,
-) -> usize
+impl<Spc, S, Gen> Region<Spc, S, Gen> for LayerRef<S, Gen>
 where
-    F: Fn(&[usize]) -> S,
+    Spc: Space<S, Gen, Loc = CellIndex>,
+    S: State<Gen>,
+    Gen: Generation,
 {
-    let next_index: usize;
-    co_ordinates.push(0);
-    if dimensions.len() == 1 {
-        for i in 0..dimensions[0] {
-            co_ordinates.pop();
-            co_ordinates.push(i);
-            let state = initial_state(co_ordinates);
-            cells.push(Cell::new_with_index(
-                initial_gen.clone(),
-                state,
-                start_index + i,
-            ));
-        }
-        next_index = start_index + dimensions[0];
-    } else if dimensions.len() > 1 {
-        let mut start_index = start_index;
-        let subdimensions = &dimensions[1..];
-        for i in 0..dimensions[0] {
-            co_ordinates.pop();
-            co_ordinates.push(i);
-            start_index = create_cells(
-                co_ordinates,
-                subdimensions,
-                cells,
-                initial_gen,
-                initial_state,
-                start_index,
-            );
-        }
-        next_index = start_index;
-    } else {
-        next_index = start_index;
+    fn locations(&self) -> impl IntoIterator<Item = Spc::Loc> {
+        (0..self.borrow().states.len()).map(CellIndex)
+    }
+
+    fn generation(&self) -> Gen {
+        self.borrow().generation.clone()
+    }
+
+    fn state(&self, location: &Spc::Loc) -> Option<S> {
+        self.borrow().states.get(location.0).cloned()
     }
-    co_ordinates.pop();
-    next_index
 }
 
-fn connect_orthogonally_and_diagonally<S, Gen>(torus: &CellTorus<S, Gen>) -> Result<()>
+impl<S: State<Gen>, Gen: Generation> Debug for Layer<S, Gen> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Layer")
+            .field("generation", &self.generation)
+            .field("cells", &self.states.len())
+            .finish()
+    }
+}
+
+impl<S, Gen> Location<CellTorus<S, Gen>, S, Gen> for CellIndex
 where
     S: State<Gen>,
     Gen: Generation,
 {
-    connect_orthogonally(torus)?;
-    connect_diagonally(torus)?;
-    Ok(())
+    fn effectors(&self, space: &CellTorus<S, Gen>) -> Result<impl IntoIterator<Item = Self>> {
+        Ok(space.effectors[space.effector_range(self.0)]
+            .iter()
+            .map(|index| CellIndex(*index)))
+    }
+
+    fn id(&self, _space: &CellTorus<S, Gen>) -> String {
+        self.0.to_string()
+    }
+
+    fn neighbourhood(
+        &self,
+        space: &CellTorus<S, Gen>,
+        radius: usize,
+    ) -> Result<Neighbourhood<Self>> {
+        let key = (*self, radius);
+        if let Some(neighbourhood) = space.neighbourhoods.borrow().get(&key) {
+            return Ok(neighbourhood.clone());
+        }
+        let neighbourhood = find_neighbourhood(self, space, radius)?;
+        space
+            .neighbourhoods
+            .borrow_mut()
+            .insert(key, neighbourhood.clone());
+        Ok(neighbourhood)
+    }
 }
 
-fn connect_orthogonally<S: State<Gen>, Gen: Generation>(torus: &CellTorus<S, Gen>) -> Result<()> {
-    let cells = &torus.cells;
-    let mut co_ordinates = Vec::<usize>::new();
-    let dimensionality = torus.dimensions.len();
-    for _ in 0..dimensionality {
-        co_ordinates.push(0);
+/// The effectors of each cell while the torus is being connected.
+struct Links(Vec<Vec<usize>>);
+
+impl Links {
+    fn new(cardinality: usize) -> Self {
+        Links(vec![Vec::new(); cardinality])
+    }
+
+    /// Makes both cells effectors of each other. Joining cells that are already joined has no effect.
+    fn join(&mut self, this: usize, that: usize) {
+        if !self.0[this].contains(&that) {
+            self.0[this].push(that);
+        }
+        if !self.0[that].contains(&this) {
+            self.0[that].push(this);
+        }
+    }
+
+    /// Stores the effectors of all cells back to back.
+    fn compress(self) -> (Vec<usize>, Vec<usize>) {
+        let mut offsets = Vec::with_capacity(self.0.len() + 1);
+        let mut effectors = Vec::with_capacity(self.0.iter().map(Vec::len).sum());
+        for cell_effectors in self.0 {
+            offsets.push(effectors.len());
+            effectors.extend(cell_effectors);
+        }
+        offsets.push(effectors.len());
+        (offsets, effectors)
     }
-    for i in 0..cells.len() {
-        assert!(get_index(&co_ordinates, &torus.dimensions)? == i);
-        let center = &cells[i];
+}
+
+fn connect_orthogonally_and_diagonally(links: &mut Links, dimensions: &[usize]) -> Result<()> {
+    connect_orthogonally(links, dimensions)?;
+    connect_diagonally(links, dimensions)?;
+    Ok(())
+}
+
+fn connect_orthogonally(links: &mut Links, dimensions: &[usize]) -> Result<()> {
+    let dimensionality = dimensions.len();
+    let mut co_ordinates = vec![0usize; dimensionality];
+    for i in 0..links.0.len() {
+        assert!(get_index(&co_ordinates, dimensions)? == i);
         for k in 0..dimensionality {
             let mut other = co_ordinates.clone();
-            for d in &[torus.dimensions[k] - 1, 1] {
-                other[k] = (co_ordinates[k] + d) % torus.dimensions[k];
-                let other_index = get_index(&other, &torus.dimensions)?;
+            for d in &[dimensions[k] - 1, 1] {
+                other[k] = (co_ordinates[k] + d) % dimensions[k];
+                let other_index = get_index(&other, dimensions)?;
                 trace!(
                     "Join effectors: ({:?}) <=> ({:?}) ~ {} <=> {}",
                     &co_ordinates, &other, i, other_index
                 );
-                let effector = &cells[other_index];
-                center.join(effector)?;
+                links.join(i, other_index);
             }
         }
-        next_co_ordinates(&mut co_ordinates, &torus.dimensions);
+        next_co_ordinates(&mut co_ordinates, dimensions);
     }
     Ok(())
 }
 
-fn connect_diagonally<S: State<Gen>, Gen: Generation>(torus: &CellTorus<S, Gen>) -> Result<()> {
-    let cells = &torus.cells;
-    let mut co_ordinates = Vec::<usize>::new();
-    let dimensionality = torus.dimensions.len();
-    for _ in 0..dimensionality {
-        co_ordinates.push(0);
-    }
-    for i in 0..cells.len() {
-        assert!(get_index(&co_ordinates, &torus.dimensions)? == i);
-        let center = &cells[i];
+fn connect_diagonally(links: &mut Links, dimensions: &[usize]) -> Result<()> {
+    let dimensionality = dimensions.len();
+    let mut co_ordinates = vec![0usize; dimensionality];
+    for i in 0..links.0.len() {
+        assert!(get_index(&co_ordinates, dimensions)? == i);
         let corner_ids: usize = 1 << dimensionality;
         for c in 0..corner_ids {
             let mut corner = Vec::new();
             let mut bits = c;
-            for k in 0..dimensionality {
-                let offset = if bits & 1 == 1 {
-                    1
-                } else {
-                    torus.dimensions[k] - 1
-                };
+            for (co_ordinate, dimension) in co_ordinates.iter().zip(dimensions) {
+                let offset = if bits & 1 == 1 { 1 } else { dimension - 1 };
                 bits >>= 1;
-                corner.push((co_ordinates[k] + offset) % torus.dimensions[k])
+                corner.push((co_ordinate + offset) % dimension)
             }
-            let corner_index = get_index(&corner, &torus.dimensions)?;
+            let corner_index = get_index(&corner, dimensions)?;
             trace!(
                 "Join corner co-ordinates: ({:?}) <=> ({:?}) ~ {} <=> {}",
                 &co_ordinates, &corner, i, corner_index
             );
-            center.join(&cells[corner_index])?;
+            links.join(i, corner_index);
         }
-        next_co_ordinates(&mut co_ordinates, &torus.dimensions);
+        next_co_ordinates(&mut co_ordinates, dimensions);
     }
     Ok(())
 }
 
-fn orthogonal_to_strings<S: State<Gen>, Gen: Generation>(
-    cells: &[Cell<S, Gen>],
-    dimensions: &[usize],
-    generation: &Gen,
-    result: &mut Vec<String>,
-) {
+fn orthogonal_to_strings<S: Display>(states: &[S], dimensions: &[usize], result: &mut Vec<String>) {
     let dimensionality = dimensions.len();
     if dimensionality > 1 {
         result.push("".to_string());
-        let mut width = 1;
-        for k in 1..dimensionality {
-            width = width * dimensions[k];
-        }
+        let width: usize = dimensions[1..].iter().product();
         for i in 0..dimensions[0] {
             let start = i * width;
-            orthogonal_to_strings(
-                &cells[start..(start + width)],
-                &dimensions[1..],
-                generation,
-                result,
-            );
+            orthogonal_to_strings(&states[start..(start + width)], &dimensions[1..], result);
         }
     } else if dimensionality == 1 {
-        result.push(line_to_string(cells, dimensions[0], generation, 0, "", ""));
+        result.push(line_to_string(states, "", ""));
     }
 }
 
-fn connect_hexagons<S: State<Gen>, Gen: Generation>(torus: &CellTorus<S, Gen>) -> Result<()> {
-    if torus.dimensions.len() != 2 {
+fn connect_hexagons(links: &mut Links, dimensions: &[usize]) -> Result<()> {
+    if dimensions.len() != 2 {
         return Err(anyhow!("Tiling with triangles is only possible in 2-D"));
     }
-    let height = torus.dimensions[0];
-    let width = torus.dimensions[1];
+    let height = dimensions[0];
+    let width = dimensions[1];
     if (height % 2) == 1 || (width % 2) == 1 {
         return Err(anyhow!(
             "Tiling with triangles is only possible if both dimensions are even"
         ));
     }
-    let cells = &torus.cells;
     let mut co_ordinates = vec![0, 0];
-    for i in 0..cells.len() {
-        assert!(get_index(&co_ordinates, &torus.dimensions)? == i);
-        let center = &cells[i];
+    for i in 0..links.0.len() {
+        assert!(get_index(&co_ordinates, dimensions)? == i);
         let y = (co_ordinates[0] + 1) % height;
         let offset = width - (co_ordinates[0]) % 2;
         let lx = (co_ordinates[1] + offset) % width;
         let rx = (co_ordinates[1] + offset + 1) % width;
         let ax = (co_ordinates[1] + 1) % width;
-        let left_index = get_index(&[y, lx], &torus.dimensions)?;
-        let right_index = get_index(&[y, rx], &torus.dimensions)?;
-        let next_index = get_index(&[co_ordinates[0], ax], &torus.dimensions)?;
+        let left_index = get_index(&[y, lx], dimensions)?;
+        let right_index = get_index(&[y, rx], dimensions)?;
+        let next_index = get_index(&[co_ordinates[0], ax], dimensions)?;
         debug!(
             "Join left hexagon: ({:?}) <=> ({:?}) ~ {} <=> {}",
             &co_ordinates,
@@ -363,56 +655,144 @@ fn connect_hexagons<S: State<Gen>, Gen: Generation>(torus: &CellTorus<S, Gen>) -
             i,
             next_index
         );
-        let left = &cells[left_index];
-        let right = &cells[right_index];
-        let next = &cells[next_index];
-        center.join(&left)?;
-        center.join(&right)?;
-        center.join(&next)?;
-        next_co_ordinates(&mut co_ordinates, &torus.dimensions);
+        links.join(i, left_index);
+        links.join(i, right_index);
+        links.join(i, next_index);
+        next_co_ordinates(&mut co_ordinates, dimensions);
     }
     Ok(())
 }
 
-fn hexagons_to_strings<S: State<Gen>, Gen: Generation>(
-    cells: &[Cell<S, Gen>],
+/// Offsets (dx, dy) of the neighbours of a triangle that points up. The neighbours of a triangle that points down are
+/// mirrored vertically. Adjacent triangles share an edge.
+const ADJACENT_TRIANGLES: [(isize, isize); 3] = [(-1, 0), (1, 0), (0, 1)];
+
+/// Touching triangles share an edge or a corner.
+const TOUCHING_TRIANGLES: [(isize, isize); 12] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-2, 0),
+    (-1, 0),
+    (1, 0),
+    (2, 0),
+    (-2, 1),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+    (2, 1),
+];
+
+/// Triangles point up where the sum of the co-ordinates is even, so both dimensions must be even for the torus to
+/// close.
+fn connect_triangles(
+    links: &mut Links,
     dimensions: &[usize],
-    generation: &Gen,
-    result: &mut Vec<String>,
-) {
+    offsets: &[(isize, isize)],
+) -> Result<()> {
+    if dimensions.len() != 2 {
+        return Err(anyhow!("Tiling with triangles is only possible in 2-D"));
+    }
+    let height = dimensions[0];
+    let width = dimensions[1];
+    if (height % 2) == 1 || (width % 2) == 1 {
+        return Err(anyhow!(
+            "Tiling with triangles is only possible if both dimensions are even"
+        ));
+    }
+    let mut co_ordinates = vec![0, 0];
+    for i in 0..links.0.len() {
+        assert!(get_index(&co_ordinates, dimensions)? == i);
+        let (y, x) = (co_ordinates[0], co_ordinates[1]);
+        let up = (x + y).is_multiple_of(2);
+        for (dx, dy) in offsets {
+            let dy = if up { *dy } else { -dy };
+            let ox = (x as isize + dx).rem_euclid(width as isize) as usize;
+            let oy = (y as isize + dy).rem_euclid(height as isize) as usize;
+            let other_index = get_index(&[oy, ox], dimensions)?;
+            trace!("Join triangle: ({x}, {y}) <=> ({ox}, {oy}) ~ {i} <=> {other_index}");
+            links.join(i, other_index);
+        }
+        next_co_ordinates(&mut co_ordinates, dimensions);
+    }
+    Ok(())
+}
+
+fn hexagons_to_strings<S: Display>(states: &[S], dimensions: &[usize], result: &mut Vec<String>) {
     let height = dimensions[0];
     let width = dimensions[1];
     let mut start = 0;
     for y in 0..height {
         let prefix = if (y % 2) == 0 { " " } else { "" };
-        result.push(line_to_string(
-            &cells[start..start + width],
-            width,
-            generation,
-            0,
-            prefix,
-            " ",
-        ));
+        result.push(line_to_string(&states[start..start + width], prefix, " "));
         start += width;
     }
 }
 
-fn line_to_string<S: State<Gen>, Gen: Generation>(
-    cells: &[Cell<S, Gen>],
-    width: usize,
-    generation: &Gen,
-    offset: usize,
-    prefix: &str,
-    sep: &str,
-) -> String {
-    let region: CellRegion<CellSpace, S, Gen> = CellRegion::new(generation.clone());
+fn line_to_string<S: Display>(states: &[S], prefix: &str, sep: &str) -> String {
     let mut line = prefix.to_string();
-    for x in 0..width {
-        let s = (region.state(&cells[(x + offset) % width]) as Option<S>)
-            .map(|s| format!("{s}"))
-            .unwrap_or("?".to_string());
-        line.push_str(&s);
+    for state in states {
+        line.push_str(&format!("{state}"));
         line.push_str(sep);
     }
     line
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{conway::Conway, wave::Wave};
+
+    #[test]
+    fn adjust_checks_the_coordinates() -> Result<()> {
+        let mut torus = new_cell_torus(Tiling::Orthogonal, &[3, 4], 0usize, |_: &[usize]| {
+            Conway::new(false)
+        })?;
+        torus.adjust(&0, 3, 2, Conway::new(true))?;
+        assert!(torus.adjust(&0, 4, 0, Conway::new(true)).is_err());
+        assert!(torus.adjust(&0, 0, 3, Conway::new(true)).is_err());
+        let live = torus.reduce(&0, Vec::new(), |region, location, mut live| {
+            if (torus.state(&0, location) as Option<Conway>).is_some_and(|c| c.alive) {
+                live.push(torus.coordinates(region, location));
+            }
+            live
+        });
+        assert_eq!(live, [(3, 2)]);
+
+        let mut cube = new_cell_torus(Tiling::Orthogonal, &[2, 2, 2], 0usize, |_: &[usize]| {
+            Conway::new(false)
+        })?;
+        assert!(cube.adjust(&0, 0, 0, Conway::new(true)).is_err());
+        Ok(())
+    }
+
+    /// Runs a wave from the middle of a hexagonal torus and returns the bits of the amplitudes of the last generation.
+    fn wave_amplitudes(generations: usize) -> Result<Vec<u64>> {
+        let mut torus = new_cell_torus(Tiling::Hexagons, &[16, 16], 0usize, |v: &[usize]| {
+            Wave::new(0.0, v == [8, 8])
+        })?;
+        let run = Run::default();
+        for generation in 0..generations {
+            torus.update_all(&generation, &run)?;
+            torus.free(&generation)?;
+        }
+        Ok(
+            torus.reduce(&generations, Vec::new(), |_, location, mut bits| {
+                if let Some(wave) = torus.state(&generations, location) as Option<Wave> {
+                    bits.push(wave.amplitude().to_bits());
+                }
+                bits
+            }),
+        )
+    }
+
+    #[test]
+    fn same_wave_runs_the_same() -> Result<()> {
+        let first = wave_amplitudes(40)?;
+        let second = wave_amplitudes(40)?;
+        assert_eq!(first.len(), 16 * 16);
+        assert!(first.iter().any(|bits| f64::from_bits(*bits) != 0.0));
+        assert_eq!(first, second);
+        Ok(())
+    }
+}
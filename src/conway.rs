@@ -1,16 +1,30 @@
-use std::fmt::{Display, Write};
+use std::{
+    collections::HashSet,
+    fmt::{Display, Write},
+    path::{Path, PathBuf},
+};
 
 use crate::{
     cell::new_cell_torus,
-    structure::{Generation, Location, Region, Space, State},
-    torus::{Tiling, Torus},
+    patch::{CsrEffectors, new_patch_torus},
+    pattern::Pattern,
+    simulation::{Cadence, Simulation},
+    structure::{FromGrayScale, GrayScale, Location, Region, Space, State, UpdateContext},
+    torus::{
+        GrayScaleTorus, Tiling, Torus,
+        animation::{Animation, AnimationOptions, Recorder},
+        import::Picture,
+        render::View,
+        svg::{SvgOptions, export_svg},
+    },
+    totalistic::{Rule, Totalistic},
 };
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 // use log::debug;
-// use log::info;
-use log::trace;
+use log::{info, trace, warn};
+use rand::{Rng, SeedableRng, rngs::StdRng};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct Conway {
     pub alive: bool,
 }
@@ -22,10 +36,13 @@ impl Conway {
 }
 
 impl State<usize> for Conway {
+    type Parameters = ();
+
     fn update<Spc: Space<Self, usize>>(
         space: &Spc,
         region: &Spc::Reg,
         location: &Spc::Loc,
+        _context: &UpdateContext<(), usize>,
     ) -> Result<Self> {
         trace!("Update: [{}]", location.id(space));
         let this_state = (region.state(location) as Option<Self>)
@@ -35,10 +52,10 @@ impl State<usize> for Conway {
         let mut count = 0;
         for effector in location.effectors(space)? {
             trace!("Effector: [{}]", effector.id(space));
-            if let Some(state) = region.state(&effector) as Option<Self> {
-                if state.alive {
-                    count += 1;
-                }
+            if let Some(state) = region.state(&effector) as Option<Self>
+                && state.alive
+            {
+                count += 1;
             }
         }
         let next_state = count == 3 || (this_state && count == 2);
@@ -54,19 +71,252 @@ impl Display for Conway {
     }
 }
 
-pub fn example() -> Result<()> {
-    let width = 5;
-    let height = 5;
+impl GrayScale for Conway {
+    type Context = ();
+
+    fn gray_value(&self, _context: &()) -> u8 {
+        if self.alive { 255 } else { 0 }
+    }
+}
+
+/// Light cells are alive.
+impl FromGrayScale for Conway {
+    type Context = ();
+
+    fn from_gray_value(value: u8, _context: &()) -> Self {
+        Conway::new(value >= 128)
+    }
+}
+
+/// Where the initial pattern comes from.
+pub enum Seed<'a> {
+    /// A blinker in the middle of a small torus.
+    Blinker,
+    /// An image with one pixel per cell.
+    Image(&'a Path),
+    /// A pattern file in one of the formats of `pattern`.
+    Pattern(&'a Path),
+    /// Each cell is alive with the given probability.
+    Random { density: f64, seed: Option<u64> },
+}
+
+/// The rule and the space to run it in. Whatever is not given is taken from the pattern or the rule: the rule from the
+/// header of an RLE pattern, or else `B3/S23`; the tiling from the suffix of the rule, or else orthogonal and diagonal.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Automaton {
+    pub rule: Option<Rule>,
+    pub tiling: Option<Tiling>,
+    /// Use a `PatchTorus` instead of a `CellTorus`.
+    pub patched: bool,
+}
+
+/// The size of the torus and the position of the top left corner of the pattern. Whatever is not given is chosen to
+/// fit the pattern: the torus leaves a margin around the pattern, and the pattern is centered.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Placement {
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub x: Option<usize>,
+    pub y: Option<usize>,
+}
+
+/// Where and how to show the run.
+pub struct Output<'a> {
+    pub export_dir: Option<&'a PathBuf>,
+    /// Number of generations between exported images.
+    pub export_every: usize,
+    pub view: &'a View,
+    pub svg: &'a SvgOptions,
+    pub animation: &'a AnimationOptions,
+}
+
+/// Cells of empty space around a pattern, if the size of the torus is not given.
+const MARGIN: usize = 10;
+
+/// Size of the torus for a random start, if the size is not given.
+const RANDOM_SIZE: usize = 64;
+
+pub fn example(
+    seed: Seed,
+    automaton: Automaton,
+    placement: Placement,
+    generations: usize,
+    output: &Output,
+) -> Result<()> {
+    let pattern = match seed {
+        Seed::Blinker => Pattern {
+            name: Some("blinker".to_string()),
+            width: 1,
+            height: 3,
+            alive: [(0, 0), (0, 1), (0, 2)].into_iter().collect(),
+            ..Default::default()
+        },
+        Seed::Pattern(path) => Pattern::load(path)?,
+        Seed::Image(_) | Seed::Random { .. } => Pattern::default(),
+    };
+    let rule = match (automaton.rule, pattern.rule.as_deref()) {
+        (Some(rule), _) => rule,
+        (None, Some(text)) => text.parse()?,
+        (None, None) => Rule::default(),
+    };
+    let tiling = automaton
+        .tiling
+        .or(rule.neighbourhood())
+        .unwrap_or(Tiling::OrthogonalAndDiagonal);
+    if let Some(neighbourhood) = rule.neighbourhood()
+        && neighbourhood != tiling
+    {
+        warn!("Rule [{rule}] was written for tiling [{neighbourhood:?}]");
+    }
+    info!("Rule: [{rule}]: tiling: [{tiling:?}]");
+
+    let picture = match seed {
+        Seed::Image(path) => Some(Picture::open(path)?),
+        _ => None,
+    };
+    let even = |n: usize| n.div_ceil(2) * 2;
+    let (width, height) = match (&picture, &seed) {
+        (Some(picture), _) => (picture.width(), picture.height()),
+        (None, Seed::Blinker) => (
+            placement.width.unwrap_or(even(5)),
+            placement.height.unwrap_or(even(5)),
+        ),
+        (None, Seed::Random { .. }) => (
+            placement.width.unwrap_or(RANDOM_SIZE),
+            placement.height.unwrap_or(RANDOM_SIZE),
+        ),
+        (None, _) => (
+            placement.width.unwrap_or(even(pattern.width + 2 * MARGIN)),
+            placement
+                .height
+                .unwrap_or(even(pattern.height + 2 * MARGIN)),
+        ),
+    };
+    if pattern.width > width || pattern.height > height {
+        return Err(anyhow!(
+            "Pattern ({} x {}) does not fit on the torus ({width} x {height})",
+            pattern.width,
+            pattern.height
+        ));
+    }
+    let alive = match seed {
+        Seed::Random { density, seed } => {
+            if !(0.0..=1.0).contains(&density) {
+                return Err(anyhow!("Density should be between 0 and 1: [{density}]"));
+            }
+            let seed = seed.unwrap_or_else(rand::random);
+            info!("Random start: density: [{density}]: seed: [{seed}]");
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut alive = HashSet::new();
+            for y in 0..height {
+                for x in 0..width {
+                    if rng.random_bool(density) {
+                        alive.insert((x, y));
+                    }
+                }
+            }
+            alive
+        }
+        _ => {
+            let left = placement.x.unwrap_or((width - pattern.width) / 2);
+            let top = placement.y.unwrap_or((height - pattern.height) / 2);
+            pattern
+                .alive
+                .iter()
+                .map(|(x, y)| ((x + left) % width, (y + top) % height))
+                .collect::<HashSet<_>>()
+        }
+    };
+
     let generation = 0usize;
-    let mut torus = new_cell_torus(
-        Tiling::OrthogonalAndDiagonal,
-        &[width, height],
-        generation.clone(),
-        |v: &[usize]| Conway::new(v[1] == 2 && (v[0] >= 1 && v[0] <= 3)),
-    )?;
-    torus.info(&generation);
-    torus.update_all(&0usize)?;
-    let generation = generation.successor();
-    torus.info(&generation);
+    if automaton.patched {
+        let mut torus = new_patch_torus::<_, _, CsrEffectors>(
+            tiling,
+            Totalistic::new(rule, false),
+            generation,
+            width,
+            height,
+        )?;
+        for (x, y) in alive {
+            torus.adjust(&generation, x, y, Totalistic::new(rule, true))?;
+        }
+        run(torus, picture.as_ref(), &rule, generations, output)
+    } else {
+        let torus = new_cell_torus(tiling, &[height, width], generation, |v: &[usize]| {
+            Totalistic::new(rule, alive.contains(&(v[1], v[0])))
+        })?;
+        run(torus, picture.as_ref(), &rule, generations, output)
+    }
+}
+
+fn run<T>(
+    torus: T,
+    picture: Option<&Picture>,
+    rule: &Rule,
+    generations: usize,
+    output: &Output,
+) -> Result<()>
+where
+    T: Torus<Totalistic, usize> + GrayScaleTorus<Totalistic, usize>,
+    <T::Spc as Space<Totalistic, usize>>::Loc: Clone + PartialEq,
+{
+    let mut torus = torus;
+    let generation = 0usize;
+    if let Some(picture) = picture {
+        picture.initialize(&mut torus, &generation, rule)?;
+    }
+    let summary = |torus: &T, generation: &usize, _step: usize| {
+        torus.info(generation);
+        report(torus, generation);
+        Ok(())
+    };
+    let export = |torus: &T, generation: &usize, _step: usize| {
+        torus.export(generation, &(), output.view, output.export_dir)?;
+        export_svg(torus, generation, output.svg, fill)
+    };
+    let frame = |torus: &T, generation: &usize, animation: &mut Animation| {
+        let img = torus.render(generation, &(), output.view)?;
+        animation.add_frame(&img, &generation.to_string())
+    };
+    let mut simulation = Simulation::<T, Totalistic, usize>::new(torus, generation)
+        .observe(Cadence::start(), summary)
+        .observe(Cadence::every(output.export_every), export)
+        .observe(
+            Cadence::every(1),
+            Recorder::new(Animation::create(output.animation)?, frame),
+        )
+        .observe(Cadence::end(), summary);
+    simulation.run(generations)?;
+    simulation.finish()?;
     Ok(())
 }
+
+fn fill(state: Option<Totalistic>) -> [u8; 3] {
+    let gray = state.map(|s| s.gray_value(&())).unwrap_or(128);
+    [gray; 3]
+}
+
+/// Logs the population and the bounding box of the live cells, so that runs of well-known patterns can be checked.
+fn report<T: Torus<Totalistic, usize>>(torus: &T, generation: &usize) {
+    let alive = torus
+        .space()
+        .reduce(generation, Vec::new(), |region, location, mut alive| {
+            if let Some(state) = region.state(location) as Option<Totalistic>
+                && state.is_alive()
+            {
+                alive.push(torus.coordinates(region, location));
+            }
+            alive
+        });
+    let bounds = alive.iter().fold(None, |bounds, &(x, y)| match bounds {
+        None => Some((x, y, x, y)),
+        Some((x0, y0, x1, y1)) => Some((x0.min(x), y0.min(y), x1.max(x), y1.max(y))),
+    });
+    match bounds {
+        Some((x0, y0, x1, y1)) => info!(
+            "Generation: [{generation}]: population: {}: bounding box: ({x0}, {y0}) - ({x1}, {y1})",
+            alive.len()
+        ),
+        None => info!("Generation: [{generation}]: population: 0"),
+    }
+}
@@ -1,7 +1,9 @@
 use crate::{
-    cell::{Cell, new_cell_torus},
-    structure::{Generation, Location, Region, Space, State},
-    torus::{Tiling, Torus},
+    cell::{Cell, CellTorus, LinkChanges, new_cell_torus},
+    palette::{ColorMap, Cyclic},
+    simulation::{Cadence, Simulation},
+    structure::{Location, Region, Rgb, Space, State, UpdateContext},
+    torus::{ColorTorus, Tiling, Torus, render::View},
 };
 use anyhow::Result;
 // use log::debug;
@@ -10,9 +12,10 @@ use log::{debug, trace};
 use std::{
     f64::consts::PI,
     fmt::{Display, Write},
+    path::PathBuf,
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct Rotate {
     pub angle: f64,
 }
@@ -24,10 +27,13 @@ impl Rotate {
 }
 
 impl State<usize> for Rotate {
+    type Parameters = ();
+
     fn update<Spc: Space<Self, usize>>(
         space: &Spc,
         region: &Spc::Reg,
         location: &Spc::Loc,
+        _context: &UpdateContext<(), usize>,
     ) -> Result<Self> {
         trace!("Update: [{}]", location.id(space));
         let this_state = (region.state(location) as Option<Self>)
@@ -49,6 +55,14 @@ impl State<usize> for Rotate {
     }
 }
 
+impl Rgb for Rotate {
+    type Context = ();
+
+    fn rgb_value(&self, _context: &()) -> [u8; 3] {
+        Cyclic.color(self.angle)
+    }
+}
+
 impl Display for Rotate {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let a = normalize(self.angle);
@@ -88,21 +102,29 @@ fn symmetric(angle: f64) -> f64 {
     if norm <= PI { norm } else { norm - (2.0 * PI) }
 }
 
-pub fn example() -> Result<()> {
-    let cell = Cell::new(0usize, Rotate::new(0.0));
+pub fn example(export_dir: Option<&PathBuf>, view: &View) -> Result<()> {
+    let cell = Cell::new(0usize, Rotate::new(0.0), 0, &LinkChanges::default());
     debug!("Bare cell: [{:?}]", cell);
     let dimensions = [5, 5, 5];
     let generation = 0usize;
-    let mut torus = new_cell_torus(
+    let torus = new_cell_torus(
         Tiling::Orthogonal,
         &dimensions,
-        generation.clone(),
+        generation,
         |v: &[usize]| Rotate::new(experiment_init(v, &dimensions)),
     )?;
-    torus.info(&generation);
-    torus.update_all(&0usize)?;
-    let generation = generation.successor();
-    torus.info(&generation);
+    // The torus is three-dimensional, so it cannot be drawn as a whole.
+    let view = match view {
+        View::Plane => &View::Projection { axis: 2 },
+        view => view,
+    };
+    let show = |torus: &CellTorus<Rotate, usize>, generation: &usize, _step: usize| {
+        torus.info(generation);
+        torus.export_color(generation, &(), view, export_dir)
+    };
+    let mut simulation = Simulation::new(torus, generation).observe(Cadence::every(1), show);
+    simulation.run(1)?;
+    simulation.finish()?;
     Ok(())
 }
 
@@ -0,0 +1,447 @@
+//! # Ising model
+//!
+//! Each cell holds a spin, up or down. The energy of a configuration is
+//!
+//! ```text
+//! E = -J Σ w s s' - h Σ s
+//! ```
+//!
+//! where the first sum runs over all pairs of a cell and one of its effectors, with the weight `w` of their link, and
+//! `h` is an external field. At temperature `T` a spin flips according to the Metropolis rule, with probability
+//! `min(1, exp(-ΔE / T))`, or it is drawn from the heat bath of its effectors, up with probability
+//! `1 / (1 + exp(-2 H / T))`, where `H` is the local field.
+//!
+//! A spin must not flip at the same time as one of its effectors, or both see a stale neighbour and the chain no longer
+//! samples the Boltzmann distribution. The cells are therefore divided into sublattices, such that no cell has an
+//! effector in its own sublattice, and each generation updates a single sublattice. A sweep over all spins takes as
+//! many generations as there are sublattices.
+//!
+//! The `ising` command sweeps the temperature on each tiling and estimates the critical temperature from the crossing
+//! of the Binder cumulants of two tori of different sizes. The exact values are known for the square, the triangular and the honeycomb lattice, and a
+//! numerical value for the square lattice with equal couplings to the diagonal neighbours.
+
+use std::{
+    f64::consts::SQRT_2,
+    fmt::{Display, Write},
+};
+
+use anyhow::{Error, Result, anyhow};
+use clap::ValueEnum;
+use log::info;
+
+use crate::{
+    cell::{CellTorus, new_cell_torus},
+    simulation::{Cadence, Simulation},
+    structure::{GrayScale, Location, Region, Space, State, UpdateContext},
+    torus::{Tiling, Torus},
+};
+
+/// How a spin chooses its next value.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+#[non_exhaustive]
+pub enum Dynamics {
+    /// Flip if that lowers the energy, and otherwise with probability `exp(-ΔE / T)`.
+    #[default]
+    Metropolis,
+    /// Choose up or down with the Boltzmann probabilities of the local field, regardless of the current spin.
+    HeatBath,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Parameters {
+    pub temperature: f64,
+    /// The coupling `J` between a spin and its effectors. Positive couplings favour aligned spins.
+    pub coupling: f64,
+    /// The external field `h`.
+    pub field: f64,
+    pub dynamics: Dynamics,
+    /// The number of sublattices. Generation `g` updates the sublattice `g % sublattices`.
+    pub sublattices: u8,
+}
+
+impl Parameters {
+    pub fn new(temperature: f64, dynamics: Dynamics, tiling: Tiling) -> Parameters {
+        Parameters {
+            temperature,
+            coupling: 1.0,
+            field: 0.0,
+            dynamics,
+            sublattices: sublattice_count(tiling),
+        }
+    }
+}
+
+impl Default for Parameters {
+    fn default() -> Parameters {
+        Parameters::new(
+            critical_temperature(Tiling::Orthogonal).unwrap_or(1.0),
+            Dynamics::Metropolis,
+            Tiling::Orthogonal,
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ising {
+    /// Plus or minus one.
+    pub spin: i8,
+    pub sublattice: u8,
+}
+
+impl Ising {
+    pub fn new(up: bool, sublattice: u8) -> Ising {
+        Ising {
+            spin: if up { 1 } else { -1 },
+            sublattice,
+        }
+    }
+
+    /// A spin at the given co-ordinates of a torus with the given tiling.
+    pub fn at(tiling: Tiling, x: usize, y: usize, up: bool) -> Ising {
+        Ising::new(up, sublattice(tiling, x, y))
+    }
+}
+
+impl State<usize> for Ising {
+    type Parameters = Parameters;
+
+    fn update<Spc: Space<Self, usize>>(
+        space: &Spc,
+        region: &Spc::Reg,
+        location: &Spc::Loc,
+        context: &UpdateContext<Parameters, usize>,
+    ) -> Result<Self> {
+        let this_state = (region.state(location) as Option<Self>)
+            .ok_or_else(|| anyhow!("Cell without state: [{}]", location.id(space)))?;
+        let parameters = context.parameters();
+        let active = context.generation() % parameters.sublattices.max(1) as usize;
+        if this_state.sublattice as usize != active {
+            return Ok(this_state);
+        }
+        let mut sum = 0.0;
+        for (effector, weight) in location.weighted_effectors(space)? {
+            if let Some(state) = region.state(&effector) as Option<Self> {
+                sum += weight * state.spin as f64;
+            }
+        }
+        let local_field = parameters.coupling * sum + parameters.field;
+        let spin = this_state.spin as f64;
+        let up = match parameters.dynamics {
+            Dynamics::Metropolis => {
+                let delta = 2.0 * spin * local_field;
+                let flip =
+                    delta <= 0.0 || context.uniform() < (-delta / parameters.temperature).exp();
+                (this_state.spin > 0) != flip
+            }
+            Dynamics::HeatBath => {
+                let p_up = 1.0 / (1.0 + (-2.0 * local_field / parameters.temperature).exp());
+                context.uniform() < p_up
+            }
+        };
+        Ok(Ising::new(up, this_state.sublattice))
+    }
+}
+
+impl Display for Ising {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_char(if self.spin > 0 { '#' } else { '.' })
+    }
+}
+
+impl GrayScale for Ising {
+    type Context = ();
+
+    fn gray_value(&self, _context: &()) -> u8 {
+        if self.spin > 0 { 255 } else { 0 }
+    }
+}
+
+/// The number of sublattices that `sublattice` uses for the given tiling.
+pub fn sublattice_count(tiling: Tiling) -> u8 {
+    match tiling {
+        Tiling::Orthogonal | Tiling::AdjacentTriangles => 2,
+        Tiling::Hexagons => 3,
+        Tiling::OrthogonalAndDiagonal => 4,
+        Tiling::TouchingTriangles => 6,
+    }
+}
+
+/// A sublattice such that no cell has an effector in its own sublattice. For hexagons and touching triangles the
+/// width of the torus must be a multiple of three; see `check_sublattices`.
+pub fn sublattice(tiling: Tiling, x: usize, y: usize) -> u8 {
+    match tiling {
+        // A checkerboard; for triangles the up and the down triangles.
+        Tiling::Orthogonal | Tiling::AdjacentTriangles => ((x + y) % 2) as u8,
+        // Odd rows are shifted half a cell to the left. In axial co-ordinates (x - ⌈y / 2⌉, y) the effectors
+        // differ by (±1, 0), (0, ±1) or ±(1, -1), so the difference of the co-ordinates modulo three changes.
+        Tiling::Hexagons => ((x + 2 * y - y.div_ceil(2)) % 3) as u8,
+        Tiling::OrthogonalAndDiagonal => (x % 2 + 2 * (y % 2)) as u8,
+        // Effectors are at most two cells away in the same row, or in the next or the previous row.
+        Tiling::TouchingTriangles => (x % 3 + 3 * (y % 2)) as u8,
+    }
+}
+
+/// Checks that no cell of the torus has an effector in its own sublattice.
+pub fn check_sublattices<T: Torus<Ising, usize>>(torus: &T, generation: &usize) -> Result<()> {
+    let space = torus.space();
+    space.reduce(generation, Ok(()), |region, location, result| {
+        result?;
+        let this_state = (region.state(location) as Option<Ising>)
+            .ok_or_else(|| anyhow!("Cell without state: [{}]", location.id(space)))?;
+        for effector in location.effectors(space)? {
+            if let Some(state) = region.state(&effector) as Option<Ising>
+                && state.sublattice == this_state.sublattice
+            {
+                return Err(anyhow!(
+                    "Effector in the same sublattice: [{}]: [{}]: the width of the torus must be a multiple of the period of the sublattices",
+                    location.id(space),
+                    effector.id(space)
+                ));
+            }
+        }
+        Ok(())
+    })
+}
+
+/// The magnetization per spin, between minus one and one.
+pub fn magnetization<T: Torus<Ising, usize>>(torus: &T, generation: &usize) -> f64 {
+    let (sum, count) = torus.space().reduce(
+        generation,
+        (0i64, 0usize),
+        |region, location, (sum, count)| match region.state(location) as Option<Ising> {
+            Some(state) => (sum + state.spin as i64, count + 1),
+            None => (sum, count),
+        },
+    );
+    sum as f64 / count.max(1) as f64
+}
+
+/// The energy per spin. Each link is counted once, although both ends have the other as an effector.
+pub fn energy<T: Torus<Ising, usize>>(
+    torus: &T,
+    generation: &usize,
+    parameters: &Parameters,
+) -> Result<f64> {
+    let space = torus.space();
+    let (energy, count) = space.reduce(
+        generation,
+        Ok::<_, Error>((0.0, 0usize)),
+        |region, location, accumulator| {
+            let (energy, count) = accumulator?;
+            let Some(state) = region.state(location) as Option<Ising> else {
+                return Ok((energy, count));
+            };
+            let spin = state.spin as f64;
+            let mut sum = 0.0;
+            for (effector, weight) in location.weighted_effectors(space)? {
+                let effector = effector.canonical(space);
+                if let Some(other) = space.state(generation, &effector) as Option<Ising> {
+                    sum += weight * other.spin as f64;
+                }
+            }
+            let energy = energy - 0.5 * parameters.coupling * spin * sum - parameters.field * spin;
+            Ok((energy, count + 1))
+        },
+    )?;
+    Ok(energy / count.max(1) as f64)
+}
+
+/// The critical temperature of the Ising model without field and with unit coupling, if it is known.
+pub fn critical_temperature(tiling: Tiling) -> Option<f64> {
+    match tiling {
+        // The square lattice (Onsager).
+        Tiling::Orthogonal => Some(2.0 / (1.0 + SQRT_2).ln()),
+        // The triangular lattice.
+        Tiling::Hexagons => Some(4.0 / 3f64.ln()),
+        // The honeycomb lattice.
+        Tiling::AdjacentTriangles => Some(2.0 / (2.0 + 3f64.sqrt()).ln()),
+        // The square lattice with equal couplings to the diagonal neighbours (series expansions and Monte Carlo).
+        Tiling::OrthogonalAndDiagonal => Some(5.26),
+        Tiling::TouchingTriangles => None,
+    }
+}
+
+/// The number of effectors of a cell, which is also the critical temperature in the mean-field approximation.
+fn coordination(tiling: Tiling) -> usize {
+    match tiling {
+        Tiling::AdjacentTriangles => 3,
+        Tiling::Orthogonal => 4,
+        Tiling::Hexagons => 6,
+        Tiling::OrthogonalAndDiagonal => 8,
+        Tiling::TouchingTriangles => 12,
+    }
+}
+
+/// The parameters of a temperature sweep.
+pub(crate) struct Sweep<'a> {
+    pub tilings: &'a [Tiling],
+    /// The temperatures to visit, from high to low. If empty, a range below the mean-field critical temperature of
+    /// each tiling.
+    pub temperatures: &'a [f64],
+    pub dynamics: Dynamics,
+    /// Width and height of the larger torus. The smaller torus is half as wide and high.
+    pub size: usize,
+    /// The number of sweeps to reach equilibrium at each temperature.
+    pub equilibrate: usize,
+    /// The number of sweeps to measure at each temperature.
+    pub measure: usize,
+    pub seed: u64,
+}
+
+/// Averages over the measured sweeps at one temperature.
+#[derive(Clone, Copy, Debug)]
+struct Averages {
+    temperature: f64,
+    magnetization: f64,
+    energy: f64,
+    susceptibility: f64,
+    specific_heat: f64,
+    /// The Binder cumulant `1 - <m⁴> / 3 <m²>²`: two thirds in the ordered phase and zero in the disordered phase.
+    /// At the critical temperature it hardly depends on the size of the torus.
+    binder: f64,
+}
+
+/// The number of temperatures of each pass of a default sweep.
+const DEFAULT_STEPS: usize = 24;
+
+/// Estimates the critical temperature of each tiling from the temperature where the Binder cumulants of a large and a
+/// small torus cross. A default sweep makes a second, finer pass around the crossing of the first pass.
+pub(crate) fn example(sweep: &Sweep) -> Result<()> {
+    if !sweep.size.is_multiple_of(4) {
+        return Err(anyhow!(
+            "The size must be a multiple of four, so that both tori have even dimensions: [{}]",
+            sweep.size
+        ));
+    }
+    let mut summary = Vec::new();
+    for &tiling in sweep.tilings {
+        let estimate = if sweep.temperatures.is_empty() {
+            let mean_field = coordination(tiling) as f64;
+            let coarse = range(0.9 * mean_field, 0.2 * mean_field);
+            let step = coarse[0] - coarse[1];
+            visit(tiling, &coarse, sweep)?
+                .map(|t| visit(tiling, &range(t + 2.0 * step, t - 2.0 * step), sweep))
+                .transpose()?
+                .flatten()
+        } else {
+            visit(tiling, sweep.temperatures, sweep)?
+        };
+        let Some(estimate) = estimate else {
+            info!("Critical temperature: [{tiling:?}]: the Binder cumulants do not cross");
+            continue;
+        };
+        match critical_temperature(tiling) {
+            Some(known) => info!(
+                "Critical temperature: [{tiling:?}]: estimate: {estimate:.3}: known: {known:.3}: deviation: {:.1}%",
+                100.0 * (estimate - known) / known
+            ),
+            None => info!("Critical temperature: [{tiling:?}]: estimate: {estimate:.3}: not known"),
+        }
+        summary.push((tiling, estimate));
+    }
+    for (tiling, estimate) in summary {
+        info!("Summary: [{tiling:?}]: {estimate:.3}");
+    }
+    Ok(())
+}
+
+/// Evenly spaced temperatures, from high to low.
+fn range(high: f64, low: f64) -> Vec<f64> {
+    (0..DEFAULT_STEPS)
+        .map(|i| high - (high - low) * i as f64 / (DEFAULT_STEPS - 1) as f64)
+        .collect()
+}
+
+/// Runs both tori at each temperature and returns the crossing of their Binder cumulants.
+fn visit(tiling: Tiling, temperatures: &[f64], sweep: &Sweep) -> Result<Option<f64>> {
+    let mut results = Vec::with_capacity(temperatures.len());
+    for &temperature in temperatures {
+        let large = run(tiling, sweep.size, temperature, sweep)?;
+        let small = run(tiling, sweep.size / 2, temperature, sweep)?;
+        info!(
+            "Tiling: [{tiling:?}]: T: {:.3}: |m|: {:.4}: e: {:.4}: χ: {:.3}: C: {:.3}: U: {:.4}: U (small): {:.4}",
+            large.temperature,
+            large.magnetization,
+            large.energy,
+            large.susceptibility,
+            large.specific_heat,
+            large.binder,
+            small.binder
+        );
+        results.push((large, small));
+    }
+    Ok(crossing(&results))
+}
+
+/// Starts with all spins up and measures the averages after the torus reached equilibrium.
+fn run(tiling: Tiling, size: usize, temperature: f64, sweep: &Sweep) -> Result<Averages> {
+    let torus = new_cell_torus(tiling, &[size, size], 0usize, |v: &[usize]| {
+        Ising::at(tiling, v[1], v[0], true)
+    })?;
+    check_sublattices(&torus, &0)?;
+    let parameters = Parameters::new(temperature, sweep.dynamics, tiling);
+    let generations_per_sweep = parameters.sublattices as usize;
+    let start = sweep.equilibrate * generations_per_sweep;
+    let mut samples = Vec::with_capacity(sweep.measure);
+    let record = |torus: &CellTorus<Ising, usize>, generation: &usize, step: usize| {
+        if step > start {
+            samples.push((
+                magnetization(torus, generation),
+                energy(torus, generation, &parameters)?,
+            ));
+        }
+        Ok(())
+    };
+    let mut simulation = Simulation::new(torus, 0usize)
+        .with_parameters(parameters)
+        .with_seed(sweep.seed)
+        .observe(Cadence::every(generations_per_sweep).skip_start(), record);
+    simulation.run((sweep.equilibrate + sweep.measure) * generations_per_sweep)?;
+    simulation.finish()?;
+
+    let n = samples.len().max(1) as f64;
+    let cells = (size * size) as f64;
+    let mean = |f: &dyn Fn(f64, f64) -> f64| samples.iter().map(|&(m, e)| f(m, e)).sum::<f64>() / n;
+    let m = mean(&|m, _| m.abs());
+    let m2 = mean(&|m, _| m * m);
+    let m4 = mean(&|m, _| m.powi(4));
+    let e = mean(&|_, e| e);
+    let e2 = mean(&|_, e| e * e);
+    Ok(Averages {
+        temperature,
+        magnetization: m,
+        energy: e,
+        susceptibility: cells * (m2 - m * m) / temperature,
+        specific_heat: cells * (e2 - e * e) / (temperature * temperature),
+        binder: if m2 > 0.0 {
+            1.0 - m4 / (3.0 * m2 * m2)
+        } else {
+            0.0
+        },
+    })
+}
+
+/// The value of the Binder cumulant at the critical temperature, which is the same for all two-dimensional lattices.
+const CRITICAL_BINDER: f64 = 0.61;
+
+/// The temperature where the Binder cumulants of the large and the small torus cross, interpolated linearly between
+/// the temperatures of the sweep. Noise makes the cumulants cross far from the critical temperature as well, so the
+/// crossing closest to the critical value of the cumulant wins.
+fn crossing(results: &[(Averages, Averages)]) -> Option<f64> {
+    results
+        .windows(2)
+        .filter_map(|pair| {
+            let (a, b) = (&pair[0], &pair[1]);
+            let da = a.0.binder - a.1.binder;
+            let db = b.0.binder - b.1.binder;
+            if da.signum() == db.signum() {
+                return None;
+            }
+            let f = da / (da - db);
+            let t = a.0.temperature + (b.0.temperature - a.0.temperature) * f;
+            let binder = a.0.binder + (b.0.binder - a.0.binder) * f;
+            Some((t, (binder - CRITICAL_BINDER).abs()))
+        })
+        .min_by(|(_, x), (_, y)| x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(t, _)| t)
+}
@@ -0,0 +1,449 @@
+//! Lenia: a cellular automaton with continuous states, a continuous rule, and a large neighbourhood.
+//!
+//! Each cell holds a value between zero and one. The update takes a weighted average of the values in the
+//! neighbourhood of a cell, the *potential*, and maps it through a growth function onto a change between minus one and
+//! one. A small step of that change is added to the value of the cell:
+//!
+//! ```text
+//! U = Σ K(d) A / Σ K(d)
+//! A' = clip(A + dt G(U), 0, 1)
+//! ```
+//!
+//! The neighbourhood consists of the cells that can be reached from the cell in at most `radius` steps from a cell to
+//! one of its effectors. The weight `K(d)` of a cell at `d` steps is given by a radial kernel: one or more concentric
+//! rings, each with the shape of the kernel core and its own peak. The center of the neighbourhood has no weight.
+//! Because the distances are counted in steps, the neighbourhood has the shape of the tiling: a hexagon for hexagons,
+//! a square for the Moore neighbourhood and a diamond for the von Neumann neighbourhood.
+//! See <https://chakazul.github.io/lenia.html>.
+
+use std::{
+    cell::RefCell,
+    f64::consts::PI,
+    fmt::{Display, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use log::{debug, info, trace};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    cell::{CellTorus, new_cell_torus},
+    simulation::{Cadence, Simulation},
+    structure::{Generation, GrayScale, Location, Region, Space, State, UpdateContext},
+    torus::{GrayScaleTorus, Tiling, Torus, geometry::Geometry, render::View},
+};
+
+/// The largest number of rings of a kernel.
+pub const MAX_RINGS: usize = 4;
+
+/// The shape of the core of a kernel ring, and of the bump of the growth function.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+#[non_exhaustive]
+pub enum Shape {
+    /// A smooth bump: `exp(4 - 1 / (r (1 - r)))` for rings, a Gaussian for growth.
+    Exponential,
+    /// A polynomial bump: `(4 r (1 - r))^4` for rings, `(1 - (u - μ)² / 9σ²)^4` for growth.
+    Polynomial,
+    /// A step: the middle half of a ring, and growth within `σ` of `μ`.
+    Rectangular,
+}
+
+impl Shape {
+    /// The value of the kernel core at the relative distance `r` within a ring, between zero and one.
+    fn core(&self, r: f64) -> f64 {
+        if r <= 0.0 || r >= 1.0 {
+            return 0.0;
+        }
+        match self {
+            Shape::Exponential => (4.0 - 1.0 / (r * (1.0 - r))).exp(),
+            Shape::Polynomial => (4.0 * r * (1.0 - r)).powi(4),
+            Shape::Rectangular => {
+                if (0.25..=0.75).contains(&r) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    /// The growth for the potential `u`, between minus one and one.
+    fn growth(&self, u: f64, mu: f64, sigma: f64) -> f64 {
+        let bump = match self {
+            Shape::Exponential => (-(u - mu).powi(2) / (2.0 * sigma * sigma)).exp(),
+            Shape::Polynomial => (1.0 - (u - mu).powi(2) / (9.0 * sigma * sigma))
+                .max(0.0)
+                .powi(4),
+            Shape::Rectangular => {
+                if (u - mu).abs() <= sigma {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        };
+        2.0 * bump - 1.0
+    }
+}
+
+/// The rule of a Lenia automaton. The rule is the same for all cells, so it is a parameter of the run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Parameters {
+    /// The number of steps from a cell to the edge of its neighbourhood.
+    pub radius: usize,
+    pub kernel: Shape,
+    /// The peak of each ring of the kernel, from the inside out.
+    peaks: [f64; MAX_RINGS],
+    rings: usize,
+    pub growth: Shape,
+    /// The potential with the most growth.
+    pub mu: f64,
+    /// The width of the growth bump.
+    pub sigma: f64,
+    /// The size of the time step.
+    pub dt: f64,
+}
+
+impl Parameters {
+    pub fn new(radius: usize, mu: f64, sigma: f64, dt: f64) -> Parameters {
+        Parameters {
+            radius,
+            kernel: Shape::Exponential,
+            peaks: [1.0; MAX_RINGS],
+            rings: 1,
+            growth: Shape::Exponential,
+            mu,
+            sigma,
+            dt,
+        }
+    }
+
+    pub fn with_shapes(self, kernel: Shape, growth: Shape) -> Parameters {
+        Parameters {
+            kernel,
+            growth,
+            ..self
+        }
+    }
+
+    pub fn with_peaks(self, peaks: &[f64]) -> Result<Parameters> {
+        if peaks.is_empty() || peaks.len() > MAX_RINGS {
+            return Err(anyhow!(
+                "Kernel should have between one and {MAX_RINGS} rings: {peaks:?}"
+            ));
+        }
+        let mut result = self;
+        result.peaks[..peaks.len()].copy_from_slice(peaks);
+        result.rings = peaks.len();
+        Ok(result)
+    }
+
+    /// The weight of a cell at the given number of steps. Each step is weighed at its middle, so that the first and
+    /// the last step of the neighbourhood get a weight, too.
+    pub fn weight(&self, steps: usize) -> f64 {
+        if steps == 0 || steps > self.radius {
+            return 0.0;
+        }
+        let r = (steps as f64 - 0.5) / self.radius as f64;
+        let position = r * self.rings as f64;
+        let ring = (position.floor() as usize).min(self.rings - 1);
+        self.peaks[ring] * self.kernel.core(position - ring as f64)
+    }
+}
+
+/// The defaults of the `lenia` command, with the smallest `mu` and `sigma` that it searches.
+impl Default for Parameters {
+    fn default() -> Parameters {
+        Parameters::new(4, DEFAULT_MU[0], DEFAULT_SIGMA[0], 0.1)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lenia {
+    pub value: f64,
+}
+
+impl Lenia {
+    pub fn new(value: f64) -> Lenia {
+        Lenia { value }
+    }
+}
+
+impl<Gen: Generation> State<Gen> for Lenia {
+    type Parameters = Parameters;
+
+    fn update<Spc: Space<Self, Gen>>(
+        space: &Spc,
+        region: &Spc::Reg,
+        location: &Spc::Loc,
+        context: &UpdateContext<Parameters, Gen>,
+    ) -> Result<Self> {
+        trace!("Update: [{}]", location.id(space));
+        let this_state = (region.state(location) as Option<Self>)
+            .ok_or_else(|| anyhow!("Cell without state: [{}]", location.id(space)))?;
+        let parameters = context.parameters();
+
+        let mut potential = 0.0;
+        let mut total = 0.0;
+        for (neighbour, steps) in location.neighbourhood(space, parameters.radius)?.iter() {
+            if let Some(state) = space.state(context.generation(), neighbour) as Option<Self> {
+                let weight = parameters.weight(*steps);
+                potential += weight * state.value;
+                total += weight;
+            }
+        }
+        let potential = if total > 0.0 { potential / total } else { 0.0 };
+        let growth = parameters
+            .growth
+            .growth(potential, parameters.mu, parameters.sigma);
+        let value = (this_state.value + parameters.dt * growth).clamp(0.0, 1.0);
+        Ok(Lenia { value })
+    }
+}
+
+impl Display for Lenia {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const SHADES: [char; 5] = [' ', '.', ':', '+', '#'];
+        let index = (self.value * (SHADES.len() - 1) as f64).round() as usize;
+        f.write_char(SHADES[index.min(SHADES.len() - 1)])
+    }
+}
+
+impl GrayScale for Lenia {
+    type Context = ();
+
+    fn gray_value(&self, _context: &()) -> u8 {
+        (self.value * 255.0).round() as u8
+    }
+}
+
+/// The tilings to search, if none are given.
+pub(crate) const DEFAULT_TILINGS: [Tiling; 2] = [Tiling::Hexagons, Tiling::OrthogonalAndDiagonal];
+
+/// The values of `mu` and `sigma` to search, if none are given.
+pub(crate) const DEFAULT_MU: [f64; 4] = [0.15, 0.2, 0.25, 0.3];
+pub(crate) const DEFAULT_SIGMA: [f64; 3] = [0.017, 0.025, 0.033];
+
+/// The parameters of a search for gliders. Each combination of a tiling, a value of `mu` and a value of `sigma` is
+/// run from the same random blob.
+pub(crate) struct Search<'a> {
+    pub tilings: &'a [Tiling],
+    pub mu: &'a [f64],
+    pub sigma: &'a [f64],
+    /// The rule, apart from `mu` and `sigma`.
+    pub parameters: Parameters,
+    pub size: usize,
+    pub generations: usize,
+    pub seed: Option<u64>,
+}
+
+/// What became of a blob.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    Vanished,
+    /// The soup grew to cover a large part of the torus.
+    Filled,
+    /// The mass kept changing.
+    Chaotic,
+    /// The mass settled, but it is spread over the torus, like a labyrinth or a number of separate blobs.
+    Scattered,
+    /// The mass settled in a single blob that stayed where it was.
+    Stationary,
+    /// The mass settled in a single blob that moved at the given speed, in cells per generation, and direction, in
+    /// degrees.
+    Glider {
+        speed: f64,
+        direction: f64,
+    },
+}
+
+/// The radius of the random soup, in units of the radius of the neighbourhood.
+const SOUP: f64 = 1.5;
+
+/// The fraction of the torus that counts as filled.
+const FILLED: f64 = 0.25;
+
+/// The largest ratio between the highest and the lowest mass in the second half of a run of a settled soup.
+const SETTLED: f64 = 1.25;
+
+/// The lowest concentration of a single blob.
+const CONCENTRATED: f64 = 0.8;
+
+/// The lowest speed of a glider, in cells per generation.
+const MOVING: f64 = 0.01;
+
+pub(crate) fn example(search: &Search, export_dir: Option<&PathBuf>) -> Result<()> {
+    if search.generations < 2 {
+        return Err(anyhow!(
+            "A search needs at least two generations to tell what became of a blob: [{}]",
+            search.generations
+        ));
+    }
+    let seed = search.seed.unwrap_or_else(rand::random);
+    info!(
+        "Search: size: [{}]: generations: [{}]: seed: [{seed}]: {:?}",
+        search.size, search.generations, search.parameters
+    );
+    let mut gliders = Vec::new();
+    for &tiling in search.tilings {
+        for &mu in search.mu {
+            for &sigma in search.sigma {
+                let parameters = Parameters {
+                    mu,
+                    sigma,
+                    ..search.parameters
+                };
+                let name = format!("{tiling:?}-mu-{mu}-sigma-{sigma}").to_lowercase();
+                let dir = export_dir.map(|d| d.join(&name));
+                let outcome = run(tiling, parameters, search, seed, dir.as_deref())?;
+                info!("Outcome: [{name}]: {outcome:?}");
+                if let Outcome::Glider { .. } = outcome {
+                    gliders.push((name, outcome));
+                }
+            }
+        }
+    }
+    info!("Found {} glider(s)", gliders.len());
+    for (name, outcome) in gliders {
+        info!("Glider: [{name}]: {outcome:?}");
+    }
+    Ok(())
+}
+
+/// Runs a random soup in the middle of the torus, and tells what became of it.
+fn run(
+    tiling: Tiling,
+    parameters: Parameters,
+    search: &Search,
+    seed: u64,
+    export_dir: Option<&Path>,
+) -> Result<Outcome> {
+    let size = search.size;
+    let geometry = Geometry::new(tiling, size, size);
+    let (pw, ph) = geometry.period();
+    let middle = (pw / 2.0, ph / 2.0);
+    let soup = SOUP * parameters.radius as f64;
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut values = vec![0.0; size * size];
+    for (i, value) in values.iter_mut().enumerate() {
+        let (dx, dy) = geometry.delta(middle, geometry.center(i % size, i / size));
+        if dx.hypot(dy) <= soup {
+            *value = rng.random::<f64>();
+        }
+    }
+    let torus = new_cell_torus(tiling, &[size, size], 0usize, |v: &[usize]| {
+        Lenia::new(values[v[0] * size + v[1]])
+    })?;
+
+    // The measurements of each generation after the first, with the distance that the center of mass travelled.
+    let history = RefCell::new(Vec::with_capacity(search.generations));
+    let mut center = measure(&torus, &geometry, &0).center;
+    let mut position = (0.0, 0.0);
+    let record = |torus: &CellTorus<Lenia, usize>, generation: &usize, _step: usize| {
+        let measurement = measure(torus, &geometry, generation);
+        debug!("Generation: [{generation}]: {measurement:?}");
+        let (dx, dy) = geometry.delta(center, measurement.center);
+        position = (position.0 + dx, position.1 + dy);
+        center = measurement.center;
+        history.borrow_mut().push((measurement, position));
+        Ok(())
+    };
+    let export_dir = export_dir.map(PathBuf::from);
+    let export = |torus: &CellTorus<Lenia, usize>, generation: &usize, _step: usize| {
+        torus.export(generation, &(), &View::Plane, export_dir.as_ref())
+    };
+    let mut simulation = Simulation::new(torus, 0usize)
+        .with_parameters(parameters)
+        .with_seed(seed)
+        .observe(Cadence::every(1).skip_start(), record)
+        .observe(Cadence::end(), export);
+    let vanished = simulation.run_until(search.generations, |_, _| {
+        history
+            .borrow()
+            .last()
+            .is_some_and(|(measurement, _)| measurement.mass < 0.5)
+    })?;
+    if vanished {
+        return Ok(Outcome::Vanished);
+    }
+    simulation.finish()?;
+
+    let history = history.into_inner();
+    let settled = &history[history.len() / 2..];
+    let (last, end) = settled[settled.len() - 1];
+    if last.covered > FILLED {
+        return Ok(Outcome::Filled);
+    }
+    let highest = settled.iter().map(|(m, _)| m.mass).fold(0.0, f64::max);
+    let lowest = settled
+        .iter()
+        .map(|(m, _)| m.mass)
+        .fold(f64::INFINITY, f64::min);
+    if highest > SETTLED * lowest {
+        return Ok(Outcome::Chaotic);
+    }
+    if settled.iter().any(|(m, _)| m.concentration < CONCENTRATED) {
+        return Ok(Outcome::Scattered);
+    }
+    let start = settled[0].1;
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let speed = dx.hypot(dy) / (settled.len() - 1) as f64;
+    if speed < MOVING {
+        return Ok(Outcome::Stationary);
+    }
+    let direction = (-dy).atan2(dx).to_degrees().rem_euclid(360.0);
+    Ok(Outcome::Glider { speed, direction })
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Measurement {
+    mass: f64,
+    /// The center of mass: the circular mean along each axis, so that a blob that wraps around the torus has its
+    /// center inside the blob.
+    center: (f64, f64),
+    /// The length of the mean of the circular mean, along the axis where it is shortest. Close to one for a single
+    /// small blob, and close to zero for mass that is spread evenly.
+    concentration: f64,
+    /// The fraction of the cells that are clearly alive.
+    covered: f64,
+}
+
+fn measure<T: Torus<Lenia, usize>>(
+    torus: &T,
+    geometry: &Geometry,
+    generation: &usize,
+) -> Measurement {
+    let (pw, ph) = geometry.period();
+    let (mass, sx, cx, sy, cy, covered) = torus.space().reduce(
+        generation,
+        (0.0, 0.0, 0.0, 0.0, 0.0, 0usize),
+        |region, location, (mass, sx, cx, sy, cy, covered)| {
+            let Some(state) = region.state(location) as Option<Lenia> else {
+                return (mass, sx, cx, sy, cy, covered);
+            };
+            let (x, y) = torus.coordinates(region, location);
+            let (px, py) = geometry.center(x, y);
+            let (ax, ay) = (2.0 * PI * px / pw, 2.0 * PI * py / ph);
+            let m = state.value;
+            (
+                mass + m,
+                sx + m * ax.sin(),
+                cx + m * ax.cos(),
+                sy + m * ay.sin(),
+                cy + m * ay.cos(),
+                covered + usize::from(m > 0.1),
+            )
+        },
+    );
+    let angle = |s: f64, c: f64| s.atan2(c).rem_euclid(2.0 * PI) / (2.0 * PI);
+    let (width, height) = geometry.size();
+    Measurement {
+        mass,
+        center: (angle(sx, cx) * pw, angle(sy, cy) * ph),
+        concentration: sx.hypot(cx).min(sy.hypot(cy)) / mass.max(f64::MIN_POSITIVE),
+        covered: covered as f64 / (width * height) as f64,
+    }
+}
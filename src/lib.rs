@@ -1,16 +1,42 @@
-mod cell;
+//! # Quantized interactions
+//!
+//! Cellular automata on tori, networks and other spaces. A rule is a `State` that computes its next value from the
+//! states of its effectors (see module `structure`). A space is either a `CellTorus`, a `PatchTorus` that divides the
+//! torus into patches that fit in the cache, or a `CellNetwork` with an arbitrary graph. Rules that update blocks of
+//! cells at once implement `BlockRule` (see module `block`), and rules that can run backwards in time implement
+//! `Reversible` (see module `reversible`). Tori can be exported as images, animations and SVG (see module `torus`).
+//!
+//! Most programs only need the prelude: `use quantized_interactions::prelude::*;`. See `examples/custom_state.rs`.
+
+mod analysis;
+mod bench;
+pub mod block;
+pub mod cell;
 mod conway;
 mod experiment;
-mod patch;
-mod structure;
-mod torus;
-mod wave;
+pub mod ising;
+pub mod lenia;
+mod network;
+pub mod palette;
+pub mod patch;
+pub mod pattern;
+pub mod prelude;
+pub mod reversible;
+pub mod simulation;
+pub mod structure;
+pub mod torus;
+pub mod totalistic;
+pub mod wave;
 
 use std::path::PathBuf;
 
 use anyhow::Result;
-use clap::{Parser, Subcommand, command};
+use clap::{Parser, Subcommand};
 use log::{debug, info};
+use patch::PatchSizeChoice;
+use torus::{
+    Tiling, animation::AnimationOptions, import::Picture, render::ViewOptions, svg::SvgOptions,
+};
 
 #[derive(Parser)]
 struct Cli {
@@ -20,11 +46,326 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    #[command(about = "Conway's game of life")]
-    Conway,
+    #[command(about = "measure the dimension and the metric that emerge from a tiling")]
+    Analyze {
+        #[arg(
+            help = "tiling to analyze (default: orthogonal, orthogonal-and-diagonal and hexagons)",
+            long,
+            value_enum
+        )]
+        tiling: Vec<Tiling>,
+
+        #[arg(
+            help = "use CellTorus instead of PatchTorus (always for triangles)",
+            required = false,
+            long
+        )]
+        cell_torus: bool,
+
+        #[arg(help = "width of torus (must be even)")]
+        size: usize,
+
+        #[arg(help = "height of torus (must be even)", required = false)]
+        height: Option<usize>,
+    },
+
+    #[command(about = "compare the throughput of the representations of a torus")]
+    Bench {
+        #[arg(help = "also measure CellTorus", required = false, long)]
+        cell_torus: bool,
+
+        #[arg(help = "number of generations", long, default_value_t = 100)]
+        generations: usize,
+
+        #[arg(help = "width of torus (must be even)")]
+        size: usize,
+
+        #[arg(help = "height of torus (must be even)", required = false)]
+        height: Option<usize>,
+    },
+
+    #[command(about = "run a gas of particles with a block rule, e.g., the billiard-ball model")]
+    Billiard {
+        #[arg(help = "block rule", long, value_enum, default_value_t = block::Preset::BilliardBall)]
+        rule: block::Preset,
+
+        #[arg(help = "tiling", long, value_enum, default_value_t = Tiling::Orthogonal)]
+        tiling: Tiling,
+
+        #[arg(help = "use PatchTorus instead of CellTorus", long)]
+        patch_torus: bool,
+
+        #[arg(
+            help = "width and height of torus (a multiple of six suits all tilings)",
+            long,
+            default_value_t = 96
+        )]
+        size: usize,
+
+        #[arg(
+            help = "probability of a particle in the middle of the torus",
+            long,
+            default_value_t = 0.5
+        )]
+        density: f64,
+
+        #[arg(help = "seed for the random start", long, default_value_t = 0)]
+        seed: u64,
+
+        #[arg(help = "number of generations", long, default_value_t = 200)]
+        generations: usize,
+
+        #[arg(help = "directory to export image-files", long)]
+        export_dir: Option<PathBuf>,
+
+        #[arg(
+            help = "number of generations between exported images",
+            long,
+            default_value_t = 1
+        )]
+        export_every: usize,
+    },
+
+    #[command(about = "Conway's game of life and other outer-totalistic rules")]
+    Conway {
+        #[arg(
+            help = "pattern file: RLE, plaintext (.cells) or Life 1.06 (default: a blinker)",
+            required = false
+        )]
+        pattern: Option<PathBuf>,
+
+        #[arg(
+            help = "image with the initial pattern, one pixel per cell (light cells are alive)",
+            long,
+            conflicts_with = "pattern"
+        )]
+        image: Option<PathBuf>,
+
+        #[arg(
+            help = "start with random cells that are alive with this probability",
+            long,
+            conflicts_with_all = ["pattern", "image"]
+        )]
+        random: Option<f64>,
+
+        #[arg(help = "seed for the random start", long)]
+        seed: Option<u64>,
+
+        #[arg(
+            help = "rule in B/S notation, e.g., B3/S23, B2/S34H, B2/S/C3 or B4A/S3AB with hexadecimal counts above 9 (default: from the pattern, or B3/S23)",
+            long
+        )]
+        rule: Option<totalistic::Rule>,
+
+        #[arg(
+            help = "tiling (default: from the suffix of the rule, or orthogonal-and-diagonal)",
+            long,
+            value_enum
+        )]
+        tiling: Option<Tiling>,
+
+        #[arg(help = "use PatchTorus instead of CellTorus", long)]
+        patch_torus: bool,
+
+        #[arg(help = "width of torus (default: pattern with a margin)", long)]
+        width: Option<usize>,
+
+        #[arg(help = "height of torus (default: pattern with a margin)", long)]
+        height: Option<usize>,
+
+        #[arg(
+            help = "column of the left edge of the pattern (default: centered)",
+            long
+        )]
+        x: Option<usize>,
+
+        #[arg(help = "row of the top edge of the pattern (default: centered)", long)]
+        y: Option<usize>,
+
+        #[arg(help = "number of generations", long, default_value_t = 100)]
+        generations: usize,
+
+        #[arg(help = "directory to export image-files", long)]
+        export_dir: Option<PathBuf>,
+
+        #[arg(
+            help = "number of generations between exported images",
+            long,
+            default_value_t = 1
+        )]
+        export_every: usize,
+
+        #[command(flatten)]
+        view: ViewOptions,
+
+        #[command(flatten)]
+        svg: SvgOptions,
+
+        #[command(flatten)]
+        animation: AnimationOptions,
+    },
 
     #[command(about = "Experiment with a real-valued state value")]
-    Experiment,
+    Experiment {
+        #[arg(
+            help = "directory to export image-files (default view: projection along z)",
+            long
+        )]
+        export_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        view: ViewOptions,
+    },
+
+    #[command(about = "search for gliders of a continuous cellular automaton (Lenia)")]
+    Lenia {
+        #[arg(
+            help = "tiling to search (default: hexagons and orthogonal-and-diagonal)",
+            long,
+            value_enum
+        )]
+        tiling: Vec<Tiling>,
+
+        #[arg(help = "width and height of torus", long, default_value_t = 40)]
+        size: usize,
+
+        #[arg(
+            help = "number of steps to the edge of the neighbourhood",
+            long,
+            default_value_t = 4
+        )]
+        radius: usize,
+
+        #[arg(
+            help = "peaks of the rings of the kernel, from the inside out",
+            long,
+            value_delimiter = ',',
+            default_value = "1"
+        )]
+        peaks: Vec<f64>,
+
+        #[arg(help = "shape of the kernel rings", long, value_enum, default_value_t = lenia::Shape::Exponential)]
+        kernel: lenia::Shape,
+
+        #[arg(help = "shape of the growth function", long, value_enum, default_value_t = lenia::Shape::Exponential)]
+        growth: lenia::Shape,
+
+        #[arg(
+            help = "potentials with the most growth to search (default: 0.15, 0.2, 0.25, 0.3)",
+            long,
+            value_delimiter = ','
+        )]
+        mu: Vec<f64>,
+
+        #[arg(
+            help = "widths of the growth function to search (default: 0.017, 0.025, 0.033)",
+            long,
+            value_delimiter = ','
+        )]
+        sigma: Vec<f64>,
+
+        #[arg(help = "time step", long, default_value_t = 0.1)]
+        dt: f64,
+
+        #[arg(
+            help = "number of generations of each run",
+            long,
+            default_value_t = 150
+        )]
+        generations: usize,
+
+        #[arg(help = "seed for the random blob", long)]
+        seed: Option<u64>,
+
+        #[arg(help = "directory to export the last generation of each run", long)]
+        export_dir: Option<PathBuf>,
+    },
+
+    #[command(
+        about = "sweep the temperature of the Ising model and estimate the critical temperature"
+    )]
+    Ising {
+        #[arg(help = "tiling to sweep (default: all)", long, value_enum)]
+        tiling: Vec<Tiling>,
+
+        #[arg(
+            help = "temperatures to visit, from high to low (default: below the mean-field estimate of each tiling)",
+            long,
+            value_delimiter = ','
+        )]
+        temperature: Vec<f64>,
+
+        #[arg(help = "how a spin chooses its next value", long, value_enum, default_value_t = ising::Dynamics::Metropolis)]
+        dynamics: ising::Dynamics,
+
+        #[arg(
+            help = "width and height of the larger torus (a multiple of twelve suits all tilings)",
+            long,
+            default_value_t = 36
+        )]
+        size: usize,
+
+        #[arg(
+            help = "number of sweeps to reach equilibrium at each temperature",
+            long,
+            default_value_t = 300
+        )]
+        equilibrate: usize,
+
+        #[arg(
+            help = "number of sweeps to measure at each temperature",
+            long,
+            default_value_t = 1000
+        )]
+        measure: usize,
+
+        #[arg(help = "seed for the random streams", long, default_value_t = 0)]
+        seed: u64,
+    },
+
+    #[command(
+        about = "run a reversible rule of the second order forwards and back, and check that it returns to the start"
+    )]
+    Reversible {
+        #[arg(help = "tiling", long, value_enum, default_value_t = Tiling::Orthogonal)]
+        tiling: Tiling,
+
+        #[arg(help = "use PatchTorus instead of CellTorus", long)]
+        patch_torus: bool,
+
+        #[arg(help = "width and height of torus", long, default_value_t = 96)]
+        size: usize,
+
+        #[arg(help = "number of states", long, default_value_t = 2)]
+        states: u8,
+
+        #[arg(
+            help = "probability of a non-zero state in the middle of the torus",
+            long,
+            default_value_t = 0.5
+        )]
+        density: f64,
+
+        #[arg(help = "seed for the random start", long, default_value_t = 0)]
+        seed: u64,
+
+        #[arg(
+            help = "number of generations forwards, and then backwards",
+            long,
+            default_value_t = 100
+        )]
+        generations: usize,
+
+        #[arg(help = "directory to export image-files", long)]
+        export_dir: Option<PathBuf>,
+
+        #[arg(
+            help = "number of generations between exported images",
+            long,
+            default_value_t = 1
+        )]
+        export_every: usize,
+    },
 
     #[command(about = "simulate a wave")]
     Wave {
@@ -37,6 +378,47 @@ enum Commands {
         #[arg(help = "execute debug function", required = false, long)]
         debug: bool,
 
+        #[arg(help = "put a wall with two slits in the path of the wave", long)]
+        double_slit: bool,
+
+        #[arg(help = "put a lens of slow medium in the path of the wave", long)]
+        lens: bool,
+
+        #[arg(
+            help = "image of the medium: black is a barrier, darker is slower",
+            long
+        )]
+        medium: Option<PathBuf>,
+
+        #[arg(
+            help = "image with the initial amplitudes, as exported with a smallest local maximum of 1",
+            long
+        )]
+        initial: Option<PathBuf>,
+
+        #[arg(help = "how a cell computes its next amplitude", long, value_enum, default_value_t = wave::Scheme::Velocity)]
+        scheme: wave::Scheme,
+
+        #[arg(
+            help = "size of the patches of a PatchTorus (default: best fit for the cache)",
+            long,
+            value_enum
+        )]
+        patch_size: Option<PatchSizeChoice>,
+
+        #[command(flatten)]
+        animation: AnimationOptions,
+
+        #[command(flatten)]
+        svg: SvgOptions,
+
+        #[arg(
+            help = "export in colour with the given palette instead of in shades of gray",
+            long,
+            value_enum
+        )]
+        color: Option<wave::WavePalette>,
+
         #[arg(help = "width of torus (must be even)")]
         size: usize,
 
@@ -44,6 +426,64 @@ enum Commands {
         height: Option<usize>,
     },
 
+    #[command(about = "run a rule on a random or loaded network")]
+    Network {
+        #[arg(help = "how to create the network", value_enum)]
+        model: network::ModelChoice,
+
+        #[arg(
+            help = "rule to run on the network",
+            long,
+            value_enum,
+            default_value = "wave"
+        )]
+        rule: network::Rule,
+
+        #[arg(help = "number of generations", long, default_value_t = 200)]
+        generations: usize,
+
+        #[arg(help = "seed for the random generator (default: random)", long)]
+        seed: Option<u64>,
+
+        #[arg(
+            help = "number of nodes of a random network",
+            long,
+            default_value_t = 1000
+        )]
+        nodes: usize,
+
+        #[arg(
+            help = "probability of a link (erdos-renyi)",
+            long,
+            default_value_t = 0.01
+        )]
+        probability: f64,
+
+        #[arg(
+            help = "number of neighbours on the ring (watts-strogatz)",
+            long,
+            default_value_t = 6
+        )]
+        neighbours: usize,
+
+        #[arg(
+            help = "probability of rewiring a link (watts-strogatz)",
+            long,
+            default_value_t = 0.1
+        )]
+        rewiring: f64,
+
+        #[arg(
+            help = "maximum length of a link (random-geometric)",
+            long,
+            default_value_t = 0.05
+        )]
+        radius: f64,
+
+        #[arg(help = "file to load the network from (edge-list, graphml)", long)]
+        file: Option<PathBuf>,
+    },
+
     #[command(name = "patch-poc", about = "proof-of-concept for patches of cells")]
     PatchPoC {
         #[arg(help = "directory to export image-files", long)]
@@ -59,18 +499,261 @@ pub fn main() -> Result<()> {
         Some(Commands::Wave {
             cell_torus,
             debug,
+            double_slit,
+            lens,
+            medium,
+            initial,
+            scheme,
             export_dir,
+            patch_size,
+            animation,
+            color,
+            svg,
             size,
             height,
         }) => {
             if debug {
                 wave::debug(size)?
             } else {
-                wave::example(!cell_torus, size, height, export_dir.as_ref())?
+                let medium = medium.as_deref().map(Picture::open).transpose()?;
+                let initial = initial.as_deref().map(Picture::open).transpose()?;
+                wave::example(
+                    !cell_torus,
+                    patch_size,
+                    wave::Obstacles {
+                        double_slit,
+                        lens,
+                        medium: medium.as_ref(),
+                    },
+                    size,
+                    height,
+                    &wave::Start {
+                        initial: initial.as_ref(),
+                        scheme,
+                        parameters: Default::default(),
+                    },
+                    &wave::Output {
+                        export_dir: export_dir.as_ref(),
+                        animation: &animation,
+                        color,
+                        svg: &svg,
+                    },
+                )?
             }
         }
-        Some(Commands::Conway) => conway::example()?,
-        Some(Commands::Experiment) => experiment::example()?,
+        Some(Commands::Analyze {
+            tiling,
+            cell_torus,
+            size,
+            height,
+        }) => {
+            let tilings = if tiling.is_empty() {
+                vec![
+                    Tiling::Orthogonal,
+                    Tiling::OrthogonalAndDiagonal,
+                    Tiling::Hexagons,
+                ]
+            } else {
+                tiling
+            };
+            analysis::example(&tilings, cell_torus, size, height)?
+        }
+        Some(Commands::Bench {
+            cell_torus,
+            generations,
+            size,
+            height,
+        }) => bench::example(size, height, generations, cell_torus)?,
+        Some(Commands::Billiard {
+            rule,
+            tiling,
+            patch_torus,
+            size,
+            density,
+            seed,
+            generations,
+            export_dir,
+            export_every,
+        }) => block::example(&block::Demo {
+            tiling,
+            preset: rule,
+            patched: patch_torus,
+            width: size,
+            height: size,
+            density,
+            seed,
+            generations,
+            export_dir: export_dir.as_ref(),
+            export_every,
+        })?,
+        Some(Commands::Reversible {
+            tiling,
+            patch_torus,
+            size,
+            states,
+            density,
+            seed,
+            generations,
+            export_dir,
+            export_every,
+        }) => reversible::example(&reversible::Demo {
+            tiling,
+            patched: patch_torus,
+            width: size,
+            height: size,
+            states,
+            density,
+            seed,
+            generations,
+            export_dir: export_dir.as_ref(),
+            export_every,
+        })?,
+        Some(Commands::Conway {
+            pattern,
+            image,
+            random,
+            seed,
+            rule,
+            tiling,
+            patch_torus,
+            width,
+            height,
+            x,
+            y,
+            generations,
+            export_dir,
+            export_every,
+            view,
+            svg,
+            animation,
+        }) => {
+            let seed = match (pattern.as_deref(), image.as_deref(), random) {
+                (Some(path), _, _) => conway::Seed::Pattern(path),
+                (None, Some(path), _) => conway::Seed::Image(path),
+                (None, None, Some(density)) => conway::Seed::Random { density, seed },
+                (None, None, None) => conway::Seed::Blinker,
+            };
+            conway::example(
+                seed,
+                conway::Automaton {
+                    rule,
+                    tiling,
+                    patched: patch_torus,
+                },
+                conway::Placement {
+                    width,
+                    height,
+                    x,
+                    y,
+                },
+                generations,
+                &conway::Output {
+                    export_dir: export_dir.as_ref(),
+                    export_every,
+                    view: &view.view()?,
+                    svg: &svg,
+                    animation: &animation,
+                },
+            )?
+        }
+        Some(Commands::Experiment { export_dir, view }) => {
+            experiment::example(export_dir.as_ref(), &view.view()?)?
+        }
+        Some(Commands::Lenia {
+            tiling,
+            size,
+            radius,
+            peaks,
+            kernel,
+            growth,
+            mu,
+            sigma,
+            dt,
+            generations,
+            seed,
+            export_dir,
+        }) => {
+            let tilings = if tiling.is_empty() {
+                lenia::DEFAULT_TILINGS.to_vec()
+            } else {
+                tiling
+            };
+            let mu = if mu.is_empty() {
+                lenia::DEFAULT_MU.to_vec()
+            } else {
+                mu
+            };
+            let sigma = if sigma.is_empty() {
+                lenia::DEFAULT_SIGMA.to_vec()
+            } else {
+                sigma
+            };
+            let parameters = lenia::Parameters::new(radius, 0.0, 0.0, dt)
+                .with_shapes(kernel, growth)
+                .with_peaks(&peaks)?;
+            let search = lenia::Search {
+                tilings: &tilings,
+                mu: &mu,
+                sigma: &sigma,
+                parameters,
+                size,
+                generations,
+                seed,
+            };
+            lenia::example(&search, export_dir.as_ref())?
+        }
+        Some(Commands::Ising {
+            tiling,
+            temperature,
+            dynamics,
+            size,
+            equilibrate,
+            measure,
+            seed,
+        }) => {
+            let tilings = if tiling.is_empty() {
+                vec![
+                    Tiling::Orthogonal,
+                    Tiling::OrthogonalAndDiagonal,
+                    Tiling::Hexagons,
+                    Tiling::AdjacentTriangles,
+                    Tiling::TouchingTriangles,
+                ]
+            } else {
+                tiling
+            };
+            ising::example(&ising::Sweep {
+                tilings: &tilings,
+                temperatures: &temperature,
+                dynamics,
+                size,
+                equilibrate,
+                measure,
+                seed,
+            })?
+        }
+        Some(Commands::Network {
+            model,
+            rule,
+            generations,
+            seed,
+            nodes,
+            probability,
+            neighbours,
+            rewiring,
+            radius,
+            file,
+        }) => {
+            let parameters = network::ModelParameters {
+                nodes,
+                probability,
+                neighbours,
+                rewiring,
+                radius,
+                file,
+            };
+            network::example(model, &parameters, seed, rule, generations)?
+        }
         Some(Commands::PatchPoC { .. }) => patch::poc_example()?,
         None => help()?,
     }
@@ -0,0 +1,194 @@
+//! Runs rules on networks that are not lattices, to see how the structure of the network shapes their behavior.
+
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use log::info;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    cell::{CellNetwork, NetworkModel, new_cell_network},
+    conway::Conway,
+    structure::{Generation, Region, Run, Space, State},
+    wave::Wave,
+};
+
+/// The network models that can be chosen on the command line.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum ModelChoice {
+    ErdosRenyi,
+    WattsStrogatz,
+    RandomGeometric,
+    EdgeList,
+    Graphml,
+}
+
+/// The rules that can be run on a network.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Rule {
+    Wave,
+    Conway,
+}
+
+/// The parameters of the network models. Each model only uses the parameters that apply to it.
+#[derive(Clone, Debug)]
+pub struct ModelParameters {
+    pub nodes: usize,
+    pub probability: f64,
+    pub neighbours: usize,
+    pub rewiring: f64,
+    pub radius: f64,
+    pub file: Option<PathBuf>,
+}
+
+impl ModelParameters {
+    fn model(&self, choice: ModelChoice) -> Result<NetworkModel> {
+        let file = || {
+            self.file
+                .clone()
+                .ok_or_else(|| anyhow!("Model {choice:?} requires a file"))
+        };
+        let model = match choice {
+            ModelChoice::ErdosRenyi => NetworkModel::ErdosRenyi {
+                nodes: self.nodes,
+                probability: self.probability,
+            },
+            ModelChoice::WattsStrogatz => NetworkModel::WattsStrogatz {
+                nodes: self.nodes,
+                neighbours: self.neighbours,
+                rewiring: self.rewiring,
+            },
+            ModelChoice::RandomGeometric => NetworkModel::RandomGeometric {
+                nodes: self.nodes,
+                radius: self.radius,
+            },
+            ModelChoice::EdgeList => NetworkModel::EdgeList(file()?),
+            ModelChoice::Graphml => NetworkModel::GraphMl(file()?),
+        };
+        Ok(model)
+    }
+}
+
+pub fn example(
+    choice: ModelChoice,
+    parameters: &ModelParameters,
+    seed: Option<u64>,
+    rule: Rule,
+    generations: usize,
+) -> Result<()> {
+    let model = parameters.model(choice)?;
+    let seed = seed.unwrap_or_else(rand::random);
+    info!("Network: {model:?}: seed: [{seed}]");
+    match rule {
+        Rule::Wave => wave_example(&model, seed, generations),
+        Rule::Conway => conway_example(&model, seed, generations),
+    }
+}
+
+/// Starts a wave at the source (see `source`) and reports how many nodes it has reached.
+fn wave_example(model: &NetworkModel, seed: u64, generations: usize) -> Result<()> {
+    let mut generation = 0usize;
+    let mut network = new_cell_network(model, seed, generation, |_| Wave::new(0.0, false))?;
+    network.info()?;
+    let source = source(&network);
+    if let Some(label) = network.label(source) {
+        info!("Source: [{label}]");
+    }
+    if let Some((x, y)) = network.position(source) {
+        info!("Source position: ({x:.3}, {y:.3})");
+    }
+    network.adjust(&generation, source, Wave::new(0.0, true))?;
+    run(
+        &mut network,
+        &mut generation,
+        generations,
+        |network, generation| {
+            let (reached, maximum) = network.reduce(
+                generation,
+                (0usize, 0.0f64),
+                |region, location, (r, m)| match region.state(location) as Option<Wave> {
+                    Some(wave) if !wave.is_center() && wave.amplitude() != 0.0 => {
+                        (r + 1, m.max(wave.amplitude().abs()))
+                    }
+                    _ => (r, m),
+                },
+            );
+            info!(
+                "Generation: [{generation}]: reached: {reached}: maximum amplitude: {maximum:.4}"
+            );
+        },
+    )
+}
+
+/// The node nearest to the mean position of all nodes, if the model places nodes in space, and otherwise the first node.
+fn source<S: State<Gen>, Gen: Generation>(network: &CellNetwork<S, Gen>) -> usize {
+    let positions = (0..network.len())
+        .map(|index| network.position(index))
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default();
+    if positions.is_empty() {
+        return 0;
+    }
+    let count = positions.len() as f64;
+    let (mx, my) = positions.iter().fold((0.0, 0.0), |(sx, sy), (x, y)| {
+        (sx + x / count, sy + y / count)
+    });
+    let distance = |(x, y): &(f64, f64)| (x - mx).powi(2) + (y - my).powi(2);
+    positions
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+        .map(|(index, _)| index)
+        .unwrap_or_default()
+}
+
+/// Brings each node to life with probability 0.3 and reports the population.
+fn conway_example(model: &NetworkModel, seed: u64, generations: usize) -> Result<()> {
+    let mut generation = 0usize;
+    let mut network = new_cell_network(model, seed, generation, |_| Conway::new(false))?;
+    network.info()?;
+    let mut rng = StdRng::seed_from_u64(seed.wrapping_add(1));
+    for i in 0..network.len() {
+        network.adjust(&generation, i, Conway::new(rng.random_bool(0.3)))?;
+    }
+    run(
+        &mut network,
+        &mut generation,
+        generations,
+        |network, generation| {
+            let population = network.reduce(generation, 0usize, |region, location, p| {
+                match region.state(location) as Option<Conway> {
+                    Some(Conway { alive: true }) => p + 1,
+                    _ => p,
+                }
+            });
+            info!("Generation: [{generation}]: population: {population}");
+        },
+    )
+}
+
+/// Runs the given number of generations and reports ten times along the way. A network is not a `Torus`: it has no
+/// coordinates, dimensions or images, which is what `Simulation` and its observers are written against. So networks
+/// step by hand.
+fn run<S, F>(
+    network: &mut CellNetwork<S, usize>,
+    generation: &mut usize,
+    generations: usize,
+    report: F,
+) -> Result<()>
+where
+    S: State<usize>,
+    F: Fn(&CellNetwork<S, usize>, &usize),
+{
+    let interval = (generations / 10).max(1);
+    for i in 1..=generations {
+        network.update_all(generation, &Run::default())?;
+        network.free(generation)?;
+        *generation = generation.successor();
+        if i % interval == 0 {
+            report(network, generation);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//! Palettes that map numbers to colours, for states that are better shown in colour than in shades of gray.
+//!
+//! A diverging palette shows signed quantities, such as the amplitude of a wave, with a neutral colour at zero. A
+//! cyclic palette shows angles, such as the state of `Rotate`, so that zero and a full turn get the same colour.
+//! When a state has several fields of interest, `channels` puts each of them in a channel of its own.
+
+use std::f64::consts::PI;
+
+pub trait ColorMap {
+    fn color(&self, value: f64) -> [u8; 3];
+}
+
+/// Runs from blue through white to red over the range [-1, 1]. Values outside the range are clamped.
+#[derive(Clone, Copy, Debug)]
+pub struct Diverging {
+    pub negative: [u8; 3],
+    pub neutral: [u8; 3],
+    pub positive: [u8; 3],
+}
+
+impl Default for Diverging {
+    fn default() -> Self {
+        Diverging {
+            negative: [33, 102, 172],
+            neutral: [247, 247, 247],
+            positive: [178, 24, 43],
+        }
+    }
+}
+
+impl ColorMap for Diverging {
+    fn color(&self, value: f64) -> [u8; 3] {
+        let value = if value.is_nan() {
+            0.0
+        } else {
+            value.clamp(-1.0, 1.0)
+        };
+        if value < 0.0 {
+            blend(self.neutral, self.negative, -value)
+        } else {
+            blend(self.neutral, self.positive, value)
+        }
+    }
+}
+
+/// Runs through the hues of the colour wheel as the value goes from zero to a full turn (in radians).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cyclic;
+
+impl ColorMap for Cyclic {
+    fn color(&self, value: f64) -> [u8; 3] {
+        let angle = value.rem_euclid(2.0 * PI);
+        let component = |offset: f64| {
+            let c = 0.5 + 0.5 * (angle - offset).cos();
+            (255.0 * c).round() as u8
+        };
+        [
+            component(0.0),
+            component(2.0 * PI / 3.0),
+            component(4.0 * PI / 3.0),
+        ]
+    }
+}
+
+/// Maps three values in the range [0, 1] to the red, green and blue channels. Values outside the range are clamped.
+pub fn channels(red: f64, green: f64, blue: f64) -> [u8; 3] {
+    [red, green, blue].map(|v| {
+        let v = if v.is_nan() { 0.0 } else { v.clamp(0.0, 1.0) };
+        (255.0 * v).round() as u8
+    })
+}
+
+fn blend(from: [u8; 3], to: [u8; 3], fraction: f64) -> [u8; 3] {
+    let mut result = [0u8; 3];
+    for (i, c) in result.iter_mut().enumerate() {
+        let f = from[i] as f64;
+        let t = to[i] as f64;
+        *c = (f + (t - f) * fraction).round() as u8;
+    }
+    result
+}
@@ -0,0 +1,417 @@
+//! # Effectors of the cells in a patch
+//!
+//! There are two ways to store the effectors of the cells in a patch:
+//!
+//! * `FixedEffectors` reserves room for exactly `N` effectors per cell. Looking up the effectors of a cell is a single multiplication, but memory is wasted if the number of effectors varies a lot.
+//! * `CsrEffectors` stores the effectors of all cells back to back (compressed sparse row). There is no limit on the number of effectors per cell, but adding an effector shifts the effectors of all cells with a higher index.
+//!
+//! Both keep the weight of each link next to the effector, at the same position in a parallel slice.
+
+use anyhow::{Result, anyhow};
+use log::debug;
+use std::marker::PhantomData;
+
+use crate::{
+    patch::{IndexOf, PatchIndex, PatchSize, Small},
+    structure::Weight,
+};
+
+pub struct EffectorIterator<'a, I: PatchIndex> {
+    effectors: &'a [I],
+    pos: usize,
+    to_go: usize,
+    none: I,
+}
+
+impl<'a, I: PatchIndex> Iterator for EffectorIterator<'a, I> {
+    type Item = I;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.to_go < 1 || self.effectors[self.pos] == self.none {
+            None
+        } else {
+            let pos = self.pos;
+            self.pos += 1;
+            self.to_go -= 1;
+            Some(self.effectors[pos])
+        }
+    }
+}
+
+pub trait Effectors: Default {
+    type Size: PatchSize;
+
+    fn iter<'a>(&'a self, index: IndexOf<Self::Size>) -> EffectorIterator<'a, IndexOf<Self::Size>>;
+    /// The effectors of the cell with the given index, together with the weights of the links.
+    fn weighted<'a>(
+        &'a self,
+        index: IndexOf<Self::Size>,
+    ) -> impl Iterator<Item = (IndexOf<Self::Size>, Weight)> + 'a;
+    /// Adds a link with weight one and returns the number of effectors of the cell. If the link already exists, it is
+    /// left alone, weight and all.
+    fn add(
+        &mut self,
+        index: IndexOf<Self::Size>,
+        effector_index: IndexOf<Self::Size>,
+    ) -> Result<usize> {
+        if self.iter(index).any(|effector| effector == effector_index) {
+            return Ok(self.iter(index).count());
+        }
+        self.add_weighted(index, effector_index, 1.0)
+    }
+    /// Adds a link with the given weight. If the link already exists, only its weight is replaced.
+    fn add_weighted(
+        &mut self,
+        index: IndexOf<Self::Size>,
+        effector_index: IndexOf<Self::Size>,
+        weight: Weight,
+    ) -> Result<usize>;
+    fn remove(
+        &mut self,
+        index: IndexOf<Self::Size>,
+        effector_index: IndexOf<Self::Size>,
+    ) -> Result<usize>;
+    /// Removes all effectors of the cell with the given index.
+    fn clear(&mut self, index: IndexOf<Self::Size>);
+    /// Removes the cell with the given index from the effectors of all cells.
+    fn detach(&mut self, effector_index: IndexOf<Self::Size>);
+    fn debug<S: AsRef<str>>(&self, label: S);
+}
+
+fn no_cell<P: PatchSize>() -> IndexOf<P> {
+    IndexOf::<P>::from_usize(P::CAPACITY)
+}
+
+/// Room for at most `N` effectors per cell.
+#[derive(Clone)]
+pub struct FixedEffectors<const N: usize, P: PatchSize = Small> {
+    effector_counts: Box<[usize]>,
+    effectors: Box<[P::Index]>,
+    weights: Box<[Weight]>,
+}
+
+pub type AtMostSixEffectors<P = Small> = FixedEffectors<6, P>;
+
+impl<const N: usize, P: PatchSize> Default for FixedEffectors<N, P> {
+    fn default() -> Self {
+        Self {
+            effector_counts: vec![0; P::CAPACITY].into_boxed_slice(),
+            effectors: vec![no_cell::<P>(); N * P::CAPACITY].into_boxed_slice(),
+            weights: vec![0.0; N * P::CAPACITY].into_boxed_slice(),
+        }
+    }
+}
+
+impl<const N: usize, P: PatchSize> FixedEffectors<N, P> {
+    fn remove_if_present(&mut self, index: P::Index, effector_index: P::Index) -> bool {
+        let i = index.to_usize();
+        let n = self.effector_counts[i];
+        let base = N * i;
+        if let Some(k) = (base..(base + n)).find(|k| self.effectors[*k] == effector_index) {
+            self.effectors.copy_within((k + 1)..(base + n), k);
+            self.weights.copy_within((k + 1)..(base + n), k);
+            self.effectors[base + n - 1] = no_cell::<P>();
+            self.weights[base + n - 1] = 0.0;
+            self.effector_counts[i] -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<const N: usize, P: PatchSize> Effectors for FixedEffectors<N, P> {
+    type Size = P;
+
+    fn iter<'a>(&'a self, index: P::Index) -> EffectorIterator<'a, P::Index> {
+        EffectorIterator {
+            effectors: &self.effectors,
+            pos: N * index.to_usize(),
+            to_go: self.effector_counts[index.to_usize()],
+            none: no_cell::<P>(),
+        }
+    }
+
+    fn weighted<'a>(&'a self, index: P::Index) -> impl Iterator<Item = (P::Index, Weight)> + 'a {
+        let base = N * index.to_usize();
+        self.iter(index).zip(self.weights[base..].iter().copied())
+    }
+
+    fn add_weighted(
+        &mut self,
+        index: P::Index,
+        effector_index: P::Index,
+        weight: Weight,
+    ) -> Result<usize> {
+        let i = index.to_usize();
+        let n = self.effector_counts[i];
+        let base = N * i;
+        if let Some(k) = (base..(base + n)).find(|k| self.effectors[*k] == effector_index) {
+            self.weights[k] = weight;
+            return Ok(self.effector_counts[i]);
+        }
+        if n >= N {
+            return Err(anyhow!("Cannot add more than {N} effectors"));
+        }
+        self.effectors[base + n] = effector_index;
+        self.weights[base + n] = weight;
+        self.effector_counts[i] += 1;
+        Ok(self.effector_counts[i])
+    }
+
+    fn remove(&mut self, index: P::Index, effector_index: P::Index) -> Result<usize> {
+        if self.remove_if_present(index, effector_index) {
+            Ok(self.effector_counts[index.to_usize()])
+        } else {
+            Err(anyhow!("Not an effector of [{index}]: [{effector_index}]"))
+        }
+    }
+
+    fn clear(&mut self, index: P::Index) {
+        let base = N * index.to_usize();
+        self.effectors[base..(base + N)].fill(no_cell::<P>());
+        self.weights[base..(base + N)].fill(0.0);
+        self.effector_counts[index.to_usize()] = 0;
+    }
+
+    fn detach(&mut self, effector_index: P::Index) {
+        for i in 0..P::CAPACITY {
+            self.remove_if_present(P::Index::from_usize(i), effector_index);
+        }
+    }
+
+    fn debug<S: AsRef<str>>(&self, label: S) {
+        for i in 0..self.effector_counts.len() {
+            let count = self.effector_counts[i];
+            if count > 0 {
+                debug!("{}: {i}: {count}", label.as_ref());
+            }
+        }
+    }
+}
+
+/// Any number of effectors per cell. The effectors of cell *i* are found in `effectors[offsets[i]..offsets[i + 1]]`.
+#[derive(Clone)]
+pub struct CsrEffectors<P: PatchSize = Small> {
+    offsets: Vec<usize>,
+    effectors: Vec<P::Index>,
+    weights: Vec<Weight>,
+    _size: PhantomData<P>,
+}
+
+impl<P: PatchSize> Default for CsrEffectors<P> {
+    fn default() -> Self {
+        Self {
+            offsets: vec![0; P::CAPACITY + 1],
+            effectors: Vec::new(),
+            weights: Vec::new(),
+            _size: PhantomData,
+        }
+    }
+}
+
+impl<P: PatchSize> CsrEffectors<P> {
+    fn range(&self, index: P::Index) -> std::ops::Range<usize> {
+        let i = index.to_usize();
+        self.offsets[i]..self.offsets[i + 1]
+    }
+
+    fn count(&self, index: P::Index) -> usize {
+        self.range(index).len()
+    }
+
+    fn remove_if_present(&mut self, index: P::Index, effector_index: P::Index) -> bool {
+        let range = self.range(index);
+        if let Some(k) = range.clone().find(|k| self.effectors[*k] == effector_index) {
+            self.effectors.remove(k);
+            self.weights.remove(k);
+            for offset in &mut self.offsets[(index.to_usize() + 1)..] {
+                *offset -= 1;
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<P: PatchSize> Effectors for CsrEffectors<P> {
+    type Size = P;
+
+    fn iter<'a>(&'a self, index: P::Index) -> EffectorIterator<'a, P::Index> {
+        let range = self.range(index);
+        EffectorIterator {
+            effectors: &self.effectors,
+            pos: range.start,
+            to_go: range.len(),
+            none: no_cell::<P>(),
+        }
+    }
+
+    fn weighted<'a>(&'a self, index: P::Index) -> impl Iterator<Item = (P::Index, Weight)> + 'a {
+        let range = self.range(index);
+        self.effectors[range.clone()]
+            .iter()
+            .copied()
+            .zip(self.weights[range].iter().copied())
+    }
+
+    fn add_weighted(
+        &mut self,
+        index: P::Index,
+        effector_index: P::Index,
+        weight: Weight,
+    ) -> Result<usize> {
+        let range = self.range(index);
+        if let Some(k) = range.clone().find(|k| self.effectors[*k] == effector_index) {
+            self.weights[k] = weight;
+            return Ok(self.count(index));
+        }
+        self.effectors.insert(range.end, effector_index);
+        self.weights.insert(range.end, weight);
+        for offset in &mut self.offsets[(index.to_usize() + 1)..] {
+            *offset += 1;
+        }
+        Ok(self.count(index))
+    }
+
+    fn remove(&mut self, index: P::Index, effector_index: P::Index) -> Result<usize> {
+        if self.remove_if_present(index, effector_index) {
+            Ok(self.count(index))
+        } else {
+            Err(anyhow!("Not an effector of [{index}]: [{effector_index}]"))
+        }
+    }
+
+    fn clear(&mut self, index: P::Index) {
+        let range = self.range(index);
+        let n = range.len();
+        self.effectors.drain(range.clone());
+        self.weights.drain(range);
+        for offset in &mut self.offsets[(index.to_usize() + 1)..] {
+            *offset -= n;
+        }
+    }
+
+    fn detach(&mut self, effector_index: P::Index) {
+        for i in 0..P::CAPACITY {
+            self.remove_if_present(P::Index::from_usize(i), effector_index);
+        }
+    }
+
+    fn debug<S: AsRef<str>>(&self, label: S) {
+        for i in 0..P::CAPACITY {
+            let count = self.count(P::Index::from_usize(i));
+            if count > 0 {
+                debug!("{}: {i}: {count}", label.as_ref());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Index = IndexOf<Small>;
+
+    #[derive(Clone, Copy, Debug)]
+    enum Operation {
+        Add(usize, usize),
+        AddWeighted(usize, usize, Weight),
+        Remove(usize, usize),
+        Clear(usize),
+        Detach(usize),
+    }
+
+    fn apply<E: Effectors<Size = Small>>(effectors: &mut E, operation: Operation) -> Result<usize> {
+        let i = Index::from_usize;
+        match operation {
+            Operation::Add(index, effector) => effectors.add(i(index), i(effector)),
+            Operation::AddWeighted(index, effector, weight) => {
+                effectors.add_weighted(i(index), i(effector), weight)
+            }
+            Operation::Remove(index, effector) => effectors.remove(i(index), i(effector)),
+            Operation::Clear(index) => {
+                effectors.clear(i(index));
+                Ok(0)
+            }
+            Operation::Detach(effector) => {
+                effectors.detach(i(effector));
+                Ok(0)
+            }
+        }
+    }
+
+    fn links<E: Effectors<Size = Small>>(effectors: &E) -> Vec<Vec<(Index, Weight)>> {
+        (0..4)
+            .map(|index| effectors.weighted(Index::from_usize(index)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn both_stores_behave_the_same() {
+        use Operation::*;
+        let mut fixed = FixedEffectors::<3>::default();
+        let mut csr = CsrEffectors::default();
+        let operations = [
+            Add(1, 2),
+            AddWeighted(1, 3, 0.5),
+            Add(0, 1),
+            Add(2, 1),
+            Add(1, 2),
+            AddWeighted(1, 2, 2.0),
+            Remove(1, 3),
+            Remove(1, 3),
+            AddWeighted(1, 0, 0.25),
+            Add(1, 3),
+            Detach(2),
+            Add(1, 2),
+            Clear(1),
+            Add(1, 0),
+            Remove(3, 0),
+        ];
+        for operation in operations {
+            let expected = apply(&mut csr, operation);
+            let actual = apply(&mut fixed, operation);
+            assert_eq!(actual.ok(), expected.ok(), "{operation:?}");
+            assert_eq!(links(&fixed), links(&csr), "{operation:?}");
+        }
+        assert_eq!(links(&fixed)[1], vec![(0, 1.0)]);
+        assert_eq!(links(&fixed)[0], vec![(1, 1.0)]);
+    }
+
+    #[test]
+    fn fixed_store_refuses_to_overflow() {
+        let mut fixed = FixedEffectors::<3>::default();
+        let mut csr = CsrEffectors::default();
+        for effector in 0..3 {
+            apply(&mut fixed, Operation::AddWeighted(1, effector, 0.5)).unwrap();
+            apply(&mut csr, Operation::AddWeighted(1, effector, 0.5)).unwrap();
+        }
+        assert!(apply(&mut fixed, Operation::Add(1, 3)).is_err());
+        assert_eq!(apply(&mut csr, Operation::Add(1, 3)).unwrap(), 4);
+        // A full cell still replaces the weights of the links it has.
+        assert_eq!(
+            apply(&mut fixed, Operation::AddWeighted(1, 2, 2.0)).unwrap(),
+            3
+        );
+        apply(&mut csr, Operation::AddWeighted(1, 2, 2.0)).unwrap();
+        apply(&mut csr, Operation::Remove(1, 3)).unwrap();
+        assert_eq!(links(&fixed), links(&csr));
+    }
+
+    #[test]
+    fn clear_resets_the_weights() {
+        let mut fixed = FixedEffectors::<3>::default();
+        for effector in 0..3 {
+            fixed.add_weighted(1, effector, 0.5).unwrap();
+        }
+        fixed.remove(1, 0).unwrap();
+        assert_eq!(&fixed.weights[3..6], &[0.5, 0.5, 0.0]);
+        fixed.clear(1);
+        assert_eq!(&fixed.weights[3..6], &[0.0; 3]);
+        assert_eq!(fixed.add(1, 2).unwrap(), 1);
+        assert_eq!(fixed.weighted(1).collect::<Vec<_>>(), vec![(2, 1.0)]);
+    }
+}
@@ -4,45 +4,135 @@
 //! Each cell is affected by a number of effectors, *i.e.*, its current state is completely determined by its state in the previous generation and the state of its effectors in the previous generation.
 //!
 //! The structure of connections between effectors and affected cells can be more or less flexible.
-//! One possibility is that all patches have the same structure, so there is a single `Effectors` struct for the entire space. This is modeled in struct `Crystal`.
-//! On the other end of the spectrum each patch could have its own `Effectors` struct. This is not implemented yet.
+//! One possibility is that all patches have the same structure, so there is a single `Effectors` struct for the entire space.
+//! On the other end of the spectrum each patch could have its own `Effectors` struct. This is modeled in struct `Crystal`: it keeps one `PatchLinks` per patch.
+//! The links of a patch can be replaced during a run (`Crystal::replace_patch_links`) and individual links can be removed (`Crystal::remove_effector`).
+//! A cell can be turned into a vacancy (`Crystal::add_vacancy`): it loses all its effectors and it is no longer an effector of any other cell, including the copies of the cell on the edges of neighbouring patches.
+//! Cells without effectors are not updated, so a vacancy keeps its state.
+//!
+//! The halo of a patch is one cell wide, which is enough for rules that only look at effectors.
+//! Rules with a larger reach use `Location::neighbourhood`: it replaces each halo cell by its original in the neighbouring patch and continues from there, so a neighbourhood can extend over several patches.
+//! Neighbourhoods are cached per location and radius, until the links of any patch change.
+//!
+//! A `Crystal` keeps sets of patches: the front holds the current generation and the past sets the previous ones, as many as the rule looks back (see `State::LOOKBACK`) plus one.
+//! `update_all` stitches the front, computes the next generation into the oldest set and makes it the front, so a step allocates nothing.
+//! The generation that the oldest set held before is overwritten, unless it was kept with `Space::retain`.
+//! `Space::reverse` reverses the order of the front and the sets that the rule looks back at, to run a `Reversible` rule backwards.
 //!
 //! The effectors of a cell with index *i* in patch *p<sub>a</sub>* can be found by calling `iter` on the `Effectors` instance that governs patch *p<sub>a</sub>*.
 //! Each invocation of `next` on the resulting iterator yields an index *e* that can be used to find the state of the effector.
 //! The global index of the patch that contains the effector *p<sub>e</sub>* can be looked up in the `cell_patches` array in patch *p<sub>a</sub>*.
 //! If cell_patches[e] equals -1, then the effector belongs to the interior of the same patch: *p<sub>e</sub>* equals *p<sub>a</sub>*; otherwise this effector belongs to the edge of this patch and to the interior of another patch.
 //! The state of an effector on the edge can be looked up in the `cells` array in *p<sub>e</sub>* using the index found in the `cell_index` array in patch *p<sub>a</sub>*.
+//!
+//! The capacity of a patch is a type parameter (see trait `PatchSize`). The `patch!` macro defines the sizes `Small`, `Medium` and `Large`.
+//! Larger patches have relatively fewer edge cells, but a patch should fit comfortably in the cache of the processor (see `plan_patch_size`).
 
+mod effectors;
 mod poc;
 mod torus;
 
+pub use effectors::{AtMostSixEffectors, CsrEffectors, Effectors, FixedEffectors};
 use log::{debug, log_enabled};
 use paste::paste;
-pub use poc::example as poc_example;
-pub use torus::new_hexagonal_torus;
+pub(crate) use poc::example as poc_example;
+pub use torus::{
+    PatchSizeChoice, PatchTorus, TorusPatchLinks, new_hexagonal_torus, new_patch_torus,
+    plan_patch_size,
+};
 
 use anyhow::{Result, anyhow};
-use std::{borrow::Cow, cell::RefCell, collections::HashMap, fmt::Debug, ops::Range, rc::Rc};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fmt::{Debug, Display},
+    hash::Hash,
+    marker::PhantomData,
+    ops::Range,
+    rc::Rc,
+};
+
+use crate::structure::{
+    BlockRule, Generation, Location, Neighbourhood, Region, Reversible, Run, Space, State,
+    UpdateContext, Weight, find_neighbourhood,
+};
+
+/// The type of the index of a cell in a patch.
+pub trait PatchIndex: Copy + Eq + Ord + Hash + Debug + Display + Default + 'static {
+    fn from_usize(value: usize) -> Self;
+    fn to_usize(self) -> usize;
+}
+
+macro_rules! patch_index {
+    ($index_type:ty) => {
+        impl PatchIndex for $index_type {
+            fn from_usize(value: usize) -> Self {
+                value as $index_type
+            }
+
+            fn to_usize(self) -> usize {
+                self as usize
+            }
+        }
+    };
+}
+
+patch_index!(u16);
+patch_index!(u32);
+
+/// The capacity of a patch.
+pub trait PatchSize: Clone + Copy + Debug + Default + 'static {
+    type Index: PatchIndex;
+    /// The number of cells in a patch, including edges. An index with this value means: no cell.
+    const CAPACITY: usize;
+    /// The width of a patch along the short side of a torus, used by the grid planner. It grows with the square root of
+    /// the capacity, starting from 17 for `Small`: `Medium` has four times as many cells and twice the width, `Large` 64
+    /// times as many cells and eight times the width.
+    const SQRT: usize;
+}
+
+pub type IndexOf<P> = <P as PatchSize>::Index;
+
+pub type PatchRef<S, Gen, P> = Rc<RefCell<Patch<S, Gen, P>>>;
 
-use crate::structure::{Generation, Location, Region, Space, State};
+/// Maps the index of a halo cell to the patch and index of the cell that it mirrors.
+pub type Edges<P> = HashMap<IndexOf<P>, (usize, IndexOf<P>)>;
+
+/// Cached neighbourhoods, by location and radius.
+type Neighbourhoods<I> = HashMap<(LocationInPatch<I>, usize), Neighbourhood<LocationInPatch<I>>>;
+
+/// The patches of one generation, with the generation if it was not freed yet.
+type PastPatches<S, Gen, P> = (Vec<PatchRef<S, Gen, P>>, Option<Gen>);
 
 pub struct Crystal<S: State<Gen> + Copy, Gen: Generation, PL: PatchLinks> {
     patch_links: Vec<PL>,
-    generations: HashMap<Gen, Vec<Rc<RefCell<SmallPatch<S, Gen>>>>>,
+    /// The patches of the current generation.
+    front: Vec<PatchRef<S, Gen, PL::Size>>,
+    front_generation: Gen,
+    /// The patches of the previous generations, the most recent first. The oldest set is room for the next generation.
+    past: VecDeque<PastPatches<S, Gen, PL::Size>>,
+    /// Copies of past generations that were kept on request.
+    retained: HashMap<Gen, Vec<PatchRef<S, Gen, PL::Size>>>,
+    /// The generations that were freed while the rule still looked back at them.
+    freed: Vec<Gen>,
+    neighbourhoods: RefCell<Neighbourhoods<IndexOf<PL::Size>>>,
 }
 
 pub trait PatchLinks {
-    type Eff: Effectors;
+    type Size: PatchSize;
+    type Eff: Effectors<Size = Self::Size>;
 
     fn effectors(&self) -> &Self::Eff;
-    fn edges(&self) -> &HashMap<SmallIndexType, (usize, SmallIndexType)>;
+    fn effectors_mut(&mut self) -> &mut Self::Eff;
+    fn edges(&self) -> &Edges<Self::Size>;
 }
 
 impl<S, Gen, PL> Crystal<S, Gen, PL>
 where
     S: State<Gen> + Copy,
     Gen: Generation,
-    PL: PatchLinks<Eff: Effectors>,
+    PL: PatchLinks,
 {
     pub fn new(
         patch_count: usize,
@@ -50,18 +140,25 @@ where
         init: S,
         patch_links_factory: impl Fn() -> PL,
     ) -> Self {
-        let mut patches = Vec::new();
-        let mut patch_links = Vec::new();
-        for index in 0..patch_count {
-            let new_patch = SmallPatch::new_init(init, index, generation.clone());
-            patches.push(Rc::new(RefCell::new(new_patch)));
-            patch_links.push(patch_links_factory());
-        }
-        let mut generations = HashMap::new();
-        generations.insert(generation.clone(), patches);
+        let new_patches = || {
+            (0..patch_count)
+                .map(|index| {
+                    Rc::new(RefCell::new(Patch::new_init(
+                        init,
+                        index,
+                        generation.clone(),
+                    )))
+                })
+                .collect::<Vec<_>>()
+        };
         Crystal {
-            patch_links,
-            generations,
+            patch_links: (0..patch_count).map(|_| patch_links_factory()).collect(),
+            front: new_patches(),
+            front_generation: generation.clone(),
+            past: (0..=S::LOOKBACK).map(|_| (new_patches(), None)).collect(),
+            retained: HashMap::new(),
+            freed: Vec::new(),
+            neighbourhoods: RefCell::new(HashMap::new()),
         }
     }
 
@@ -69,15 +166,218 @@ where
         self.patch_links.len()
     }
 
-    fn stitch(&self, patch: &mut SmallPatch<S, Gen>, generation: &Gen) {
+    /// The patches of the given generation: the current one, a previous one until it is freed, or a retained one.
+    fn patches(&self, generation: &Gen) -> Option<&Vec<PatchRef<S, Gen, PL::Size>>> {
+        if *generation == self.front_generation {
+            return Some(&self.front);
+        }
+        self.past
+            .iter()
+            .find(|(_, past_generation)| past_generation.as_ref() == Some(generation))
+            .map(|(patches, _)| patches)
+            .or_else(|| self.retained.get(generation))
+    }
+
+    /// Computes the successor of the current generation into the oldest set of patches and makes that the current set.
+    fn advance<F>(&mut self, generation: &Gen, compute: F) -> Result<()>
+    where
+        F: FnOnce(&Self, &[PatchRef<S, Gen, PL::Size>], &Gen) -> Result<()>,
+    {
+        if *generation != self.front_generation {
+            return Err(anyhow!(
+                "Not the current generation: [{generation:?}]: current: [{:?}]",
+                self.front_generation
+            ));
+        }
+        let next_generation = generation.successor();
+        let oldest = self
+            .past
+            .back_mut()
+            .ok_or_else(|| anyhow!("No room for the next generation"))?;
+        // The oldest generation is overwritten, so rules cannot see it anymore.
+        oldest.1 = None;
+        let next = std::mem::take(&mut oldest.0);
+        if let Err(e) = compute(self, &next, &next_generation) {
+            if let Some(oldest) = self.past.back_mut() {
+                oldest.0 = next;
+            }
+            return Err(e);
+        }
+        self.past.pop_back();
+        let previous = std::mem::replace(&mut self.front, next);
+        let previous_generation = std::mem::replace(&mut self.front_generation, next_generation);
+        self.past.push_front((previous, Some(previous_generation)));
+        self.forget_freed();
+        Ok(())
+    }
+
+    /// Forgets the generation that the rule no longer looks back at, if it was freed before.
+    fn forget_freed(&mut self) {
+        if let Some((_, Some(generation))) = self.past.get(S::LOOKBACK)
+            && let Some(i) = self.freed.iter().position(|freed| freed == generation)
+        {
+            self.freed.swap_remove(i);
+            self.past[S::LOOKBACK].1 = None;
+        }
+    }
+
+    /// Sets the number of inner cells and the number of cells including edges of a patch, in all sets of patches.
+    fn set_patch_size(&self, patch: usize, size: IndexOf<PL::Size>, total_size: IndexOf<PL::Size>) {
+        let past = self.past.iter().map(|(patches, _)| &patches[patch]);
+        for patch_ref in std::iter::once(&self.front[patch]).chain(past) {
+            let mut patch = patch_ref.borrow_mut();
+            patch.size = size;
+            patch.total_size = total_size;
+        }
+    }
+
+    pub fn patch_links(&self, patch: usize) -> Option<&PL> {
+        self.patch_links.get(patch)
+    }
+
+    /// Replaces the internal wiring of a patch and returns the previous wiring.
+    /// The new links must describe the same cells and edges, *i.e.*, only the effectors may differ: the edges must be
+    /// the same and each effector must be a cell of the patch, including its edges. Other links are rejected.
+    pub fn replace_patch_links(&mut self, patch: usize, patch_links: PL) -> Result<PL> {
+        let old = self
+            .patch_links
+            .get(patch)
+            .ok_or_else(|| anyhow!("No such patch: [{patch}]"))?;
+        if patch_links.edges() != old.edges() {
+            return Err(anyhow!("The new links of patch [{patch}] have other edges"));
+        }
+        let total_size = self.front[patch].borrow().total_size.to_usize();
+        let effectors = patch_links.effectors();
+        for i in 0..PL::Size::CAPACITY {
+            let index = IndexOf::<PL::Size>::from_usize(i);
+            if let Some(effector) = effectors
+                .iter(index)
+                .find(|effector| i >= total_size || effector.to_usize() >= total_size)
+            {
+                return Err(anyhow!(
+                    "The new links of patch [{patch}] link cells outside the patch: [{index}]: [{effector}]"
+                ));
+            }
+        }
+        self.neighbourhoods.get_mut().clear();
+        Ok(std::mem::replace(&mut self.patch_links[patch], patch_links))
+    }
+
+    /// Removes the link between a cell and one of its effectors and returns the number of effectors that are left.
+    pub fn remove_effector(
+        &mut self,
+        patch: usize,
+        index: IndexOf<PL::Size>,
+        effector_index: IndexOf<PL::Size>,
+    ) -> Result<usize> {
+        self.neighbourhoods.get_mut().clear();
+        self.patch_links
+            .get_mut(patch)
+            .ok_or_else(|| anyhow!("No such patch: [{patch}]"))?
+            .effectors_mut()
+            .remove(index, effector_index)
+    }
+
+    /// Changes the weight of an existing link between a cell and one of its effectors.
+    pub fn set_effector_weight(
+        &mut self,
+        patch: usize,
+        index: IndexOf<PL::Size>,
+        effector_index: IndexOf<PL::Size>,
+        weight: Weight,
+    ) -> Result<()> {
+        let effectors = self
+            .patch_links
+            .get_mut(patch)
+            .ok_or_else(|| anyhow!("No such patch: [{patch}]"))?
+            .effectors_mut();
+        if !effectors.iter(index).any(|e| e == effector_index) {
+            return Err(anyhow!(
+                "Not an effector of <{patch}#{index}>: [{effector_index}]"
+            ));
+        }
+        effectors.add_weighted(index, effector_index, weight)?;
+        Ok(())
+    }
+
+    /// Disconnects a cell from the space. Copies of the cell on the edges of other patches are disconnected as well.
+    pub fn add_vacancy(&mut self, patch: usize, index: IndexOf<PL::Size>) -> Result<()> {
+        self.neighbourhoods.get_mut().clear();
+        let effectors = self
+            .patch_links
+            .get_mut(patch)
+            .ok_or_else(|| anyhow!("No such patch: [{patch}]"))?
+            .effectors_mut();
+        effectors.clear(index);
+        effectors.detach(index);
+        for patch_links in self.patch_links.iter_mut() {
+            let copies = patch_links
+                .edges()
+                .iter()
+                .filter(|(_, (p, i))| *p == patch && *i == index)
+                .map(|(copy, _)| *copy)
+                .collect::<Vec<_>>();
+            for copy in copies {
+                debug!("Detach copy of vacancy: <{patch}#{index}>: [{copy}]");
+                patch_links.effectors_mut().detach(copy);
+            }
+        }
+        Ok(())
+    }
+
+    /// Computes the next generation cell by cell with the given rule. The edges of each patch are stitched first.
+    fn update_cells<F>(&mut self, generation: &Gen, run: &Run<S::Parameters>, rule: F) -> Result<()>
+    where
+        F: Fn(
+            &Self,
+            &PatchRef<S, Gen, PL::Size>,
+            &LocationInPatch<IndexOf<PL::Size>>,
+            &UpdateContext<S::Parameters, Gen>,
+        ) -> Result<S>,
+    {
+        debug!("Number of patches: [{generation:?}]: {}", self.front.len());
+        let context = run.context(generation);
+        self.advance(generation, |crystal, next, next_generation| {
+            for (patch_index, patch_ref) in crystal.front.iter().enumerate() {
+                let mut patch = patch_ref.borrow_mut();
+                crystal.stitch(&mut patch, generation);
+                drop(patch); // Drop temporary mutable borrow
+                let patch = patch_ref.borrow();
+                let mut updated_patch = next[patch_index].borrow_mut();
+                updated_patch.cells.copy_from_slice(&patch.cells);
+                updated_patch.generation = next_generation.clone();
+                debug!("Patch size: {}", patch.size);
+                for i in 0..patch.size.to_usize() {
+                    let i = IndexOf::<PL::Size>::from_usize(i);
+                    let location = LocationInPatch {
+                        index: i,
+                        patch: patch.index,
+                    };
+                    if crystal.patch_links[patch_index]
+                        .effectors()
+                        .iter(i)
+                        .next()
+                        .is_some()
+                    {
+                        let new_state =
+                            rule(crystal, patch_ref, &location, &context.at(&location))?;
+                        updated_patch.cells[i.to_usize()] = new_state;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn stitch(&self, patch: &mut Patch<S, Gen, PL::Size>, generation: &Gen) {
         let this_index = &patch.index;
         debug!("Stitch patch: [{}]", this_index);
-        if let Some(patches) = self.generations.get(generation) {
+        if let Some(patches) = self.patches(generation) {
             let edges = &self.patch_links[patch.index].edges();
             debug!("Number of edge cells: [{}]", edges.len());
             for (i, (other_index, j)) in edges.iter() {
                 let other = patches[*other_index].borrow();
-                let state = other.cells[*j as usize];
+                let state = other.cells[j.to_usize()];
                 if log_enabled!(log::Level::Debug) {
                     let loc = LocationInPatch {
                         patch: *this_index,
@@ -85,7 +385,7 @@ where
                     };
                     debug!("Copy ({other_index}, {j} = {state:?}) to ({loc:?})");
                 }
-                patch.cells[*i as usize] = state;
+                patch.cells[i.to_usize()] = state;
             }
         }
     }
@@ -97,14 +397,11 @@ where
     Gen: Generation,
     PL: PatchLinks,
 {
-    type Reg = Rc<RefCell<SmallPatch<S, Gen>>>;
-    type Loc = LocationInPatch;
+    type Reg = PatchRef<S, Gen, PL::Size>;
+    type Loc = LocationInPatch<IndexOf<PL::Size>>;
 
     fn regions(&self, generation: &Gen) -> impl IntoIterator<Item = Self::Reg> {
-        self.generations
-            .get(generation)
-            .map(Clone::clone)
-            .unwrap_or_else(|| Vec::new())
+        self.patches(generation).cloned().unwrap_or_else(Vec::new)
     }
 
     fn region<'a>(
@@ -112,106 +409,232 @@ where
         generation: &Gen,
         location: &Self::Loc,
     ) -> Option<std::borrow::Cow<'a, Self::Reg>> {
-        self.generations
-            .get(generation)
+        self.patches(generation)
             .and_then(|patches| patches.get(location.patch))
-            .map(|patch| Cow::Borrowed::<'a>(patch))
+            .map(Cow::Borrowed::<'a>)
     }
 
-    fn update_all(&mut self, generation: &Gen) -> Result<()> {
-        let patches = &self.generations[generation];
-        let next_generation = generation.successor();
-        let mut updated_patches = Vec::new();
-        debug!("Number of patches: [{generation:?}]: {}", patches.len());
-        for patch_index in 0..patches.len() {
-            let patch_ref = &patches[patch_index];
-            let mut patch = patch_ref.borrow_mut();
-            self.stitch(&mut patch, generation);
-            drop(patch); // Drop temporary mutable borrow
-            let patch = patch_ref.borrow();
-            let mut updated_patch = patch.clone();
-            updated_patch.generation = next_generation.clone();
-            debug!("Patch size: {}", patch.size);
-            for i in 0..patch.size {
-                let location = LocationInPatch {
-                    index: i,
-                    patch: patch.index,
+    fn update_all(&mut self, generation: &Gen, run: &Run<S::Parameters>) -> Result<()> {
+        self.update_cells(generation, run, S::update)
+    }
+
+    fn update_all_inverse(&mut self, generation: &Gen, run: &Run<S::Parameters>) -> Result<()>
+    where
+        S: Reversible<Gen>,
+    {
+        self.update_cells(generation, run, S::update_inverse)
+    }
+
+    /// Copies the current generation into the next one and replaces the cells of each block. The blocks consist of
+    /// inner cells, so the edges of the new generation are stitched afterwards.
+    fn update_blocks(
+        &mut self,
+        generation: &Gen,
+        run: &Run<S::Parameters>,
+        blocks: &[Vec<Self::Loc>],
+    ) -> Result<()>
+    where
+        S: BlockRule<Gen>,
+    {
+        let context = run.context(generation);
+        self.advance(generation, |crystal, next, next_generation| {
+            for (patch_ref, updated_ref) in crystal.front.iter().zip(next.iter()) {
+                let mut updated_patch = updated_ref.borrow_mut();
+                updated_patch
+                    .cells
+                    .copy_from_slice(&patch_ref.borrow().cells);
+                updated_patch.generation = next_generation.clone();
+            }
+            let mut states = Vec::new();
+            for block in blocks {
+                let Some(first) = block.first() else {
+                    continue;
                 };
-                if self.patch_links[patch_index]
-                    .effectors()
-                    .iter(i)
-                    .next()
-                    .is_some()
-                {
-                    let new_state = S::update(self, patch_ref, &location)?;
-                    updated_patch.cells[i as usize] = new_state;
+                states.clear();
+                states.extend(block.iter().map(|location| {
+                    crystal.front[location.patch].borrow().cells[location.index.to_usize()]
+                }));
+                S::update_block(&mut states, &context.at(first))?;
+                for (location, state) in block.iter().zip(states.iter()) {
+                    next[location.patch].borrow_mut().cells[location.index.to_usize()] = *state;
                 }
             }
-            updated_patches.push(Rc::new(RefCell::new(updated_patch)));
+            Ok(())
+        })?;
+        let generation = self.front_generation.clone();
+        for patch_ref in self.front.iter() {
+            let mut patch = patch_ref.borrow_mut();
+            self.stitch(&mut patch, &generation);
         }
-        self.generations.insert(next_generation, updated_patches);
         Ok(())
     }
 
+    fn reverse(&mut self, generation: &Gen) -> Result<()> {
+        if *generation != self.front_generation {
+            return Err(anyhow!(
+                "Not the current generation: [{generation:?}]: current: [{:?}]",
+                self.front_generation
+            ));
+        }
+        if self
+            .past
+            .iter()
+            .take(S::LOOKBACK)
+            .any(|(_, past)| past.is_none())
+        {
+            return Err(anyhow!(
+                "Not enough generations to reverse: [{generation:?}]: need {}",
+                S::LOOKBACK
+            ));
+        }
+        let mut sets = std::iter::once(self.front.clone())
+            .chain(
+                self.past
+                    .iter()
+                    .take(S::LOOKBACK)
+                    .map(|(patches, _)| patches.clone()),
+            )
+            .collect::<Vec<_>>();
+        let generations = std::iter::once(self.front_generation.clone())
+            .chain(
+                self.past
+                    .iter()
+                    .take(S::LOOKBACK)
+                    .flat_map(|(_, past)| past.clone()),
+            )
+            .collect::<Vec<_>>();
+        sets.reverse();
+        for (patches, generation) in sets.iter().zip(generations.iter()) {
+            for patch_ref in patches {
+                patch_ref.borrow_mut().generation = generation.clone();
+            }
+            self.retained.remove(generation);
+        }
+        let mut sets = sets.into_iter();
+        if let Some(front) = sets.next() {
+            self.front = front;
+        }
+        for ((past, _), patches) in self.past.iter_mut().zip(sets) {
+            *past = patches;
+        }
+        Ok(())
+    }
+
+    /// Forgets the given generation, unless the rule still looks back at it (see `State::LOOKBACK`).
     fn free(&mut self, generation: &Gen) -> Result<()> {
-        self.generations.remove(generation);
+        if *generation == self.front_generation {
+            return Err(anyhow!(
+                "Cannot free the current generation: [{generation:?}]"
+            ));
+        }
+        for (i, (_, past_generation)) in self.past.iter_mut().enumerate() {
+            if past_generation.as_ref() == Some(generation) {
+                if i < S::LOOKBACK {
+                    self.freed.push(generation.clone());
+                } else {
+                    *past_generation = None;
+                }
+            }
+        }
+        self.retained.remove(generation);
         Ok(())
     }
+
+    /// Keeps a copy of the given generation, so that it survives later updates until it is freed.
+    /// Only the current generation and the previous ones that were not freed yet can be retained.
+    fn retain(&mut self, generation: &Gen) -> Result<()> {
+        let copies = self
+            .patches(generation)
+            .ok_or_else(|| anyhow!("Generation not available: [{generation:?}]"))?
+            .iter()
+            .map(|patch_ref| Rc::new(RefCell::new(patch_ref.borrow().clone())))
+            .collect::<Vec<_>>();
+        self.retained.insert(generation.clone(), copies);
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct Patch<S: State<Gen> + Copy, Gen: Generation, P: PatchSize> {
+    cells: Box<[S]>,
+    index: usize,
+    generation: Gen,
+    size: P::Index,
+    total_size: P::Index, // Includes edges
+}
+
+impl<S, Gen, P> Patch<S, Gen, P>
+where
+    S: State<Gen> + Copy,
+    Gen: Generation,
+    P: PatchSize,
+{
+    pub fn new_init(init: S, index: usize, generation: Gen) -> Self {
+        Patch {
+            cells: vec![init; P::CAPACITY].into_boxed_slice(),
+            index,
+            generation,
+            size: P::Index::default(),
+            total_size: P::Index::default(),
+        }
+    }
 }
 
 macro_rules! patch {
-    ($name:ident { cells: [$index_type:ty; $size:expr] }) => {
+    ($name:ident { cells: [$index_type:ty; $size:expr], sqrt: $sqrt:expr }) => {
         paste! {
-            const [< $name:snake:upper _PATCH_SIZE >]: $index_type = $size;
+            pub const [< $name:snake:upper _PATCH_SIZE >]: $index_type = $size;
 
-            type [<$name IndexType>] = $index_type;
+            pub type [<$name IndexType>] = $index_type;
 
-            #[derive(Clone)]
-            pub struct [<$name Patch>]<S: State<Gen> + Copy, Gen: Generation> {
-                cells: [S; [< $name:snake:upper _PATCH_SIZE >] as usize],
-                index: usize,
-                generation: Gen,
-                size: $index_type,
-                total_size: $index_type, // Includes edges
-            }
+            #[derive(Clone, Copy, Debug, Default)]
+            pub struct $name;
 
-            impl<S, Gen> [<$name Patch>]<S, Gen>
-            where
-                S: State<Gen> + Copy,
-                Gen: Generation,
-            {
-                pub fn new_init(init: S, index: usize, generation: Gen) -> Self {
-                    [<$name Patch>] {
-                        cells: [init; [< $name:snake:upper _PATCH_SIZE >] as usize],
-                        index,
-                        generation,
-                        size: 0,
-                        total_size: 0,
-                    }
-                }
+            impl PatchSize for $name {
+                type Index = $index_type;
+                const CAPACITY: usize = $size;
+                const SQRT: usize = $sqrt;
             }
 
+            pub type [<$name Patch>]<S, Gen> = Patch<S, Gen, $name>;
         }
     };
 }
 
 patch! {
     Small {
-        cells: [u16; 0x3FF]
+        cells: [u16; 0x3FF],
+        sqrt: 17
     }
 }
 
-impl<Spc, S, Gen> Region<Spc, S, Gen> for Rc<RefCell<SmallPatch<S, Gen>>>
+patch! {
+    Medium {
+        cells: [u16; 0x1000],
+        sqrt: 34
+    }
+}
+
+patch! {
+    Large {
+        cells: [u32; 0xFFFF],
+        sqrt: 136
+    }
+}
+
+impl<Spc, S, Gen, P> Region<Spc, S, Gen> for PatchRef<S, Gen, P>
 where
-    Spc: Space<S, Gen, Loc = LocationInPatch>,
+    Spc: Space<S, Gen, Loc = LocationInPatch<P::Index>>,
     S: State<Gen> + Copy,
     Gen: Generation,
+    P: PatchSize,
 {
     fn locations(&self) -> impl IntoIterator<Item = Spc::Loc> {
         let patch = self.borrow();
         AllLocationsInPatchIterator {
-            inner: 0..patch.size,
+            inner: 0..patch.size.to_usize(),
             patch: patch.index,
+            _index: Default::default(),
         }
     }
 
@@ -223,17 +646,18 @@ where
         let i = location.index;
         let region = self.borrow();
         if i < region.total_size {
-            Some(region.cells[location.index as usize])
+            Some(region.cells[i.to_usize()])
         } else {
             None
         }
     }
 }
 
-impl<S, Gen> Debug for SmallPatch<S, Gen>
+impl<S, Gen, P> Debug for Patch<S, Gen, P>
 where
     S: State<Gen> + Copy,
     Gen: Generation,
+    P: PatchSize,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Patch")
@@ -242,68 +666,99 @@ where
             .finish()
     }
 }
-pub struct LocationInPatch {
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LocationInPatch<I: PatchIndex = SmallIndexType> {
     patch: usize,
-    index: SmallIndexType,
+    index: I,
 }
 
-impl Debug for LocationInPatch {
+impl<I: PatchIndex> Debug for LocationInPatch<I> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!("[PatchLoc:{}#{}]", self.patch, self.index))
     }
 }
 
-impl<S, Gen, PL> Location<Crystal<S, Gen, PL>, S, Gen> for LocationInPatch
+impl<S, Gen, PL> Location<Crystal<S, Gen, PL>, S, Gen> for LocationInPatch<IndexOf<PL::Size>>
 where
     S: State<Gen> + Copy,
     Gen: Generation,
     PL: PatchLinks,
 {
     fn effectors(&self, space: &Crystal<S, Gen, PL>) -> Result<impl IntoIterator<Item = Self>> {
-        let patch_effectors = &space.patch_links[self.patch].effectors();
-        let cell_effectors = patch_effectors
+        let patch = self.patch;
+        let cell_effectors = space.patch_links[patch]
+            .effectors()
             .iter(self.index)
-            .map(|i| LocationInPatch {
-                index: i,
-                patch: self.patch,
-            })
-            .collect::<Vec<Self>>();
-        // debug!(
-        //     "Effectors: [{}]: [{}]: [{}]: {:?}.",
-        //     self.patch,
-        //     self.index,
-        //     cell_effectors.len(),
-        //     cell_effectors.iter().map(|l| l.index).collect::<Vec<u8>>()
-        // );
+            .map(move |index| LocationInPatch { index, patch });
+        Ok(cell_effectors)
+    }
+
+    fn weighted_effectors(
+        &self,
+        space: &Crystal<S, Gen, PL>,
+    ) -> Result<impl IntoIterator<Item = (Self, Weight)>> {
+        let patch = self.patch;
+        let cell_effectors = space.patch_links[patch]
+            .effectors()
+            .weighted(self.index)
+            .map(move |(index, weight)| (LocationInPatch { index, patch }, weight));
         Ok(cell_effectors)
     }
 
     fn id(&self, space: &Crystal<S, Gen, PL>) -> String {
-        if log_enabled!(log::Level::Trace) {
-            if let Some((op, oi)) = space
+        if log_enabled!(log::Level::Trace)
+            && let Some((op, oi)) = space
                 .patch_links
                 .get(self.patch)
                 .and_then(|pl| pl.edges().get(&self.index))
-            {
-                return format!("<{}#{}> ~ <{}#{}>", self.patch, self.index, op, oi);
-            }
+        {
+            return format!("<{}#{}> ~ <{}#{}>", self.patch, self.index, op, oi);
         }
         format!("<{}#{}>", self.patch, self.index)
     }
+
+    fn canonical(&self, space: &Crystal<S, Gen, PL>) -> Self {
+        space.patch_links[self.patch]
+            .edges()
+            .get(&self.index)
+            .map(|(patch, index)| LocationInPatch {
+                patch: *patch,
+                index: *index,
+            })
+            .unwrap_or(*self)
+    }
+
+    fn neighbourhood(
+        &self,
+        space: &Crystal<S, Gen, PL>,
+        radius: usize,
+    ) -> Result<Neighbourhood<Self>> {
+        let key = (*self, radius);
+        if let Some(neighbourhood) = space.neighbourhoods.borrow().get(&key) {
+            return Ok(neighbourhood.clone());
+        }
+        let neighbourhood = find_neighbourhood(self, space, radius)?;
+        space
+            .neighbourhoods
+            .borrow_mut()
+            .insert(key, neighbourhood.clone());
+        Ok(neighbourhood)
+    }
 }
 
-pub struct AllLocationsInPatchIterator {
-    inner: Range<SmallIndexType>,
+pub struct AllLocationsInPatchIterator<I: PatchIndex> {
+    inner: Range<usize>,
     patch: usize,
+    _index: PhantomData<I>,
 }
 
-impl Iterator for AllLocationsInPatchIterator {
-    type Item = LocationInPatch;
+impl<I: PatchIndex> Iterator for AllLocationsInPatchIterator<I> {
+    type Item = LocationInPatch<I>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(i) = self.inner.next() {
             Some(LocationInPatch {
-                index: i,
+                index: I::from_usize(i),
                 patch: self.patch,
             })
         } else {
@@ -312,92 +767,69 @@ impl Iterator for AllLocationsInPatchIterator {
     }
 }
 
-pub struct EffectorIterator<'a> {
-    effectors: &'a [SmallIndexType],
-    pos: usize,
-    to_go: SmallIndexType,
-}
-
-impl<'a> Iterator for EffectorIterator<'a> {
-    type Item = SmallIndexType;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.to_go < 1 || self.effectors[self.pos] == SMALL_PATCH_SIZE {
-            None
-        } else {
-            let pos = self.pos;
-            self.pos += 1;
-            self.to_go -= 1;
-            Some(self.effectors[pos])
-        }
-    }
-}
-
-pub trait Effectors: Default {
-    fn iter<'a>(&'a self, index: SmallIndexType) -> EffectorIterator<'a>;
-    fn add(
-        &mut self,
-        index: SmallIndexType,
-        effector_index: SmallIndexType,
-    ) -> Result<SmallIndexType>;
-    fn debug<S: AsRef<str>>(&self, label: S);
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        torus::{Tiling, Torus},
+        wave::{Integrator, LeapfrogScheme, VelocityScheme, Wave},
+    };
 
-#[derive(Clone)]
-pub struct AtMostSixEffectors {
-    effector_counts: [SmallIndexType; SMALL_PATCH_SIZE as usize],
-    effectors: [SmallIndexType; 6 * SMALL_PATCH_SIZE as usize],
-}
+    type Hexagons<K> = PatchTorus<Wave<K>, usize, TorusPatchLinks<CsrEffectors>>;
 
-impl Default for AtMostSixEffectors {
-    fn default() -> Self {
-        let mut result = Self {
-            effector_counts: [0; SMALL_PATCH_SIZE as usize],
-            effectors: [SMALL_PATCH_SIZE; 6 * SMALL_PATCH_SIZE as usize],
-        };
-        for i in 0..6 {
-            result.effectors[i] = 0;
-        }
-        result
+    fn torus<K: Integrator>() -> Result<Hexagons<K>> {
+        new_patch_torus(Tiling::Hexagons, Wave::new(0.0, false), 0usize, 8, 8)
     }
-}
 
-impl Effectors for AtMostSixEffectors {
-    fn iter<'a>(&'a self, index: SmallIndexType) -> EffectorIterator<'a> {
-        EffectorIterator {
-            effectors: &self.effectors,
-            pos: 6 * index as usize,
-            to_go: self.effector_counts[index as usize],
-        }
+    /// The generations up to the given one that the space can return.
+    fn readable<S: State<usize> + Copy, PL: PatchLinks>(
+        crystal: &Crystal<S, usize, PL>,
+        last: usize,
+    ) -> Vec<usize> {
+        (0..=last)
+            .filter(|generation| crystal.patches(generation).is_some())
+            .collect()
     }
 
-    fn add(
-        &mut self,
-        index: SmallIndexType,
-        effector_index: SmallIndexType,
-    ) -> Result<SmallIndexType> {
-        let i = index as usize;
-        let n = self.effector_counts[i] as usize;
-        let base = 6 * i;
-        for k in base..(base + n) {
-            if self.effectors[k] == effector_index {
-                return Ok(self.effector_counts[i]);
-            }
-        }
-        if n >= 6 {
-            return Err(anyhow!("Cannot add more than 6 effectors"));
-        }
-        self.effectors[base + n] = effector_index;
-        self.effector_counts[i] += 1;
-        Ok(self.effector_counts[i])
+    #[test]
+    fn keeps_the_current_and_retained_generations() -> Result<()> {
+        let mut torus = torus::<VelocityScheme>()?;
+        let run = Run::default();
+        let crystal = torus.space_mut();
+        crystal.update_all(&0, &run)?;
+        assert_eq!(readable(crystal, 5), [0, 1]);
+        crystal.free(&0)?;
+        assert_eq!(readable(crystal, 5), [1]);
+        crystal.update_all(&1, &run)?;
+        crystal.retain(&1)?;
+        crystal.update_all(&2, &run)?;
+        assert_eq!(readable(crystal, 5), [1, 2, 3]);
+        crystal.free(&2)?;
+        assert_eq!(readable(crystal, 5), [1, 3]);
+        crystal.free(&1)?;
+        assert_eq!(readable(crystal, 5), [3]);
+        assert!(crystal.free(&3).is_err());
+        assert!(crystal.update_all(&2, &run).is_err());
+        Ok(())
     }
 
-    fn debug<S: AsRef<str>>(&self, label: S) {
-        for i in 0..self.effector_counts.len() {
-            let count = self.effector_counts[i];
-            if count > 0 {
-                debug!("{}: {i}: {count}", label.as_ref());
-            }
-        }
+    #[test]
+    fn keeps_the_generations_that_the_rule_looks_back_at() -> Result<()> {
+        let mut torus = torus::<LeapfrogScheme>()?;
+        let run = Run::default();
+        let crystal = torus.space_mut();
+        crystal.update_all(&0, &run)?;
+        crystal.free(&0)?;
+        assert_eq!(readable(crystal, 5), [0, 1]);
+        crystal.update_all(&1, &run)?;
+        crystal.free(&1)?;
+        assert_eq!(readable(crystal, 5), [1, 2]);
+        crystal.retain(&1)?;
+        crystal.update_all(&2, &run)?;
+        crystal.free(&2)?;
+        crystal.update_all(&3, &run)?;
+        crystal.free(&3)?;
+        assert_eq!(readable(crystal, 5), [1, 3, 4]);
+        Ok(())
     }
 }
@@ -1,16 +1,20 @@
 use std::{collections::HashSet, fmt::Display};
 
 use crate::{
-    patch::new_hexagonal_torus,
-    structure::{Generation, Location, Space, State},
-    torus::Torus,
+    patch::{
+        AtMostSixEffectors, CsrEffectors, FixedEffectors, Large, Medium, new_hexagonal_torus,
+        new_patch_torus,
+    },
+    structure::{Generation, Location, Region, Run, Space, State, UpdateContext},
+    torus::{Tiling, Torus},
 };
 
 use anyhow::{Result, anyhow};
 use log::info;
 
+/// Knows how many effectors each cell should have.
 #[derive(Default, Debug, Clone, Copy)]
-struct Trivial;
+struct Trivial(usize);
 
 impl Display for Trivial {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -34,25 +38,70 @@ where
 }
 
 impl State<usize> for Trivial {
+    type Parameters = ();
+
     fn update<Spc: Space<Self, usize>>(
         space: &Spc,
-        _region: &Spc::Reg,
+        region: &Spc::Reg,
         location: &Spc::Loc,
+        _context: &UpdateContext<(), usize>,
     ) -> Result<Self> {
+        let this_state: Self = region.state(location).unwrap_or_default();
         let count = location.effectors(space)?.into_iter().count();
-        if count != 6 && count != 0 {
+        if count != this_state.0 && count != 0 {
             return Err(anyhow!("Wrong count: [{}]", count));
         }
-        Ok(Trivial)
+        Ok(this_state)
     }
 }
 
 pub fn example() -> Result<()> {
     info!("Patch PoC");
-    let mut crystal = new_hexagonal_torus(Trivial::default(), 0usize, 40, 30)?;
     let generation = 0usize;
+    let mut crystal = new_hexagonal_torus(Trivial(6), generation, 40, 30)?;
     crystal.info(&generation);
-    crystal.update_all_cells(&generation)?;
+    crystal.update_all_cells(&generation, &Run::default())?;
+
+    info!("Patch PoC: hexagons in medium and large patches");
+    let mut crystal = new_patch_torus::<_, _, AtMostSixEffectors<Medium>>(
+        Tiling::Hexagons,
+        Trivial(6),
+        generation,
+        100,
+        70,
+    )?;
+    crystal.update_all_cells(&generation, &Run::default())?;
+    let mut crystal = new_patch_torus::<_, _, AtMostSixEffectors<Large>>(
+        Tiling::Hexagons,
+        Trivial(6),
+        generation,
+        300,
+        200,
+    )?;
+    crystal.update_all_cells(&generation, &Run::default())?;
+
+    info!("Patch PoC: orthogonal");
+    let mut crystal =
+        new_patch_torus::<_, _, CsrEffectors>(Tiling::Orthogonal, Trivial(4), generation, 41, 29)?;
+    crystal.update_all_cells(&generation, &Run::default())?;
+
+    info!("Patch PoC: orthogonal and diagonal");
+    let mut crystal = new_patch_torus::<_, _, FixedEffectors<8>>(
+        Tiling::OrthogonalAndDiagonal,
+        Trivial(8),
+        generation,
+        41,
+        29,
+    )?;
+    crystal.update_all_cells(&generation, &Run::default())?;
+    let mut crystal = new_patch_torus::<_, _, CsrEffectors>(
+        Tiling::OrthogonalAndDiagonal,
+        Trivial(8),
+        generation,
+        41,
+        29,
+    )?;
+    crystal.update_all_cells(&generation, &Run::default())?;
 
     Ok(())
 }
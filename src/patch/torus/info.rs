@@ -1,10 +1,10 @@
-use std::{collections::HashMap, iter::repeat};
+use std::iter::repeat;
 
 use log::info;
 
 use crate::{
     patch::{
-        AtMostSixEffectors, Effectors, SMALL_PATCH_SIZE, SmallIndexType,
+        Edges, Effectors, IndexOf, PatchIndex, PatchSize,
         torus::{PatchLinks, TorusPatchLinks, calculate_grid, prepare_shuffle},
     },
     structure::{Generation, State},
@@ -12,36 +12,36 @@ use crate::{
 
 use super::PatchTorus;
 
-pub fn info_hexagons<S, Gen>(torus: &PatchTorus<S, Gen, TorusPatchLinks>)
+pub fn info_hexagons<S, Gen, E>(torus: &PatchTorus<S, Gen, TorusPatchLinks<E>>)
 where
     S: State<Gen> + Copy,
     Gen: Generation,
+    E: Effectors,
 {
     info!("# Crystal hexagons info");
     info!("");
     let crystal = &torus.crystal;
     let width = torus.dimensions[0];
     let height = torus.dimensions[1];
-    let (w, h) = calculate_grid(width, height);
+    let (w, h) = calculate_grid::<E::Size>(width, height);
+    let none = E::Size::CAPACITY;
     let wide = w > 1;
     let tall = h > 1;
     for i in 0..crystal.patch_count() {
         let patch_links = &crystal.patch_links[i];
         info!("## Patch: {i}: edges");
-        let projections = |e: &HashMap<SmallIndexType, (usize, SmallIndexType)>, x, y, w| {
+        let projections = |e: &Edges<E::Size>, x, y, w| {
+            let key = IndexOf::<E::Size>::from_usize(index(x, y, w));
             vec![
                 index(x, y, w),
-                e.get(&index(x, y, w))
-                    .map(|v| v.0)
-                    .unwrap_or(SMALL_PATCH_SIZE as usize) as SmallIndexType,
-                e.get(&index(x, y, w))
-                    .map(|v| v.1)
-                    .unwrap_or(SMALL_PATCH_SIZE),
+                e.get(&key).map(|v| v.0).unwrap_or(none),
+                e.get(&key).map(|v| v.1.to_usize()).unwrap_or(none),
             ]
         };
         info_hexagon(
             patch_links.edges(),
             3,
+            none,
             &projections,
             patch_links.total_width,
             patch_links.total_height,
@@ -55,17 +55,18 @@ where
             wide,
             tall,
         );
-        let projections = |e: &AtMostSixEffectors, x, y, w| {
+        let projections = |e: &E, x, y, w| {
             let index = shuffle(index(x, y, w));
             let mut result = vec![index];
-            for effector in e.iter(index) {
-                result.push(effector);
+            for effector in e.iter(IndexOf::<E::Size>::from_usize(index)) {
+                result.push(effector.to_usize());
             }
             result
         };
         info_hexagon(
             patch_links.effectors(),
             7,
+            none,
             &projections,
             patch_links.total_width,
             patch_links.total_height,
@@ -78,9 +79,10 @@ where
 fn info_hexagon<C>(
     context: &C,
     projection_count: u8,
-    projections: &impl Fn(&C, SmallIndexType, SmallIndexType, SmallIndexType) -> Vec<SmallIndexType>,
-    width: SmallIndexType,
-    height: SmallIndexType,
+    ko: usize,
+    projections: &impl Fn(&C, usize, usize, usize) -> Vec<usize>,
+    width: usize,
+    height: usize,
     even: bool,
 ) {
     let mut indent = even;
@@ -89,7 +91,6 @@ fn info_hexagon<C>(
         let xx = format!("  {x:02x}");
         header.push_str(&xx);
     }
-    let ko = SMALL_PATCH_SIZE;
     info!("{}", header);
     info!("");
     for y in 0..height {
@@ -119,6 +120,6 @@ fn info_hexagon<C>(
     }
 }
 
-fn index(x: SmallIndexType, y: SmallIndexType, w: SmallIndexType) -> SmallIndexType {
+fn index(x: usize, y: usize, w: usize) -> usize {
     y * w + x
 }
@@ -1,15 +1,16 @@
 mod info;
 
 use anyhow::{Result, anyhow};
+use clap::ValueEnum;
 use log::{debug, warn};
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{mem::size_of, rc::Rc};
 
 use crate::{
     patch::{
-        AtMostSixEffectors, Effectors, LocationInPatch, PatchLinks, SMALL_PATCH_SIZE,
-        SmallIndexType, SmallPatch,
+        AtMostSixEffectors, Edges, Effectors, IndexOf, LocationInPatch, Medium, PatchIndex,
+        PatchLinks, PatchRef, PatchSize, Small,
     },
-    structure::{Generation, Space, State},
+    structure::{Generation, Run, Space, State, Weight},
     torus::{Tiling, Torus},
 };
 use info::info_hexagons;
@@ -24,30 +25,40 @@ pub struct PatchTorus<S: State<Gen> + Copy, Gen: Generation, PL: PatchLinks> {
 }
 
 #[derive(Default)]
-pub struct TorusPatchLinks {
-    effectors: AtMostSixEffectors,
-    edges: HashMap<SmallIndexType, (usize, SmallIndexType)>,
-    total_width: SmallIndexType,
-    total_height: SmallIndexType,
-    inner_width: SmallIndexType,
-    inner_height: SmallIndexType,
+pub struct TorusPatchLinks<E: Effectors = AtMostSixEffectors> {
+    effectors: E,
+    edges: Edges<E::Size>,
+    total_width: usize,
+    total_height: usize,
+    inner_width: usize,
+    inner_height: usize,
     even: bool,
 }
 
-impl PatchLinks for TorusPatchLinks {
-    type Eff = AtMostSixEffectors;
+impl<E: Effectors> PatchLinks for TorusPatchLinks<E> {
+    type Size = E::Size;
+    type Eff = E;
 
     fn effectors(&self) -> &Self::Eff {
         &self.effectors
     }
 
-    fn edges(&self) -> &std::collections::HashMap<SmallIndexType, (usize, SmallIndexType)> {
+    fn effectors_mut(&mut self) -> &mut Self::Eff {
+        &mut self.effectors
+    }
+
+    fn edges(&self) -> &Edges<E::Size> {
         &self.edges
     }
 }
 
-impl<S: State<Gen> + Copy, Gen: Generation> Torus<S, Gen> for PatchTorus<S, Gen, TorusPatchLinks> {
-    type Spc = Crystal<S, Gen, TorusPatchLinks>;
+impl<S, Gen, E> Torus<S, Gen> for PatchTorus<S, Gen, TorusPatchLinks<E>>
+where
+    S: State<Gen> + Copy,
+    Gen: Generation,
+    E: Effectors,
+{
+    type Spc = Crystal<S, Gen, TorusPatchLinks<E>>;
 
     fn space(&self) -> &Self::Spc {
         &self.crystal
@@ -65,8 +76,8 @@ impl<S: State<Gen> + Copy, Gen: Generation> Torus<S, Gen> for PatchTorus<S, Gen,
         }
     }
 
-    fn update_all_cells(&mut self, generation: &Gen) -> Result<()> {
-        self.crystal.update_all(generation)
+    fn update_all_cells(&mut self, generation: &Gen, run: &Run<S::Parameters>) -> Result<()> {
+        self.crystal.update_all(generation, run)
     }
 
     fn tiling(&self) -> Tiling {
@@ -78,56 +89,156 @@ impl<S: State<Gen> + Copy, Gen: Generation> Torus<S, Gen> for PatchTorus<S, Gen,
     }
 
     fn adjust(&mut self, generation: &Gen, x: usize, y: usize, state: S) -> Result<()> {
-        let mut p = 0;
-        let mut px = x;
-        while self.crystal.patch_links[p].inner_width as usize <= px {
-            px -= self.crystal.patch_links[p].inner_width as usize;
-            p += 1;
-        }
-        let (w, _) = calculate_grid(self.dimensions[0], self.dimensions[1]);
-        let mut py = y;
-        while self.crystal.patch_links[p].inner_height as usize <= py {
-            py -= self.crystal.patch_links[p].inner_height as usize;
-            p += w;
-        }
-        let patch_ref = &self.crystal.generations[generation][p];
+        let (p, pi) = self.locate(x, y)?;
+        let patch_ref = &self
+            .crystal
+            .patches(generation)
+            .ok_or_else(|| anyhow!("Generation not available: [{generation:?}]"))?[p];
         let mut patch = patch_ref.borrow_mut();
-        let pi =
-            py as SmallIndexType * self.crystal.patch_links[p].inner_width + px as SmallIndexType;
-        patch.cells[pi as usize] = state;
+        patch.cells[pi.to_usize()] = state;
         Ok(())
     }
 
     fn coordinates(
         &self,
-        patch_ref: &Rc<RefCell<SmallPatch<S, Gen>>>,
-        location: &LocationInPatch,
+        patch_ref: &PatchRef<S, Gen, E::Size>,
+        location: &LocationInPatch<IndexOf<E::Size>>,
     ) -> (usize, usize) {
+        self.position(patch_ref.borrow().index, location.index)
+    }
+}
+
+impl<S, Gen, E> PatchTorus<S, Gen, TorusPatchLinks<E>>
+where
+    S: State<Gen> + Copy,
+    Gen: Generation,
+    E: Effectors,
+{
+    /// Disconnects the cell at the given coordinates from its neighbours.
+    pub fn add_vacancy(&mut self, x: usize, y: usize) -> Result<()> {
+        let (p, pi) = self.locate(x, y)?;
+        self.crystal.add_vacancy(p, pi)
+    }
+
+    /// Sets the weight of each link of the cell at the given coordinates.
+    /// The weight is computed from the coordinates of the effector at the other end of the link.
+    pub fn set_weights<F>(&mut self, x: usize, y: usize, weight: F) -> Result<()>
+    where
+        F: Fn(usize, usize) -> Weight,
+    {
+        let (p, pi) = self.locate(x, y)?;
+        let patch_links = &self.crystal.patch_links[p];
+        let weights = patch_links
+            .effectors
+            .iter(pi)
+            .map(|effector| {
+                let (ep, ei) = patch_links
+                    .edges
+                    .get(&effector)
+                    .copied()
+                    .unwrap_or((p, effector));
+                let (ex, ey) = self.position(ep, ei);
+                (effector, weight(ex, ey))
+            })
+            .collect::<Vec<_>>();
+        for (effector, weight) in weights {
+            self.crystal.set_effector_weight(p, pi, effector, weight)?;
+        }
+        Ok(())
+    }
+
+    /// Finds the coordinates of the cell with the given index in the inner part of the given patch.
+    fn position(&self, i: usize, index: IndexOf<E::Size>) -> (usize, usize) {
         let patch_links = &self.crystal.patch_links;
-        let patch = patch_ref.borrow();
-        let i = patch.index;
         let w = self.patch_grid[0];
         let r = i / w;
         let c = i % w;
-        let mut left_x = 0;
-        for p in 0..c {
-            left_x += patch_links[p].inner_width as usize;
-        }
+        let left_x: usize = patch_links[..c].iter().map(|pl| pl.inner_width).sum();
         let mut top_y = 0;
         let mut top_i = 0;
         for _ in 0..r {
-            top_y += patch_links[top_i].inner_height as usize;
+            top_y += patch_links[top_i].inner_height;
             top_i += w;
         }
         let pw = patch_links[i].inner_width;
-        let li = location.index;
+        let li = index.to_usize();
         let ly = li / pw;
         let lx = li % pw;
-        (left_x + lx as usize, top_y + ly as usize)
+        (left_x + lx, top_y + ly)
+    }
+
+    /// Finds the patch and the index within that patch of the cell at the given coordinates.
+    fn locate(&self, x: usize, y: usize) -> Result<(usize, IndexOf<E::Size>)> {
+        if x >= self.dimensions[0] || y >= self.dimensions[1] {
+            return Err(anyhow!(
+                "Coordinates outside the torus: ({x}, {y}): {:?}",
+                self.dimensions
+            ));
+        }
+        let patch_links = &self.crystal.patch_links;
+        let mut p = 0;
+        let mut px = x;
+        while patch_links[p].inner_width <= px {
+            px -= patch_links[p].inner_width;
+            p += 1;
+        }
+        let w = self.patch_grid[0];
+        let mut py = y;
+        while patch_links[p].inner_height <= py {
+            py -= patch_links[p].inner_height;
+            p += w;
+        }
+        let pi = py * patch_links[p].inner_width + px;
+        Ok((p, IndexOf::<E::Size>::from_usize(pi)))
     }
 }
 
-const SQRT_PATCH_SIZE: u8 = 17;
+/// The patch sizes that can be chosen at run time.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum PatchSizeChoice {
+    Small,
+    Medium,
+    /// Never chosen by default, because it does not fit in the cache.
+    Large,
+}
+
+/// The part of the cache that the cells and effectors of a patch may occupy.
+const CACHE_BUDGET: usize = 512 * 1024;
+
+/// Picks the largest patch size for which the previous and next generation of a patch, together with its effectors, fit in the cache budget.
+/// A larger size is never picked if a smaller size already covers the entire torus with a single patch.
+/// Only `Small` and `Medium` are considered: a `Large` patch has 0xFFFF cells with four-byte indices, so it needs several megabytes for any state and never fits the budget.
+/// `Large` is only used when it is asked for explicitly.
+pub fn plan_patch_size<S>(width: usize, height: usize, max_effectors: usize) -> PatchSizeChoice {
+    let footprint = |capacity: usize, index_size: usize| {
+        capacity
+            * (2 * size_of::<S>() + index_size + max_effectors * (index_size + size_of::<Weight>()))
+    };
+    let candidates = [
+        (
+            PatchSizeChoice::Small,
+            Small::CAPACITY,
+            size_of::<IndexOf<Small>>(),
+        ),
+        (
+            PatchSizeChoice::Medium,
+            Medium::CAPACITY,
+            size_of::<IndexOf<Medium>>(),
+        ),
+    ];
+    let mut choice = PatchSizeChoice::Small;
+    for (candidate, capacity, index_size) in candidates {
+        if footprint(capacity, index_size) > CACHE_BUDGET {
+            break;
+        }
+        choice = candidate;
+        if width * height <= capacity {
+            break;
+        }
+    }
+    debug!("Patch size for ({width} x {height}): [{choice:?}]");
+    choice
+}
 
 pub fn new_hexagonal_torus<S: State<Gen> + Copy, Gen: Generation>(
     init: S,
@@ -135,39 +246,81 @@ pub fn new_hexagonal_torus<S: State<Gen> + Copy, Gen: Generation>(
     width: usize,
     height: usize,
 ) -> Result<PatchTorus<S, Gen, TorusPatchLinks>> {
-    if width % 2 == 1 || height % 2 == 1 {
-        return Err(anyhow!("Must both be even: ({width}, {height})"));
-    }
+    new_patch_torus(Tiling::Hexagons, init, initial_gen, width, height)
+}
+
+/// Creates a two-dimensional torus of patches. The effectors type `E` must have room for the number of neighbours of the tiling:
+/// four for `Orthogonal`, six for `Hexagons` and eight for `OrthogonalAndDiagonal`.
+pub fn new_patch_torus<S, Gen, E>(
+    tiling: Tiling,
+    init: S,
+    initial_gen: Gen,
+    width: usize,
+    height: usize,
+) -> Result<PatchTorus<S, Gen, TorusPatchLinks<E>>>
+where
+    S: State<Gen> + Copy,
+    Gen: Generation,
+    E: Effectors,
+{
+    let offsets = match tiling {
+        Tiling::Orthogonal => {
+            let offset_coords = vec![(0, -1), (-1, 0), (1, 0), (0, 1)];
+            Alternatives::new(offset_coords.clone(), offset_coords)
+        }
+        Tiling::OrthogonalAndDiagonal => {
+            let offset_coords = vec![
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ];
+            Alternatives::new(offset_coords.clone(), offset_coords)
+        }
+        Tiling::Hexagons => {
+            if width % 2 == 1 || height % 2 == 1 {
+                return Err(anyhow!("Must both be even: ({width}, {height})"));
+            }
+            let even_offset_coords = vec![(0, -1), (1, -1), (-1, 0), (1, 0), (0, 1), (1, 1)];
+            let odd_offset_coords = vec![(-1, -1), (0, -1), (-1, 0), (1, 0), (-1, 1), (0, 1)];
+            Alternatives::new(even_offset_coords, odd_offset_coords)
+        }
+        _ => return Err(anyhow!("Tiling not supported by PatchTorus: [{tiling:?}]")),
+    };
     let dimensions = vec![width, height];
     let patch_links_factory = || TorusPatchLinks::default();
-    let (w, h) = calculate_grid(width, height);
+    let (w, h) = calculate_grid::<E::Size>(width, height);
     let patch_grid = vec![w, h];
     let mut crystal = Crystal::new(w * h, &initial_gen, init, patch_links_factory);
-    connect_cells_hexagonally(&mut crystal, width, w, height, h, &initial_gen)?;
+    connect_cells(&mut crystal, width, w, height, h, offsets)?;
     Ok(PatchTorus {
         crystal,
         dimensions,
         patch_grid,
-        tiling: Tiling::Hexagons,
+        tiling,
     })
 }
 
-fn calculate_grid(width: usize, height: usize) -> (usize, usize) {
+fn calculate_grid<P: PatchSize>(width: usize, height: usize) -> (usize, usize) {
     if width >= height {
-        calculate_oblong(width, height)
+        calculate_oblong::<P>(width, height)
     } else {
-        let (v, h) = calculate_oblong(height, width);
+        let (v, h) = calculate_oblong::<P>(height, width);
         (h, v)
     }
 }
 
-fn calculate_oblong(long: usize, short: usize) -> (usize, usize) {
-    let sps = SQRT_PATCH_SIZE as usize;
+fn calculate_oblong<P: PatchSize>(long: usize, short: usize) -> (usize, usize) {
+    let sps = P::SQRT;
     let sm = (short + sps) / (sps + 1);
     let mut s = sm;
-    let (mut l, mut q, mut e) = calculate_footprint(long, short, sm);
+    let (mut l, mut q, mut e) = calculate_footprint::<P>(long, short, sm);
     if s > 1 {
-        let (la, qa, ea) = calculate_footprint(long, short, sm - 1);
+        let (la, qa, ea) = calculate_footprint::<P>(long, short, sm - 1);
         if qa < q || (qa == q && ea < e) {
             s = sm - 1;
             l = la;
@@ -175,7 +328,7 @@ fn calculate_oblong(long: usize, short: usize) -> (usize, usize) {
             e = ea;
         }
     }
-    let (lb, qb, eb) = calculate_footprint(long, short, sm + 1);
+    let (lb, qb, eb) = calculate_footprint::<P>(long, short, sm + 1);
     if qb < q || (qb == q && eb < e) {
         s = sm + 1;
         l = lb;
@@ -184,10 +337,10 @@ fn calculate_oblong(long: usize, short: usize) -> (usize, usize) {
     (l, s)
 }
 
-fn calculate_footprint(long: usize, short: usize, s: usize) -> (usize, usize, usize) {
+fn calculate_footprint<P: PatchSize>(long: usize, short: usize, s: usize) -> (usize, usize, usize) {
     let sd = if s > 1 { 2 } else { 0 };
-    let sx = sd + (short + s - 1) / s;
-    let lx = (SMALL_PATCH_SIZE as usize) / sx;
+    let sx = sd + short.div_ceil(s);
+    let lx = P::CAPACITY / sx;
     let ld = if lx < long { 2 } else { 0 };
     let l = (long + lx - ld - 1) / (lx - ld);
     let mut edge = 0;
@@ -210,27 +363,25 @@ fn calculate_footprint(long: usize, short: usize, s: usize) -> (usize, usize, us
     footprint
 }
 
-fn connect_cells_hexagonally<S, Gen>(
-    crystal: &mut Crystal<S, Gen, TorusPatchLinks>,
+fn connect_cells<S, Gen, E>(
+    crystal: &mut Crystal<S, Gen, TorusPatchLinks<E>>,
     width: usize,
     w: usize,
     height: usize,
     h: usize,
-    generation: &Gen,
+    even_offsets: Alternatives,
 ) -> Result<()>
 where
     S: State<Gen> + Copy,
     Gen: Generation,
+    E: Effectors,
 {
     debug!(
         "Connect cells: [{}]: ([{width}] / [{w}]) x ([{height}] / [{h}])",
         crystal.patch_count()
     );
 
-    let even_offset_coords = vec![(0, -1), (1, -1), (-1, 0), (1, 0), (0, 1), (1, 1)];
-    let odd_offset_coords = vec![(-1, -1), (0, -1), (-1, 0), (1, 0), (-1, 1), (0, 1)];
-    let even_offsets = Alternatives::new(even_offset_coords, odd_offset_coords);
-
+    let index = IndexOf::<E::Size>::from_usize;
     let patch_grid = PatchGrid::new(width, w, height, h);
     let mut cell_rows_before = 0;
     let mut br = 0;
@@ -244,6 +395,11 @@ where
             let hi = patch_grid.internal_row_height(r);
             let internal_size = wi * hi;
             let even = cell_rows_before & 0x01 == 0; // TODO: is this correct?
+            crystal.set_patch_size(
+                p,
+                IndexOf::<E::Size>::from_usize(internal_size),
+                IndexOf::<E::Size>::from_usize(wc * hr),
+            );
             let patch_links = &mut crystal.patch_links[p];
             patch_links.total_width = wc;
             patch_links.total_height = hr;
@@ -253,13 +409,6 @@ where
             debug!(
                 "Patch: #{p}: [{r}]: [{c}]: ([{wc}] x [{hr}]): [{cell_colums_before}, {cell_rows_before}, {even}]"
             );
-            if let Some(patches) = crystal.generations.get_mut(generation) {
-                if let Some(patch_ref) = patches.get_mut(p) {
-                    let mut patch = patch_ref.borrow_mut();
-                    patch.size = internal_size;
-                    patch.total_size = wc * hr;
-                }
-            }
 
             let shuffle = prepare_shuffle(wc, hr, w > 1, h > 1);
 
@@ -278,7 +427,7 @@ where
                         let xx = (x + ox + wc - 1) % wc;
                         let yy = (y + oy + hr - 1) % hr;
                         let j = (yy * wc) + xx;
-                        effectors.add(shuffle(i), shuffle(j))?;
+                        effectors.add(index(shuffle(i)), index(shuffle(j)))?;
                     }
                 }
                 offsets = offsets.other();
@@ -295,8 +444,14 @@ where
                 let below = (r + 1) % h;
                 let fudge = if w > 1 { 1 } else { 0 };
                 for i in fudge..(wc - fudge) {
-                    edges.insert(shuffle(i), (above * w + c, above_base + i - fudge)); // top to bottom of above
-                    edges.insert(shuffle(this_base + i), (below * w + c, i - fudge)); // bottom to top of below
+                    edges.insert(
+                        index(shuffle(i)),
+                        (above * w + c, index(above_base + i - fudge)),
+                    ); // top to bottom of above
+                    edges.insert(
+                        index(shuffle(this_base + i)),
+                        (below * w + c, index(i - fudge)),
+                    ); // bottom to top of below
                 }
             }
             if w > 1 {
@@ -310,12 +465,12 @@ where
                 let mut offset = fudge * wc;
                 for i in fudge..(hr - fudge) {
                     edges.insert(
-                        shuffle(offset),
-                        (row + left, ((i + 1 - fudge) * left_width) - 1),
+                        index(shuffle(offset)),
+                        (row + left, index(((i + 1 - fudge) * left_width) - 1)),
                     ); // leftmost cell to rightmost cell of patch to the left
                     edges.insert(
-                        shuffle(this_base + offset),
-                        (row + right, (i - fudge) * right_width),
+                        index(shuffle(this_base + offset)),
+                        (row + right, index((i - fudge) * right_width)),
                     ); // rightmost cell to leftmost cell of patch to the right
                     offset += wc;
                 }
@@ -330,10 +485,16 @@ where
                     let tris = patch_grid.internal_size(above, right);
                     let lft_wi = patch_grid.internal_column_width(left);
                     let right_wi = patch_grid.internal_column_width(right);
-                    edges.insert(shuffle(0), (above * w + lft, tlis - 1));
-                    edges.insert(shuffle(wc - 1), (above * w + right, tris - right_wi));
-                    edges.insert(shuffle((hr - 1) * wc), (below * w + lft, lft_wi - 1));
-                    edges.insert(shuffle(hr * wc - 1), (below * w + right, 0));
+                    edges.insert(index(shuffle(0)), (above * w + lft, index(tlis - 1)));
+                    edges.insert(
+                        index(shuffle(wc - 1)),
+                        (above * w + right, index(tris - right_wi)),
+                    );
+                    edges.insert(
+                        index(shuffle((hr - 1) * wc)),
+                        (below * w + lft, index(lft_wi - 1)),
+                    );
+                    edges.insert(index(shuffle(hr * wc - 1)), (below * w + right, index(0)));
                 }
             }
         }
@@ -347,7 +508,7 @@ where
 /// then a two by two patch has edges on both sides as well as corners. It is shuffled as follows:
 ///
 /// Original:
-/// ```
+/// ```text
 /// |  0 |  1 |  2 |  3 |
 /// |  4 |  5 |  6 |  7 |
 /// |  8 |  9 | 10 | 11 |
@@ -355,7 +516,7 @@ where
 /// ```
 ///
 /// Shuffled
-/// ```
+/// ```text
 /// |  5 |  6 |            First row
 /// |  9 | 10 |            Last row
 /// |  0 |  1 |  2 |  3 |  Top edge
@@ -363,14 +524,9 @@ where
 /// |  4 |  8 |            Left edge, minus corners
 /// |  7 | 11 |            Right edge, minus corners
 /// ```
-fn prepare_shuffle(
-    wc: SmallIndexType,
-    hr: SmallIndexType,
-    wide: bool,
-    tall: bool,
-) -> impl Fn(SmallIndexType) -> SmallIndexType {
+fn prepare_shuffle(wc: usize, hr: usize, wide: bool, tall: bool) -> impl Fn(usize) -> usize {
     let p_size = hr * wc;
-    let mut shuffle = [0 as SmallIndexType; SMALL_PATCH_SIZE as usize];
+    let mut shuffle = vec![0; p_size];
     let mut y_start = 0;
     let mut y_end = p_size;
     let mut x_start = 0;
@@ -386,55 +542,53 @@ fn prepare_shuffle(
     let mut i = 0;
     while y_start < y_end {
         for x in x_start..x_end {
-            shuffle[(y_start + x) as usize] = i;
-            i = i + 1;
+            shuffle[y_start + x] = i;
+            i += 1;
         }
         y_start += wc;
     }
     if tall {
-        for x in 0..wc {
-            shuffle[x as usize] = i;
+        for s in shuffle[..wc].iter_mut() {
+            *s = i;
             i += 1;
         }
-        for x in 0..wc {
-            shuffle[(y_end + x) as usize] = i;
+        for s in shuffle[y_end..y_end + wc].iter_mut() {
+            *s = i;
             i += 1;
         }
     }
     if wide {
         let fy = if tall { 1 } else { 0 };
         for y in (fy)..(hr - fy) {
-            shuffle[(y * wc) as usize] = i;
+            shuffle[y * wc] = i;
             i += 1;
         }
         for y in (fy)..(hr - fy) {
-            shuffle[(y * wc + wc - 1) as usize] = i;
+            shuffle[y * wc + wc - 1] = i;
             i += 1;
         }
     }
     assert!(i == p_size);
-    move |i| shuffle[i as usize]
+    move |i| shuffle[i]
 }
 
 struct PatchGrid {
-    wi: SmallIndexType, // Base internal width of patches
-    wp: SmallIndexType, // Base width of patches
-    wq: usize,          // Number of patch columns that are one cell wider
-    hi: SmallIndexType, // Base internal height of patches
-    hp: SmallIndexType, // Base height of patches
-    hq: usize,          // Number of patch rows that are one cell wider
+    wi: usize, // Base internal width of patches
+    wp: usize, // Base width of patches
+    wq: usize, // Number of patch columns that are one cell wider
+    hi: usize, // Base internal height of patches
+    hp: usize, // Base height of patches
+    hq: usize, // Number of patch rows that are one cell wider
 }
 
 impl PatchGrid {
     fn new(width: usize, w: usize, height: usize, h: usize) -> Self {
-        let wi = (width / w) as SmallIndexType; // With of a small patch
-        let wq = width - w * (wi as usize); // Number of collums that are one cell wider
+        let wi = width / w; // With of a small patch
+        let wq = width - w * wi; // Number of collums that are one cell wider
         let wp = if w > 1 { wi + 2 } else { wi };
-        let wp = wp as SmallIndexType;
-        let hi = (height / h) as SmallIndexType; // Height of a small patch
-        let hq = height - h * (hi as usize); // Number of rows that are one cell taller
+        let hi = height / h; // Height of a small patch
+        let hq = height - h * hi; // Number of rows that are one cell taller
         let hp = if h > 1 { hi + 2 } else { hi };
-        let hp = hp as SmallIndexType;
         PatchGrid {
             wi,
             wp,
@@ -445,30 +599,30 @@ impl PatchGrid {
         }
     }
 
-    fn internal_row_height(&self, r: usize) -> SmallIndexType {
+    fn internal_row_height(&self, r: usize) -> usize {
         self.hi + (if r < self.hq { 1 } else { 0 })
     }
 
-    fn row_height(&self, r: usize) -> SmallIndexType {
+    fn row_height(&self, r: usize) -> usize {
         self.hp + (if r < self.hq { 1 } else { 0 })
     }
 
-    fn internal_column_width(&self, c: usize) -> SmallIndexType {
+    fn internal_column_width(&self, c: usize) -> usize {
         self.wi + (if c < self.wq { 1 } else { 0 })
     }
 
-    fn column_width(&self, c: usize) -> SmallIndexType {
+    fn column_width(&self, c: usize) -> usize {
         self.wp + (if c < self.wq { 1 } else { 0 })
     }
 
-    fn internal_size(&self, r: usize, c: usize) -> SmallIndexType {
+    fn internal_size(&self, r: usize, c: usize) -> usize {
         self.internal_row_height(r) * self.internal_column_width(c)
     }
 }
 
 struct Offsets {
-    even: Vec<(SmallIndexType, SmallIndexType)>,
-    odd: Vec<(SmallIndexType, SmallIndexType)>,
+    even: Vec<(usize, usize)>,
+    odd: Vec<(usize, usize)>,
 }
 
 #[derive(Clone)]
@@ -479,12 +633,12 @@ enum Alternatives {
 
 impl Alternatives {
     fn new(even_coords: Vec<(i8, i8)>, odd_coords: Vec<(i8, i8)>) -> Self {
-        let even = coords_to_small_index_type(even_coords);
-        let odd = coords_to_small_index_type(odd_coords);
+        let even = coords_to_unsigned(even_coords);
+        let odd = coords_to_unsigned(odd_coords);
         Alternatives::Even(Rc::new(Offsets { even, odd }))
     }
 
-    fn offsets(&self) -> &[(SmallIndexType, SmallIndexType)] {
+    fn offsets(&self) -> &[(usize, usize)] {
         match self {
             Alternatives::Even(offsets) => &offsets.even,
             Alternatives::Odd(offsets) => &offsets.odd,
@@ -499,10 +653,10 @@ impl Alternatives {
     }
 }
 
-fn coords_to_small_index_type(coords: Vec<(i8, i8)>) -> Vec<(SmallIndexType, SmallIndexType)> {
+fn coords_to_unsigned(coords: Vec<(i8, i8)>) -> Vec<(usize, usize)> {
     let mut result = Vec::new();
     for (x, y) in coords {
-        result.push(((x + 1) as SmallIndexType, (y + 1) as SmallIndexType));
+        result.push(((x + 1) as usize, (y + 1) as usize));
     }
     result
 }
@@ -0,0 +1,266 @@
+//! Reads patterns for Life-like cellular automata in the common file formats:
+//!
+//! * RLE: a header `x = <width>, y = <height>, rule = <rule>` followed by runs of `b` (dead), `o` (alive) and `$` (end
+//!   of row), terminated by `!`;
+//! * plaintext (`.cells`): one row per line, `.` for dead and `O` for alive, with comments that start with `!`;
+//! * Life 1.06: a `#Life 1.06` header followed by the coordinates of the live cells, one pair per line.
+//!
+//! Lines that start with `#` are comments in RLE and Life 1.06. The name of the pattern is taken from `#N` (RLE) or
+//! `!Name:` (plaintext). See <https://conwaylife.com/wiki/File_formats>.
+
+use std::{collections::HashSet, fs::read_to_string, path::Path};
+
+use anyhow::{Result, anyhow};
+use log::info;
+
+#[derive(Clone, Debug, Default)]
+pub struct Pattern {
+    pub name: Option<String>,
+    /// The rule from the header of an RLE file, *e.g.*, `B3/S23`.
+    pub rule: Option<String>,
+    pub width: usize,
+    pub height: usize,
+    /// The coordinates of the live cells, relative to the top left corner of the bounding box.
+    pub alive: HashSet<(usize, usize)>,
+}
+
+impl Pattern {
+    /// Reads a pattern from a file. The format is recognized by the header, or else by the extension of the file.
+    pub fn load(path: &Path) -> Result<Pattern> {
+        let text =
+            read_to_string(path).map_err(|e| anyhow!("Could not read pattern: {path:?}: {e}"))?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let first = text.lines().next().unwrap_or_default().trim();
+        let pattern = if first.starts_with("#Life 1.06") {
+            parse_life_106(&text)?
+        } else if first.starts_with('!') || extension.as_deref() == Some("cells") {
+            parse_plaintext(&text)?
+        } else {
+            parse_rle(&text)?
+        };
+        info!(
+            "Pattern: {path:?}: {}: ({} x {}): {} live cells: rule: {}",
+            pattern.name.as_deref().unwrap_or("<unnamed>"),
+            pattern.width,
+            pattern.height,
+            pattern.alive.len(),
+            pattern.rule.as_deref().unwrap_or("<none>")
+        );
+        Ok(pattern)
+    }
+
+    /// Creates a pattern from the coordinates of the live cells, which may be negative.
+    fn from_cells(cells: &[(i64, i64)], name: Option<String>, rule: Option<String>) -> Pattern {
+        let min_x = cells.iter().map(|c| c.0).min().unwrap_or(0);
+        let min_y = cells.iter().map(|c| c.1).min().unwrap_or(0);
+        let alive = cells
+            .iter()
+            .map(|(x, y)| ((x - min_x) as usize, (y - min_y) as usize))
+            .collect::<HashSet<_>>();
+        let width = alive.iter().map(|c| c.0 + 1).max().unwrap_or(0);
+        let height = alive.iter().map(|c| c.1 + 1).max().unwrap_or(0);
+        Pattern {
+            name,
+            rule,
+            width,
+            height,
+            alive,
+        }
+    }
+}
+
+fn parse_rle(text: &str) -> Result<Pattern> {
+    let mut name = None;
+    let mut header = None;
+    let mut body = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some(n) = comment.strip_prefix('N') {
+                name = Some(n.trim().to_string());
+            }
+        } else if header.is_none() && line.starts_with('x') {
+            header = Some(line.to_string());
+        } else {
+            body.push_str(line);
+        }
+    }
+    let header = header.ok_or_else(|| anyhow!("RLE pattern without header line"))?;
+    let mut width = None;
+    let mut height = None;
+    let mut rule = None;
+    for field in header.split(',') {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Malformed RLE header: [{header}]"))?;
+        match key.trim() {
+            "x" => width = Some(value.trim().parse::<usize>()?),
+            "y" => height = Some(value.trim().parse::<usize>()?),
+            "rule" => rule = Some(value.trim().to_string()),
+            _ => (),
+        }
+    }
+
+    let mut cells = Vec::new();
+    let (mut x, mut y) = (0i64, 0i64);
+    let mut count = String::new();
+    for c in body.chars() {
+        if c.is_ascii_digit() {
+            count.push(c);
+            continue;
+        }
+        let run = if count.is_empty() {
+            1
+        } else {
+            count.parse::<i64>()?
+        };
+        count.clear();
+        match c {
+            'b' | '.' => x += run,
+            '$' => {
+                x = 0;
+                y += run;
+            }
+            '!' => break,
+            c if c.is_ascii_alphabetic() => {
+                // Multi-state patterns use other letters for live states.
+                for i in 0..run {
+                    cells.push((x + i, y));
+                }
+                x += run;
+            }
+            c if c.is_whitespace() => (),
+            c => return Err(anyhow!("Unexpected character in RLE pattern: [{c}]")),
+        }
+    }
+
+    let mut pattern = Pattern::from_cells(&cells, name, rule);
+    // Keep the bounding box of the header, so that empty rows and columns at the edges are preserved.
+    if let (Some(w), Some(h)) = (width, height) {
+        let alive = cells
+            .iter()
+            .map(|(x, y)| (*x as usize, *y as usize))
+            .collect::<HashSet<_>>();
+        if alive.iter().all(|(x, y)| *x < w && *y < h) {
+            pattern.alive = alive;
+            pattern.width = w;
+            pattern.height = h;
+        }
+    }
+    Ok(pattern)
+}
+
+fn parse_plaintext(text: &str) -> Result<Pattern> {
+    let mut name = None;
+    let mut cells = Vec::new();
+    let mut width = 0;
+    let mut y = 0usize;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if let Some(comment) = line.strip_prefix('!') {
+            if let Some(n) = comment.strip_prefix("Name:") {
+                name = Some(n.trim().to_string());
+            }
+            continue;
+        }
+        for (x, c) in line.chars().enumerate() {
+            match c {
+                'O' | 'o' | '*' => cells.push((x, y)),
+                '.' => (),
+                c => return Err(anyhow!("Unexpected character in plaintext pattern: [{c}]")),
+            }
+        }
+        width = width.max(line.chars().count());
+        y += 1;
+    }
+    Ok(Pattern {
+        name,
+        rule: None,
+        width,
+        height: y,
+        alive: cells.into_iter().collect(),
+    })
+}
+
+fn parse_life_106(text: &str) -> Result<Pattern> {
+    let mut cells = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let (Some(x), Some(y), None) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(anyhow!("Malformed Life 1.06 line: [{line}]"));
+        };
+        cells.push((x.parse::<i64>()?, y.parse::<i64>()?));
+    }
+    Ok(Pattern::from_cells(&cells, None, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The live cells of a glider that moves to the bottom right.
+    fn glider() -> HashSet<(usize, usize)> {
+        [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn rle_glider() {
+        let pattern = parse_rle("#N Glider\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n").unwrap();
+        assert_eq!(pattern.name.as_deref(), Some("Glider"));
+        assert_eq!(pattern.rule.as_deref(), Some("B3/S23"));
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.alive, glider());
+    }
+
+    #[test]
+    fn rle_keeps_bounding_box_of_header() {
+        let pattern = parse_rle("x = 5, y = 4\n$bo$2bo$3o!").unwrap();
+        assert_eq!((pattern.width, pattern.height), (5, 4));
+        let shifted = glider()
+            .into_iter()
+            .map(|(x, y)| (x, y + 1))
+            .collect::<HashSet<_>>();
+        assert_eq!(pattern.alive, shifted);
+    }
+
+    #[test]
+    fn rle_without_header_is_rejected() {
+        assert!(parse_rle("bo$2bo$3o!").is_err());
+    }
+
+    #[test]
+    fn plaintext_glider() {
+        let pattern = parse_plaintext("!Name: Glider\n!\n.O.\n..O\nOOO\n").unwrap();
+        assert_eq!(pattern.name.as_deref(), Some("Glider"));
+        assert_eq!(pattern.rule, None);
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.alive, glider());
+    }
+
+    #[test]
+    fn plaintext_rejects_unknown_characters() {
+        assert!(parse_plaintext(".O.\n..X\n").is_err());
+    }
+
+    #[test]
+    fn life_106_glider() {
+        let pattern = parse_life_106("#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n").unwrap();
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.alive, glider());
+    }
+
+    #[test]
+    fn life_106_rejects_malformed_lines() {
+        assert!(parse_life_106("#Life 1.06\n0 1 2\n").is_err());
+        assert!(parse_life_106("#Life 1.06\n0\n").is_err());
+    }
+}
@@ -0,0 +1,47 @@
+//! The traits and types that most programs need to define a rule and run it on a torus.
+//!
+//! ```
+//! use quantized_interactions::prelude::*;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! // A blinker in Conway's Game of Life: three cells in a row at y = 2. Coordinates are given as [y, x].
+//! let rule: Rule = "B3/S23".parse()?;
+//! let torus = new_cell_torus(Tiling::OrthogonalAndDiagonal, &[5, 5], 0usize, |v: &[usize]| {
+//!     Totalistic::new(rule, v[0] == 2 && (1..=3).contains(&v[1]))
+//! })?;
+//! let mut simulation = Simulation::new(torus, 0usize);
+//! simulation.step()?;
+//! let torus = simulation.finish()?;
+//!
+//! let mut alive = torus.space().reduce(&1, Vec::new(), |region, location, mut alive| {
+//!     if torus.space().state(&1, location).is_some_and(|cell: Totalistic| cell.is_alive()) {
+//!         alive.push(torus.coordinates(region, location));
+//!     }
+//!     alive
+//! });
+//! alive.sort();
+//! // After one step, the blinker stands upright at x = 2.
+//! assert_eq!(alive, vec![(2, 1), (2, 2), (2, 3)]);
+//! # Ok(())
+//! # }
+//! ```
+
+pub use crate::{
+    block::{BlockTable, Particle, Partition, partition},
+    cell::{CellTorus, new_cell_torus},
+    ising::{Ising, Parameters as IsingParameters},
+    lenia::{Lenia, Parameters as LeniaParameters},
+    patch::{
+        AtMostSixEffectors, CsrEffectors, FixedEffectors, PatchTorus, new_hexagonal_torus,
+        new_patch_torus,
+    },
+    reversible::{Parameters as ReversibleParameters, SecondOrder},
+    simulation::{Cadence, Observer, Simulation},
+    structure::{
+        BlockRule, FromGrayScale, Generation, GrayScale, Location, Neighbourhood, Region,
+        Reversible, Rgb, Run, Space, State, UpdateContext, Weight,
+    },
+    torus::{ColorTorus, GrayScaleTorus, Tiling, Torus, render::View},
+    totalistic::{Rule, Totalistic},
+    wave::{Wave, WaveParameters},
+};
@@ -0,0 +1,375 @@
+//! # Reversible automata
+//!
+//! Fredkin's rules of the second order compute the next state of a cell from its effectors and from the state that the
+//! cell had one generation before: *s*(*t* + 1) = *f*(*t*) + *s*(*t* - 1), modulo the number of states. Whatever *f*
+//! is, the previous state follows from the next one, *s*(*t* - 1) = *s*(*t* + 1) - *f*(*t*), so each of these rules
+//! is reversible (see `Reversible`). Here *f* is the sum of the states of the effectors. With two states the sum is
+//! the parity and the inverse is the rule itself.
+//!
+//! The `reversible` command runs a random pattern forwards, turns time around (see `Simulation::reverse`), runs it back
+//! as many generations and checks whether it lands exactly on the start. Any rounding, any dependency on the order of
+//! the updates and any cell that sees beyond its effectors would show up as a difference.
+
+use std::{fmt::Display, path::PathBuf};
+
+use anyhow::{Result, anyhow};
+use log::{info, warn};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    cell::new_cell_torus,
+    patch::{CsrEffectors, new_patch_torus},
+    simulation::{Cadence, Simulation},
+    structure::{GrayScale, Location, Region, Reversible, Space, State, UpdateContext},
+    torus::{GrayScaleTorus, Tiling, Torus, render::View},
+};
+
+/// The constants of a `SecondOrder` rule.
+#[derive(Clone, Copy, Debug)]
+pub struct Parameters {
+    /// The number of states. States are added modulo this number.
+    pub states: u8,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Parameters { states: 2 }
+    }
+}
+
+/// A cell of a rule of the second order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SecondOrder {
+    pub value: u8,
+}
+
+impl SecondOrder {
+    pub fn new(value: u8) -> SecondOrder {
+        SecondOrder { value }
+    }
+
+    /// The sum of the states of the effectors of a cell, and the state of the cell one generation back, which is the
+    /// zero state before the first generation.
+    fn inputs<Spc: Space<Self, usize>>(
+        space: &Spc,
+        region: &Spc::Reg,
+        location: &Spc::Loc,
+        context: &UpdateContext<Parameters, usize>,
+    ) -> Result<(u32, u32)> {
+        let mut sum = 0u32;
+        for effector in location.effectors(space)? {
+            if let Some(state) = region.state(&effector) as Option<Self> {
+                sum += state.value as u32;
+            }
+        }
+        let back = match context.generation().checked_sub(1) {
+            None => SecondOrder::default(),
+            Some(previous) => space.state(&previous, location).ok_or_else(|| {
+                anyhow!(
+                    "Generation not available: [{previous}]: [{}]",
+                    location.id(space)
+                )
+            })?,
+        };
+        Ok((sum, back.value as u32))
+    }
+}
+
+impl State<usize> for SecondOrder {
+    type Parameters = Parameters;
+
+    /// The rule reads the state of the cell one generation back.
+    const LOOKBACK: usize = 1;
+
+    fn update<Spc: Space<Self, usize>>(
+        space: &Spc,
+        region: &Spc::Reg,
+        location: &Spc::Loc,
+        context: &UpdateContext<Parameters, usize>,
+    ) -> Result<Self> {
+        let states = context.parameters().states as u32;
+        let (sum, back) = Self::inputs(space, region, location, context)?;
+        Ok(SecondOrder::new(((sum + back) % states) as u8))
+    }
+}
+
+impl Reversible<usize> for SecondOrder {
+    fn update_inverse<Spc: Space<Self, usize>>(
+        space: &Spc,
+        region: &Spc::Reg,
+        location: &Spc::Loc,
+        context: &UpdateContext<Parameters, usize>,
+    ) -> Result<Self> {
+        let states = context.parameters().states as u32;
+        let (sum, back) = Self::inputs(space, region, location, context)?;
+        Ok(SecondOrder::new(
+            ((back + states - sum % states) % states) as u8,
+        ))
+    }
+}
+
+impl Display for SecondOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+/// The zero state is black and the highest state is white.
+impl GrayScale for SecondOrder {
+    type Context = Parameters;
+
+    fn gray_value(&self, parameters: &Parameters) -> u8 {
+        let highest = parameters.states.saturating_sub(1).max(1) as u32;
+        (255 * (self.value as u32).min(highest) / highest) as u8
+    }
+}
+
+/// The settings of the `reversible` command.
+pub(crate) struct Demo<'a> {
+    pub tiling: Tiling,
+    /// Use a `PatchTorus` instead of a `CellTorus`.
+    pub patched: bool,
+    pub width: usize,
+    pub height: usize,
+    pub states: u8,
+    /// The probability that a cell in the middle of the torus starts in a random non-zero state. The rest of the torus
+    /// starts in the zero state.
+    pub density: f64,
+    pub seed: u64,
+    /// The number of generations forwards, and then backwards.
+    pub generations: usize,
+    pub export_dir: Option<&'a PathBuf>,
+    /// Number of generations between exported images.
+    pub export_every: usize,
+}
+
+/// Runs a random pattern forwards and back, and reports whether it returns to the start.
+pub(crate) fn example(demo: &Demo) -> Result<()> {
+    if demo.states < 2 {
+        return Err(anyhow!(
+            "A rule needs at least two states: [{}]",
+            demo.states
+        ));
+    }
+    if !(0.0..=1.0).contains(&demo.density) {
+        return Err(anyhow!(
+            "Density should be between 0 and 1: [{}]",
+            demo.density
+        ));
+    }
+    let (width, height) = (demo.width, demo.height);
+    let mut rng = StdRng::seed_from_u64(demo.seed);
+    let mut values = vec![0u8; width * height];
+    for y in height / 4..height - height / 4 {
+        for x in width / 4..width - width / 4 {
+            if rng.random_bool(demo.density) {
+                values[y * width + x] = rng.random_range(1..demo.states);
+            }
+        }
+    }
+    let generation = 0usize;
+    if demo.patched {
+        let mut torus = new_patch_torus::<_, _, CsrEffectors>(
+            demo.tiling,
+            SecondOrder::default(),
+            generation,
+            width,
+            height,
+        )?;
+        for (i, value) in values.iter().enumerate().filter(|(_, v)| **v != 0) {
+            torus.adjust(&generation, i % width, i / width, SecondOrder::new(*value))?;
+        }
+        run(torus, &values, demo)
+    } else {
+        let torus = new_cell_torus(
+            demo.tiling,
+            &[height, width],
+            generation,
+            |v: &[usize]| SecondOrder::new(values[v[0] * width + v[1]]),
+        )?;
+        run(torus, &values, demo)
+    }
+}
+
+fn run<T: Torus<SecondOrder, usize>>(torus: T, start: &[u8], demo: &Demo) -> Result<()> {
+    let parameters = Parameters {
+        states: demo.states,
+    };
+    let export = |torus: &T, generation: &usize, _step: usize| {
+        torus.export(generation, &parameters, &View::Plane, demo.export_dir)
+    };
+    let mut simulation = Simulation::new(torus, 0usize)
+        .with_parameters(parameters)
+        .observe(Cadence::every(demo.export_every), export);
+    simulation.run(demo.generations)?;
+    let turn = *simulation.generation();
+    info!(
+        "Generation: [{turn}]: differs from the start in {} cells",
+        differences(simulation.torus(), &turn, start)
+    );
+    simulation.reverse()?;
+    simulation.run(demo.generations)?;
+    let generation = *simulation.generation();
+    let torus = simulation.finish()?;
+    let differences = differences(&torus, &generation, start);
+    if differences == 0 {
+        info!(
+            "Generation: [{generation}]: recovered the start exactly after {} generations forwards and back",
+            demo.generations
+        );
+    } else {
+        warn!("Generation: [{generation}]: did not recover the start: {differences} cells differ");
+    }
+    Ok(())
+}
+
+/// The number of cells whose state in the given generation differs from the start.
+fn differences<T: Torus<SecondOrder, usize>>(torus: &T, generation: &usize, start: &[u8]) -> usize {
+    let width = torus.dimensions()[0];
+    torus
+        .space()
+        .reduce(generation, 0, |region, location, count| {
+            let (x, y) = torus.coordinates(region, location);
+            match region.state(location) as Option<SecondOrder> {
+                Some(state) if state.value == start[y * width + x] => count,
+                _ => count + 1,
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    use super::*;
+    use crate::{block::Partition, cell::CellTorus, structure::BlockRule};
+
+    const SIZE: usize = 12;
+
+    /// A random pattern of the given number of states in the middle of the torus.
+    fn start(states: u8) -> Vec<u8> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut values = vec![0u8; SIZE * SIZE];
+        for y in SIZE / 4..SIZE - SIZE / 4 {
+            for x in SIZE / 4..SIZE - SIZE / 4 {
+                values[y * SIZE + x] = rng.random_range(0..states);
+            }
+        }
+        values
+    }
+
+    fn cell_torus(values: &[u8]) -> Result<CellTorus<SecondOrder, usize>> {
+        new_cell_torus(Tiling::Hexagons, &[SIZE, SIZE], 0usize, |v: &[usize]| {
+            SecondOrder::new(values[v[0] * SIZE + v[1]])
+        })
+    }
+
+    fn patch_torus(values: &[u8]) -> Result<impl Torus<SecondOrder, usize>> {
+        let mut torus = new_patch_torus::<_, _, CsrEffectors>(
+            Tiling::Hexagons,
+            SecondOrder::default(),
+            0usize,
+            SIZE,
+            SIZE,
+        )?;
+        for (i, value) in values.iter().enumerate() {
+            torus.adjust(&0, i % SIZE, i / SIZE, SecondOrder::new(*value))?;
+        }
+        Ok(torus)
+    }
+
+    /// Runs forwards, turns around and runs back as many generations. Returns the number of cells that differ from the
+    /// start at the turn and at the end.
+    fn forwards_and_back<T: Torus<SecondOrder, usize>>(
+        torus: T,
+        states: u8,
+        values: &[u8],
+    ) -> Result<(usize, usize)> {
+        let generations = 20;
+        let mut simulation = Simulation::new(torus, 0usize).with_parameters(Parameters { states });
+        simulation.run(generations)?;
+        let turn = differences(simulation.torus(), simulation.generation(), values);
+        simulation.reverse()?;
+        simulation.run(generations)?;
+        let end = differences(simulation.torus(), simulation.generation(), values);
+        Ok((turn, end))
+    }
+
+    #[test]
+    fn cell_torus_returns_to_the_start() -> Result<()> {
+        for states in [2, 3, 5] {
+            let values = start(states);
+            let (turn, end) = forwards_and_back(cell_torus(&values)?, states, &values)?;
+            assert!(turn > 0, "States: [{states}]: the pattern did not change");
+            assert_eq!(end, 0, "States: [{states}]");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn patch_torus_returns_to_the_start() -> Result<()> {
+        for states in [2, 3, 5] {
+            let values = start(states);
+            let (turn, end) = forwards_and_back(patch_torus(&values)?, states, &values)?;
+            assert!(turn > 0, "States: [{states}]: the pattern did not change");
+            assert_eq!(end, 0, "States: [{states}]");
+        }
+        Ok(())
+    }
+
+    /// Leaves the states alone, only to build a partitioned simulation of a reversible rule.
+    impl BlockRule<usize> for SecondOrder {
+        fn update_block(
+            _states: &mut [Self],
+            _context: &UpdateContext<Parameters, usize>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn partitioned_simulation_cannot_reverse() -> Result<()> {
+        let mut simulation = Simulation::new(cell_torus(&start(2))?, 0usize)
+            .with_partition(Partition::new(vec![Vec::new()])?);
+        simulation.run(2)?;
+        assert!(simulation.reverse().is_err());
+        Ok(())
+    }
+
+    /// The states of a generation, in the order of the locations of the space.
+    fn snapshot<T: Torus<SecondOrder, usize>>(torus: &T, generation: &usize) -> Vec<Option<u8>> {
+        torus
+            .space()
+            .reduce(generation, Vec::new(), |region, location, mut states| {
+                states.push((region.state(location) as Option<SecondOrder>).map(|s| s.value));
+                states
+            })
+    }
+
+    #[test]
+    fn history_after_reverse_holds_the_recorded_generations() -> Result<()> {
+        let recorded = Rc::new(RefCell::new(HashMap::new()));
+        let record = recorded.clone();
+        let observer = move |torus: &CellTorus<SecondOrder, usize>, generation: &usize, _step| {
+            record
+                .borrow_mut()
+                .insert(*generation, snapshot(torus, generation));
+            Ok(())
+        };
+        let mut simulation = Simulation::new(cell_torus(&start(2))?, 0usize)
+            .with_history(3)
+            .observe(Cadence::every(1), observer);
+        simulation.run(5)?;
+        simulation.reverse()?;
+        let history = simulation.history().copied().collect::<Vec<_>>();
+        assert!(!history.is_empty());
+        for generation in history {
+            assert_eq!(
+                Some(&snapshot(simulation.torus(), &generation)),
+                recorded.borrow().get(&generation),
+                "Generation: [{generation}]"
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,361 @@
+//! # Simulations
+//!
+//! A `Simulation` owns a torus and runs it generation by generation: it updates all cells, frees the generations that
+//! are no longer needed and calls the observers that want to see the new generation.
+//! An observer is anything that implements `Observer`, *e.g.*, a closure that exports an image or logs a measurement.
+//! Each observer is registered with a `Cadence` that tells at which steps it is called.
+//!
+//! The rule sees the parameters and the seed of the `Run` of the simulation, which default to the default parameters
+//! and seed zero.
+//!
+//! A simulation of a `BlockRule` updates the blocks of a `Partition` instead of each cell (see `with_partition`): step
+//! *n* uses the phase *n* modulo the number of phases.
+//!
+//! A simulation of a `Reversible` rule can turn time around with `reverse` and go back to where it started.
+//!
+//! By default only the current generation is kept. `Simulation::with_history` keeps a number of earlier generations
+//! as well (see `Space::retain`), so that observers can compare the current generation with the ones before it.
+//!
+//! Spaces that are not tori, like `CellNetwork`, have no coordinates to export or measure, so they are run without a
+//! `Simulation`.
+
+use std::{collections::VecDeque, marker::PhantomData};
+
+use anyhow::{Result, anyhow};
+use log::debug;
+
+use crate::{
+    block::Partition,
+    structure::{BlockRule, Generation, Reversible, Run, Space, State},
+    torus::Torus,
+};
+
+/// The steps at which an observer is called. Step 0 is the generation the simulation starts with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cadence {
+    every: Option<usize>,
+    start: bool,
+    end: bool,
+}
+
+impl Cadence {
+    /// At the start and every given number of steps after it.
+    pub fn every(steps: usize) -> Cadence {
+        Cadence {
+            every: Some(steps.max(1)),
+            start: true,
+            end: false,
+        }
+    }
+
+    /// Only at the start.
+    pub fn start() -> Cadence {
+        Cadence {
+            every: None,
+            start: true,
+            end: false,
+        }
+    }
+
+    /// Only when the simulation finishes.
+    pub fn end() -> Cadence {
+        Cadence {
+            every: None,
+            start: false,
+            end: true,
+        }
+    }
+
+    /// Not at the start.
+    pub fn skip_start(self) -> Cadence {
+        Cadence {
+            start: false,
+            ..self
+        }
+    }
+
+    /// Also when the simulation finishes.
+    pub fn and_end(self) -> Cadence {
+        Cadence { end: true, ..self }
+    }
+
+    fn wants(&self, step: usize) -> bool {
+        if step == 0 {
+            self.start
+        } else {
+            self.every.is_some_and(|every| step.is_multiple_of(every))
+        }
+    }
+}
+
+/// Looks at the generations of a simulation, *e.g.*, to export them or to measure them.
+pub trait Observer<T, S, Gen>
+where
+    T: Torus<S, Gen>,
+    S: State<Gen>,
+    Gen: Generation,
+{
+    fn observe(&mut self, torus: &T, generation: &Gen, step: usize) -> Result<()>;
+
+    /// Called once when the simulation finishes, after the last observation.
+    fn finish(&mut self, _torus: &T, _generation: &Gen) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<T, S, Gen, F> Observer<T, S, Gen> for F
+where
+    T: Torus<S, Gen>,
+    S: State<Gen>,
+    Gen: Generation,
+    F: FnMut(&T, &Gen, usize) -> Result<()>,
+{
+    fn observe(&mut self, torus: &T, generation: &Gen, step: usize) -> Result<()> {
+        self(torus, generation, step)
+    }
+}
+
+type Observers<'a, T, S, Gen> = Vec<(Cadence, Box<dyn Observer<T, S, Gen> + 'a>)>;
+
+/// Computes the successor of a generation in the space of a torus, given the parameters of the run and the step.
+type Update<'a, T, S, Gen> = Box<
+    dyn Fn(
+            &mut <T as Torus<S, Gen>>::Spc,
+            &Gen,
+            &Run<<S as State<Gen>>::Parameters>,
+            usize,
+        ) -> Result<()>
+        + 'a,
+>;
+
+pub struct Simulation<'a, T, S, Gen>
+where
+    T: Torus<S, Gen>,
+    S: State<Gen>,
+    Gen: Generation,
+{
+    torus: T,
+    generation: Gen,
+    run: Run<S::Parameters>,
+    step: usize,
+    started: bool,
+    history: usize,
+    retained: VecDeque<Gen>,
+    observers: Observers<'a, T, S, Gen>,
+    /// How to compute the next generation, if not with `Space::update_all`.
+    update: Option<Update<'a, T, S, Gen>>,
+    /// Whether the simulation runs backwards in time.
+    reversed: bool,
+    _state: PhantomData<S>,
+}
+
+impl<'a, T, S, Gen> Simulation<'a, T, S, Gen>
+where
+    T: Torus<S, Gen>,
+    S: State<Gen>,
+    Gen: Generation,
+{
+    /// Starts a simulation at the given generation, which must be the current generation of the torus.
+    pub fn new(torus: T, generation: Gen) -> Self {
+        Simulation {
+            torus,
+            generation,
+            run: Run::default(),
+            step: 0,
+            started: false,
+            history: 0,
+            retained: VecDeque::new(),
+            observers: Vec::new(),
+            update: None,
+            reversed: false,
+            _state: PhantomData,
+        }
+    }
+
+    /// Sets the parameters of the rule.
+    pub fn with_parameters(mut self, parameters: S::Parameters) -> Self {
+        self.run.parameters = parameters;
+        self
+    }
+
+    /// Sets the seed of the random streams of the rule.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.run.seed = seed;
+        self
+    }
+
+    /// Keeps the given number of generations before the current one.
+    pub fn with_history(self, history: usize) -> Self {
+        Simulation { history, ..self }
+    }
+
+    /// Registers an observer. Observers that are called at the same step are called in the order of registration.
+    pub fn observe(mut self, cadence: Cadence, observer: impl Observer<T, S, Gen> + 'a) -> Self {
+        self.observers.push((cadence, Box::new(observer)));
+        self
+    }
+
+    pub fn torus(&self) -> &T {
+        &self.torus
+    }
+
+    pub fn torus_mut(&mut self) -> &mut T {
+        &mut self.torus
+    }
+
+    pub fn generation(&self) -> &Gen {
+        &self.generation
+    }
+
+    /// The number of steps taken so far.
+    pub fn steps(&self) -> usize {
+        self.step
+    }
+
+    /// Whether the simulation runs backwards in time (see `reverse`).
+    pub fn is_reversed(&self) -> bool {
+        self.reversed
+    }
+
+    /// The generations before the current one that are still available, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &Gen> {
+        self.retained.iter()
+    }
+
+    /// Computes the next generation and calls the observers that want to see it.
+    pub fn step(&mut self) -> Result<()> {
+        self.start()?;
+        let previous = self.generation.clone();
+        match self.update.as_ref() {
+            Some(update) => update(self.torus.space_mut(), &previous, &self.run, self.step)?,
+            None => self.torus.space_mut().update_all(&previous, &self.run)?,
+        }
+        self.generation = previous.successor();
+        self.step += 1;
+        if self.history > 0 {
+            self.torus.space_mut().retain(&previous)?;
+            self.retained.push_back(previous);
+            while self.retained.len() > self.history {
+                if let Some(oldest) = self.retained.pop_front() {
+                    self.torus.space_mut().free(&oldest)?;
+                }
+            }
+        } else {
+            self.torus.space_mut().free(&previous)?;
+        }
+        self.notify(self.step)
+    }
+
+    /// Runs the given number of generations.
+    pub fn run(&mut self, generations: usize) -> Result<()> {
+        self.run_until(generations, |_, _| false).map(|_| ())
+    }
+
+    /// Runs at most the given number of generations, but stops as soon as the predicate holds for the current
+    /// generation. Tells whether the predicate stopped the run.
+    pub fn run_until<P>(&mut self, generations: usize, mut stop: P) -> Result<bool>
+    where
+        P: FnMut(&T, &Gen) -> bool,
+    {
+        self.start()?;
+        for _ in 0..generations {
+            if stop(&self.torus, &self.generation) {
+                debug!("Stopped: [{:?}]", self.generation);
+                return Ok(true);
+            }
+            self.step()?;
+        }
+        Ok(stop(&self.torus, &self.generation))
+    }
+
+    /// Calls the observers that want to see the end, lets all observers finish and returns the torus.
+    pub fn finish(mut self) -> Result<T> {
+        self.start()?;
+        for (cadence, observer) in self.observers.iter_mut() {
+            if cadence.end {
+                observer.observe(&self.torus, &self.generation, self.step)?;
+            }
+            observer.finish(&self.torus, &self.generation)?;
+        }
+        Ok(self.torus)
+    }
+
+    /// Shows the first generation to the observers, unless that already happened.
+    fn start(&mut self) -> Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        self.notify(0)
+    }
+
+    fn notify(&mut self, step: usize) -> Result<()> {
+        for (cadence, observer) in self.observers.iter_mut() {
+            if cadence.wants(step) {
+                observer.observe(&self.torus, &self.generation, step)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, T, S, Gen> Simulation<'a, T, S, Gen>
+where
+    T: Torus<S, Gen>,
+    S: BlockRule<Gen>,
+    Gen: Generation,
+{
+    /// Updates the blocks of the partition instead of each cell, one phase per step.
+    pub fn with_partition(self, partition: Partition<<T::Spc as Space<S, Gen>>::Loc>) -> Self
+    where
+        <T::Spc as Space<S, Gen>>::Loc: 'a,
+    {
+        let update: Update<'a, T, S, Gen> = Box::new(move |space, generation, run, step| {
+            space.update_blocks(generation, run, partition.blocks(step))
+        });
+        Simulation {
+            update: Some(update),
+            ..self
+        }
+    }
+}
+
+impl<'a, T, S, Gen> Simulation<'a, T, S, Gen>
+where
+    T: Torus<S, Gen>,
+    S: Reversible<Gen>,
+    Gen: Generation,
+{
+    /// Turns time around: later steps compute the inverse of the rule, or the rule again if the simulation already ran
+    /// backwards. The current state stays the same, so as many steps as were taken forwards lead back to the start.
+    /// A rule that looks back at earlier generations first takes that many more steps, because after the turn it looks
+    /// back at the later ones (see `Space::reverse`). These generations are no longer part of the history.
+    pub fn reverse(&mut self) -> Result<()> {
+        if self.update.is_some() && !self.reversed {
+            return Err(anyhow!(
+                "Only simulations that update each cell can run backwards"
+            ));
+        }
+        for _ in 0..S::LOOKBACK {
+            self.step()?;
+        }
+        self.torus.space_mut().reverse(&self.generation)?;
+        // The space relabels the generations that the rule looks back at and drops their retained copies.
+        for _ in 0..S::LOOKBACK {
+            self.retained.pop_back();
+        }
+        self.reversed = !self.reversed;
+        self.update = if self.reversed {
+            let update: Update<'a, T, S, Gen> =
+                Box::new(|space, generation, run, _step| space.update_all_inverse(generation, run));
+            Some(update)
+        } else {
+            None
+        };
+        debug!(
+            "Reversed: [{:?}]: backwards: {}",
+            self.generation, self.reversed
+        );
+        Ok(())
+    }
+}
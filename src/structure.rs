@@ -1,8 +1,11 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use rand::{SeedableRng, rngs::StdRng};
 use std::{
     borrow::Cow,
+    collections::HashSet,
     fmt::{Debug, Display},
-    hash::Hash,
+    hash::{Hash, Hasher},
+    rc::Rc,
 };
 
 pub trait Generation: Hash + Eq + PartialEq + Debug + Clone {
@@ -22,7 +25,7 @@ where
 
 pub trait Space<S: State<Gen>, Gen: Generation> {
     type Reg: Region<Self, S, Gen> + ToOwned;
-    type Loc: Location<Self, S, Gen>;
+    type Loc: Location<Self, S, Gen> + Clone + Eq + Hash;
 
     fn regions(&self, generation: &Gen) -> impl IntoIterator<Item = Self::Reg>;
 
@@ -37,10 +40,48 @@ pub trait Space<S: State<Gen>, Gen: Generation> {
             .and_then(|region| region.state(location))
     }
 
-    fn update_all(&mut self, generation: &Gen) -> Result<()>;
+    fn update_all(&mut self, generation: &Gen, run: &Run<S::Parameters>) -> Result<()>;
+
+    /// Computes the next generation with a block rule instead of a rule per cell: each of the given blocks is updated
+    /// as a whole (see `BlockRule`). Cells that are not in any block keep their state.
+    /// Spaces that cannot update blocks return an error.
+    fn update_blocks(
+        &mut self,
+        _generation: &Gen,
+        _run: &Run<S::Parameters>,
+        _blocks: &[Vec<Self::Loc>],
+    ) -> Result<()>
+    where
+        S: BlockRule<Gen>,
+    {
+        Err(anyhow!("Block updates are not supported by this space"))
+    }
+
+    /// Computes the next generation with the inverse of the rule, *i.e.*, one step back in time (see `Reversible`).
+    /// Spaces that cannot run backwards return an error.
+    fn update_all_inverse(&mut self, _generation: &Gen, _run: &Run<S::Parameters>) -> Result<()>
+    where
+        S: Reversible<Gen>,
+    {
+        Err(anyhow!("Inverse updates are not supported by this space"))
+    }
+
+    /// Reverses the order of the states of the given generation, which must be the current one, and the generations
+    /// that the rule looks back at (see `State::LOOKBACK`). The generations keep their place, so the current one gets
+    /// the states of the oldest of them. This turns time around for a `Reversible` rule. Retained copies of these
+    /// generations are dropped. Spaces that cannot do that return an error.
+    fn reverse(&mut self, _generation: &Gen) -> Result<()> {
+        Err(anyhow!("Reversing is not supported by this space"))
+    }
 
     fn free(&mut self, generation: &Gen) -> Result<()>;
 
+    /// Keeps the given generation available after later updates, until it is freed.
+    /// Spaces that keep every generation until it is freed need not do anything.
+    fn retain(&mut self, _generation: &Gen) -> Result<()> {
+        Ok(())
+    }
+
     fn reduce<A, F>(&self, generation: &Gen, init: A, f: F) -> A
     where
         F: Fn(&Self::Reg, &Self::Loc, A) -> A,
@@ -55,26 +96,274 @@ pub trait Space<S: State<Gen>, Gen: Generation> {
     }
 }
 
+/// The weight of the link between a cell and one of its effectors, *e.g.*, a coupling coefficient or a distance.
+pub type Weight = f64;
+
 pub trait Location<Spc: Space<S, Gen> + ?Sized, S: State<Gen>, Gen: Generation>: Sized {
     fn effectors(&self, space: &Spc) -> Result<impl IntoIterator<Item = Self>>;
+    /// The effectors together with the weights of their links. Links without an explicit weight have weight `1.0`.
+    fn weighted_effectors(&self, space: &Spc) -> Result<impl IntoIterator<Item = (Self, Weight)>> {
+        Ok(self.effectors(space)?.into_iter().map(|e| (e, 1.0)))
+    }
     fn id(&self, space: &Spc) -> String;
+    /// The location that holds the original of this location. Spaces that keep copies of cells, like the edges of
+    /// patches, map a copy onto the original. For other spaces this is the location itself.
+    fn canonical(&self, _space: &Spc) -> Self
+    where
+        Self: Clone,
+    {
+        self.clone()
+    }
+    /// The locations that can be reached from this location in at most `radius` steps from a location to one of its
+    /// effectors, together with the number of steps, nearest first. The location itself is not included.
+    /// The locations are canonical, so they may lie outside the region of this location: look up their states with
+    /// `Space::state` rather than `Region::state`. Spaces that can keep the result override this to cache it.
+    fn neighbourhood(&self, space: &Spc, radius: usize) -> Result<Neighbourhood<Self>>
+    where
+        Self: Clone + Eq + Hash,
+    {
+        find_neighbourhood(self, space, radius)
+    }
+}
+
+/// Locations with their distance in steps from a central location, nearest first (see `Location::neighbourhood`).
+pub type Neighbourhood<L> = Rc<[(L, usize)]>;
+
+/// Finds the neighbourhood of a location with a breadth-first search over canonical effectors.
+pub fn find_neighbourhood<Spc, S, Gen, L>(
+    location: &L,
+    space: &Spc,
+    radius: usize,
+) -> Result<Neighbourhood<L>>
+where
+    Spc: Space<S, Gen> + ?Sized,
+    S: State<Gen>,
+    Gen: Generation,
+    L: Location<Spc, S, Gen> + Clone + Eq + Hash,
+{
+    let origin = location.canonical(space);
+    let mut seen = HashSet::from([origin.clone()]);
+    let mut result = Vec::new();
+    let mut frontier = vec![origin];
+    for steps in 1..=radius {
+        let mut next = Vec::new();
+        for current in frontier.iter() {
+            for effector in current.effectors(space)? {
+                let effector = effector.canonical(space);
+                if seen.insert(effector.clone()) {
+                    result.push((effector.clone(), steps));
+                    next.push(effector);
+                }
+            }
+        }
+        frontier = next;
+    }
+    Ok(result.into())
 }
 
 pub trait State<Gen: Generation>: Debug + Clone + Display {
+    /// The constants of the rule that are the same for all cells, *e.g.*, a coupling coefficient. Rules without
+    /// constants use `()`.
+    type Parameters: Debug + Clone + Default;
+
+    /// The number of generations before the current one that the rule reads, *e.g.*, one for a rule of the second
+    /// order. The rule reads them with `Space::state`. Spaces keep that many generations, even if they are freed.
+    const LOOKBACK: usize = 0;
+
     fn update<Spc: Space<Self, Gen>>(
         space: &Spc,
         region: &Spc::Reg,
         location: &Spc::Loc,
+        context: &UpdateContext<Self::Parameters, Gen>,
+    ) -> Result<Self>;
+}
+
+/// A rule that updates a block of cells at once, *e.g.*, a rule on the blocks of a Margolus neighbourhood. The cells of
+/// a block do not look outside the block, so a rule that conserves the number of particles in a block conserves it on
+/// the whole space, and a rule that is a permutation of the states of a block is reversible.
+pub trait BlockRule<Gen: Generation>: State<Gen> {
+    /// Replaces the states of a block, in the order of the locations of the block, by their successors.
+    fn update_block(
+        states: &mut [Self],
+        context: &UpdateContext<Self::Parameters, Gen>,
+    ) -> Result<()>;
+}
+
+/// A rule that can run backwards in time. After `Space::reverse`, the generations of a space go back in time: the
+/// current generation holds the states at time *t*, and the generations that the rule looks back at hold the states at
+/// *t* + 1, *t* + 2, and so on. The inverse of the rule of the second order *s*(*t* + 1) = *f*(*t*) + *s*(*t* - 1) is
+/// thus *s*(*t* - 1) = *s*(*t* + 1) - *f*(*t*), where *s*(*t* + 1) is the state one generation back.
+pub trait Reversible<Gen: Generation>: State<Gen> {
+    /// Computes the state of a cell one step earlier in time.
+    fn update_inverse<Spc: Space<Self, Gen>>(
+        space: &Spc,
+        region: &Spc::Reg,
+        location: &Spc::Loc,
+        context: &UpdateContext<Self::Parameters, Gen>,
     ) -> Result<Self>;
 }
 
+/// The settings of a run that rules can see: the parameters of the rule and the seed of the random streams.
+#[derive(Clone, Debug, Default)]
+pub struct Run<P> {
+    pub parameters: P,
+    pub seed: u64,
+}
+
+impl<P> Run<P> {
+    pub fn new(parameters: P, seed: u64) -> Run<P> {
+        Run { parameters, seed }
+    }
+
+    /// The context of the updates from the given generation to the next. Spaces call `UpdateContext::at` for each
+    /// location that they update.
+    pub fn context<'a, Gen: Hash>(&'a self, generation: &'a Gen) -> UpdateContext<'a, P, Gen> {
+        let mut hasher = StreamHasher(0);
+        self.seed.hash(&mut hasher);
+        generation.hash(&mut hasher);
+        UpdateContext {
+            parameters: &self.parameters,
+            generation,
+            stream: hasher.finish(),
+        }
+    }
+}
+
+/// What a rule knows besides the states of the cells: the parameters of the run, the generation that is updated and a
+/// stream of random numbers. The stream only depends on the seed of the run, the generation and the location, so a
+/// stochastic rule gives the same result in every run with the same seed, whatever the order of the updates.
+pub struct UpdateContext<'a, P, Gen> {
+    parameters: &'a P,
+    generation: &'a Gen,
+    stream: u64,
+}
+
+impl<'a, P, Gen> UpdateContext<'a, P, Gen> {
+    /// The context of the update of the given location.
+    pub fn at<L: Hash>(&self, location: &L) -> UpdateContext<'a, P, Gen> {
+        let mut hasher = StreamHasher(self.stream);
+        location.hash(&mut hasher);
+        UpdateContext {
+            parameters: self.parameters,
+            generation: self.generation,
+            stream: hasher.finish(),
+        }
+    }
+
+    pub fn parameters(&self) -> &P {
+        self.parameters
+    }
+
+    /// The generation that the states are read from. The update computes the successor of this generation.
+    pub fn generation(&self) -> &Gen {
+        self.generation
+    }
+
+    /// The random stream of this location in this generation. Each call starts the stream from the beginning.
+    /// The generator is seeded from the stream, but `StdRng` may change its algorithm in a new version of rand, so
+    /// unlike `uniform`, its numbers are only reproducible with the same version.
+    pub fn rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.stream)
+    }
+
+    /// A single random number between zero (inclusive) and one (exclusive) for this location in this generation.
+    /// Much cheaper than `rng` for rules that need only one.
+    pub fn uniform(&self) -> f64 {
+        let mut hasher = StreamHasher(self.stream);
+        hasher.write_u64(0);
+        (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Mixes each word into the hash with the finalizer of SplitMix64. Unlike the hashers of the standard library, it
+/// does not change between versions, so neither do the streams nor the numbers of `uniform`.
+struct StreamHasher(u64);
+
+impl Hasher for StreamHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_u64(u64::from(*byte));
+        }
+    }
+
+    fn write_u64(&mut self, word: u64) {
+        let mut z = (self.0 ^ word).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        self.0 = z ^ (z >> 31);
+    }
+
+    fn write_usize(&mut self, word: usize) {
+        self.write_u64(word as u64);
+    }
+}
+
 pub trait GrayScale {
     type Context;
     fn gray_value(&self, context: &Self::Context) -> u8;
 }
 
+/// The inverse of `GrayScale`: creates a state from a shade of gray, *e.g.*, a pixel of an image.
+pub trait FromGrayScale: Sized {
+    type Context;
+    fn from_gray_value(value: u8, context: &Self::Context) -> Self;
+}
+
+/// The colour counterpart of `GrayScale`: maps a state to red, green and blue components.
+pub trait Rgb {
+    type Context;
+    fn rgb_value(&self, context: &Self::Context) -> [u8; 3];
+}
+
 impl Generation for usize {
     fn successor(&self) -> Self {
         self + 1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uniforms(
+        run: &Run<()>,
+        generation: usize,
+        locations: impl Iterator<Item = (usize, usize)>,
+    ) -> Vec<f64> {
+        let context = run.context(&generation);
+        let mut numbers: Vec<_> = locations
+            .map(|location| (location, context.at(&location).uniform()))
+            .collect();
+        numbers.sort_by_key(|(location, _)| *location);
+        numbers.into_iter().map(|(_, number)| number).collect()
+    }
+
+    #[test]
+    fn uniform_is_pinned() {
+        let run = Run::new((), 42);
+        assert_eq!(
+            run.context(&7usize).at(&(3usize, 5usize)).uniform(),
+            0.7681536684265917
+        );
+    }
+
+    #[test]
+    fn streams_do_not_depend_on_the_order_of_the_updates() {
+        let run = Run::new((), 42);
+        let grid = || (0..8).flat_map(|y| (0..8).map(move |x| (x, y)));
+        let forwards = uniforms(&run, 7, grid());
+        let backwards = uniforms(&run, 7, grid().collect::<Vec<_>>().into_iter().rev());
+        assert_eq!(forwards, backwards);
+        assert_eq!(forwards, uniforms(&Run::new((), 42), 7, grid()));
+        assert!(forwards.iter().all(|number| (0.0..1.0).contains(number)));
+
+        let distinct: HashSet<_> = forwards.iter().map(|number| number.to_bits()).collect();
+        assert_eq!(distinct.len(), forwards.len());
+        assert_ne!(forwards, uniforms(&run, 8, grid()));
+        assert_ne!(forwards, uniforms(&Run::new((), 43), 7, grid()));
+    }
+}
@@ -0,0 +1,288 @@
+//! Writes a run as a single animated GIF instead of a directory with one PNG per generation.
+//!
+//! Frames are rendered by `GrayScaleTorus::render` or `ColorTorus::render_color`, optionally enlarged and labeled with
+//! the generation number.
+
+use std::{
+    fs::{File, create_dir_all, remove_file},
+    io::BufWriter,
+    path::PathBuf,
+};
+
+use anyhow::{Result, anyhow};
+use clap::Args;
+use gif::{Encoder, Frame, Repeat};
+use image::{
+    DynamicImage, GrayImage, RgbImage, Rgba, RgbaImage,
+    imageops::{self, FilterType},
+};
+use log::info;
+
+use crate::{
+    simulation::Observer,
+    structure::{Generation, State},
+    torus::Torus,
+};
+
+#[derive(Args, Clone, Debug)]
+pub struct AnimationOptions {
+    #[arg(help = "file to write an animated GIF of the run to", long = "gif")]
+    pub path: Option<PathBuf>,
+
+    #[arg(
+        help = "number of generations between frames",
+        long,
+        default_value_t = 10
+    )]
+    pub frame_every: usize,
+
+    #[arg(
+        help = "delay between frames in milliseconds",
+        long,
+        default_value_t = 100
+    )]
+    pub frame_delay: u32,
+
+    #[arg(help = "enlarge each frame by this factor", long, default_value_t = 1)]
+    pub scale: u32,
+
+    #[arg(help = "show the generation number in each frame", long)]
+    pub label: bool,
+}
+
+/// The file is created up front, but the encoder only with the first frame, because the encoder needs the size of the
+/// frames.
+pub struct Animation {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    encoder: Option<Encoder<BufWriter<File>>>,
+    options: AnimationOptions,
+    frame_count: usize,
+}
+
+impl Animation {
+    /// Creates the GIF file, if the options contain a path.
+    pub fn create(options: &AnimationOptions) -> Result<Option<Animation>> {
+        let Some(path) = options.path.as_ref() else {
+            return Ok(None);
+        };
+        if options.frame_every == 0 || options.scale == 0 {
+            return Err(anyhow!(
+                "Frame cadence and scale must be positive: [{}]: [{}]",
+                options.frame_every,
+                options.scale
+            ));
+        }
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            create_dir_all(dir)?;
+        }
+        let writer = BufWriter::new(
+            File::create(path).map_err(|e| anyhow!("Could not create GIF: {path:?}: {e}"))?,
+        );
+        Ok(Some(Animation {
+            path: path.clone(),
+            writer: Some(writer),
+            encoder: None,
+            options: options.clone(),
+            frame_count: 0,
+        }))
+    }
+
+    /// Tells whether the step with the given number should become a frame.
+    pub fn wants(&self, step: usize) -> bool {
+        step.is_multiple_of(self.options.frame_every)
+    }
+
+    pub fn add_frame(&mut self, img: &GrayImage, label: &str) -> Result<()> {
+        self.add_rgba_frame(DynamicImage::ImageLuma8(img.clone()).into_rgba8(), label)
+    }
+
+    pub fn add_color_frame(&mut self, img: &RgbImage, label: &str) -> Result<()> {
+        self.add_rgba_frame(DynamicImage::ImageRgb8(img.clone()).into_rgba8(), label)
+    }
+
+    fn add_rgba_frame(&mut self, img: RgbaImage, label: &str) -> Result<()> {
+        let scale = self.options.scale;
+        let mut img = if scale > 1 {
+            imageops::resize(
+                &img,
+                img.width() * scale,
+                img.height() * scale,
+                FilterType::Nearest,
+            )
+        } else {
+            img
+        };
+        if self.options.label {
+            draw_label(&mut img, label, 2 * scale);
+        }
+        let (width, height) = (u16::try_from(img.width())?, u16::try_from(img.height())?);
+        let encoder = match self.encoder.as_mut() {
+            Some(encoder) => encoder,
+            None => {
+                let writer = self
+                    .writer
+                    .take()
+                    .ok_or_else(|| anyhow!("GIF without writer: {:?}", self.path))?;
+                let mut encoder = Encoder::new(writer, width, height, &[])?;
+                encoder.set_repeat(Repeat::Infinite)?;
+                self.encoder.insert(encoder)
+            }
+        };
+        let mut frame = Frame::from_rgba_speed(width, height, &mut img.into_raw(), 1);
+        // GIF delays are in hundredths of a second.
+        frame.delay = u16::try_from(self.options.frame_delay / 10).unwrap_or(u16::MAX);
+        encoder.write_frame(&frame)?;
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    /// Writes the end of the GIF and flushes it to the file. An animation without frames is not a valid GIF, so its
+    /// file is removed and an error is returned.
+    pub fn finish(self) -> Result<()> {
+        let Some(encoder) = self.encoder else {
+            drop(self.writer);
+            remove_file(&self.path)?;
+            return Err(anyhow!("No frames to write to: {:?}", self.path));
+        };
+        encoder
+            .into_inner()?
+            .into_inner()
+            .map_err(|e| anyhow!("Could not write GIF: {:?}: {}", self.path, e.error()))?
+            .sync_all()?;
+        info!("Wrote {} frames to: {:?}", self.frame_count, self.path);
+        Ok(())
+    }
+}
+
+/// Adds the frames that an animation wants while a simulation runs, and finishes the animation at the end.
+/// Without an animation it does nothing.
+pub struct Recorder<F> {
+    animation: Option<Animation>,
+    frame: F,
+}
+
+impl<F> Recorder<F> {
+    /// The function draws a generation and adds it to the animation.
+    pub fn new(animation: Option<Animation>, frame: F) -> Recorder<F> {
+        Recorder { animation, frame }
+    }
+}
+
+impl<T, S, Gen, F> Observer<T, S, Gen> for Recorder<F>
+where
+    T: Torus<S, Gen>,
+    S: State<Gen>,
+    Gen: Generation,
+    F: FnMut(&T, &Gen, &mut Animation) -> Result<()>,
+{
+    fn observe(&mut self, torus: &T, generation: &Gen, step: usize) -> Result<()> {
+        match self.animation.as_mut() {
+            Some(animation) if animation.wants(step) => (self.frame)(torus, generation, animation),
+            _ => Ok(()),
+        }
+    }
+
+    fn finish(&mut self, _torus: &T, _generation: &Gen) -> Result<()> {
+        match self.animation.take() {
+            Some(animation) => animation.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Glyphs of three by five pixels for the digits, one row per element, most significant bit on the left.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// Draws the digits of the label in white on a black box in the top left corner. Other characters are skipped.
+fn draw_label(img: &mut RgbaImage, label: &str, pixel: u32) {
+    let digits = label
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .collect::<Vec<_>>();
+    let box_width = (4 * digits.len() as u32 + 1) * pixel;
+    let box_height = 7 * pixel;
+    for x in 0..box_width.min(img.width()) {
+        for y in 0..box_height.min(img.height()) {
+            img.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+        }
+    }
+    for (i, digit) in digits.iter().enumerate() {
+        let left = (4 * i as u32 + 1) * pixel;
+        for (row, bits) in DIGITS[*digit as usize].iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                let x0 = left + column * pixel;
+                let y0 = (row as u32 + 1) * pixel;
+                for x in x0..(x0 + pixel) {
+                    for y in y0..(y0 + pixel) {
+                        if x < img.width() && y < img.height() {
+                            img.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs};
+
+    use image::{AnimationDecoder, codecs::gif::GifDecoder};
+
+    use super::*;
+
+    fn options(name: &str) -> AnimationOptions {
+        AnimationOptions {
+            path: Some(temp_dir().join(format!("{name}-{}.gif", std::process::id()))),
+            frame_every: 1,
+            frame_delay: 100,
+            scale: 2,
+            label: true,
+        }
+    }
+
+    #[test]
+    fn writes_a_complete_gif() -> Result<()> {
+        let options = options("animation-frames");
+        let path = options.path.clone().unwrap_or_default();
+        let mut animation = Animation::create(&options)?.ok_or_else(|| anyhow!("No animation"))?;
+        animation.add_frame(&GrayImage::new(8, 6), "0")?;
+        animation.add_color_frame(&RgbImage::new(8, 6), "1")?;
+        animation.finish()?;
+        let bytes = fs::read(&path)?;
+        fs::remove_file(&path)?;
+        assert_eq!(bytes.last(), Some(&0x3b), "GIF trailer");
+        let frames = GifDecoder::new(std::io::Cursor::new(bytes))?
+            .into_frames()
+            .collect_frames()?;
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].buffer().dimensions(), (16, 12));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_an_animation_without_frames() -> Result<()> {
+        let options = options("animation-empty");
+        let path = options.path.clone().unwrap_or_default();
+        let animation = Animation::create(&options)?.ok_or_else(|| anyhow!("No animation"))?;
+        assert!(animation.finish().is_err());
+        assert!(!path.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::Result;
+use image::{Rgb as RgbPixel, RgbImage};
+use log::info;
+use std::{fs::create_dir_all, path::PathBuf};
+
+use crate::{
+    structure::{Generation, Rgb, State},
+    torus::{
+        ColorTorus, Torus,
+        render::{View, render, write_png},
+    },
+};
+
+impl<T: Torus<S, Gen>, S, Gen> ColorTorus<S, Gen> for T
+where
+    S: State<Gen> + Rgb + Copy,
+    Gen: Generation,
+{
+    fn export_color(
+        &self,
+        generation: &Gen,
+        context: &<S as Rgb>::Context,
+        view: &View,
+        export_dir: Option<&PathBuf>,
+    ) -> Result<()> {
+        if let Some(dir) = export_dir {
+            create_dir_all(dir)?;
+            info!("Exporting generation in colour [{generation:?}]");
+            let img = self.render_color(generation, context, view)?;
+            write_png(&img, generation, dir)?;
+        }
+        Ok(())
+    }
+
+    fn render_color(
+        &self,
+        generation: &Gen,
+        context: &<S as Rgb>::Context,
+        view: &View,
+    ) -> Result<RgbImage> {
+        render(self, generation, view, |state: Option<S>| {
+            RgbPixel(
+                state
+                    .map(|s| s.rgb_value(context))
+                    .unwrap_or([128, 128, 128]),
+            )
+        })
+    }
+}
@@ -0,0 +1,136 @@
+//! The shapes of the tilings in the plane, shared by the exporters and the importer.
+
+use std::f64::consts::PI;
+
+use crate::torus::Tiling;
+
+/// The shape and the placement of the cells of a tiling.
+pub struct Geometry {
+    tiling: Tiling,
+    width: usize,
+    height: usize,
+}
+
+impl Geometry {
+    pub fn new(tiling: Tiling, width: usize, height: usize) -> Geometry {
+        Geometry {
+            tiling,
+            width,
+            height,
+        }
+    }
+
+    /// Height of a row of triangles, and the distance between the rows of hexagons.
+    const ROW: f64 = 0.866_025_403_784_438_6;
+
+    /// Distance from the center of a hexagon to its corners.
+    const HEXAGON_RADIUS: f64 = 0.577_350_269_189_625_8;
+
+    /// The number of cells along the width and the height of the torus.
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn up(x: usize, y: usize) -> bool {
+        (x + y).is_multiple_of(2)
+    }
+
+    pub fn center(&self, x: usize, y: usize) -> (f64, f64) {
+        let (fx, fy) = (x as f64, y as f64);
+        match self.tiling {
+            Tiling::Orthogonal | Tiling::OrthogonalAndDiagonal => (fx + 0.5, fy + 0.5),
+            Tiling::Hexagons => {
+                // Even rows are shifted half a cell to the right.
+                let shift = if y.is_multiple_of(2) { 1.0 } else { 0.5 };
+                (fx + shift, Self::HEXAGON_RADIUS + fy * Self::ROW)
+            }
+            Tiling::AdjacentTriangles | Tiling::TouchingTriangles => {
+                // The centroid lies a third of the height from the base.
+                let offset = if Self::up(x, y) { 2.0 / 3.0 } else { 1.0 / 3.0 };
+                (fx / 2.0 + 0.5, (fy + offset) * Self::ROW)
+            }
+        }
+    }
+
+    pub fn polygon(&self, x: usize, y: usize) -> Vec<(f64, f64)> {
+        let (cx, cy) = self.center(x, y);
+        match self.tiling {
+            Tiling::Orthogonal | Tiling::OrthogonalAndDiagonal => vec![
+                (cx - 0.5, cy - 0.5),
+                (cx + 0.5, cy - 0.5),
+                (cx + 0.5, cy + 0.5),
+                (cx - 0.5, cy + 0.5),
+            ],
+            Tiling::Hexagons => (0..6)
+                .map(|k| {
+                    let angle = PI / 2.0 + (k as f64) * PI / 3.0;
+                    (
+                        cx + Self::HEXAGON_RADIUS * angle.cos(),
+                        cy - Self::HEXAGON_RADIUS * angle.sin(),
+                    )
+                })
+                .collect(),
+            Tiling::AdjacentTriangles | Tiling::TouchingTriangles => {
+                let left = x as f64 / 2.0;
+                let top = y as f64 * Self::ROW;
+                let bottom = top + Self::ROW;
+                if Self::up(x, y) {
+                    vec![(left + 0.5, top), (left + 1.0, bottom), (left, bottom)]
+                } else {
+                    vec![(left, top), (left + 1.0, top), (left + 0.5, bottom)]
+                }
+            }
+        }
+    }
+
+    /// The size of the drawing.
+    pub fn extent(&self) -> (f64, f64) {
+        let (w, h) = (self.width as f64, self.height as f64);
+        match self.tiling {
+            Tiling::Orthogonal | Tiling::OrthogonalAndDiagonal => (w, h),
+            Tiling::Hexagons => (w + 0.5, (h - 1.0) * Self::ROW + 2.0 * Self::HEXAGON_RADIUS),
+            Tiling::AdjacentTriangles | Tiling::TouchingTriangles => {
+                ((w + 1.0) / 2.0, h * Self::ROW)
+            }
+        }
+    }
+
+    /// The size of the torus before it wraps around.
+    pub fn period(&self) -> (f64, f64) {
+        let (w, h) = (self.width as f64, self.height as f64);
+        match self.tiling {
+            Tiling::Orthogonal | Tiling::OrthogonalAndDiagonal => (w, h),
+            Tiling::Hexagons => (w, h * Self::ROW),
+            Tiling::AdjacentTriangles | Tiling::TouchingTriangles => (w / 2.0, h * Self::ROW),
+        }
+    }
+
+    /// The shortest vector from one point to another on the torus.
+    pub fn delta(&self, from: (f64, f64), to: (f64, f64)) -> (f64, f64) {
+        let (pw, ph) = self.period();
+        let wrap = |d: f64, period: f64| d - period * (d / period).round();
+        (wrap(to.0 - from.0, pw), wrap(to.1 - from.1, ph))
+    }
+
+    /// The edge between a cell and the adjacent cell at the given vector from its center. Cells that only share a
+    /// corner have no edge in common.
+    pub fn shared_edge(
+        &self,
+        center: (f64, f64),
+        delta: (f64, f64),
+    ) -> Option<((f64, f64), (f64, f64))> {
+        let (neighbour_distance, edge_length) = match self.tiling {
+            Tiling::Orthogonal | Tiling::OrthogonalAndDiagonal => (1.0, 1.0),
+            Tiling::Hexagons => (1.0, Self::HEXAGON_RADIUS),
+            Tiling::AdjacentTriangles | Tiling::TouchingTriangles => (Self::HEXAGON_RADIUS, 1.0),
+        };
+        let distance = delta.0.hypot(delta.1);
+        if (distance - neighbour_distance).abs() > 0.01 {
+            return None;
+        }
+        let (mx, my) = (center.0 + delta.0 / 2.0, center.1 + delta.1 / 2.0);
+        let scale = edge_length / 2.0 / distance;
+        let (ex, ey) = (-delta.1 * scale, delta.0 * scale);
+        Some(((mx - ex, my - ey), (mx + ex, my + ey)))
+    }
+}
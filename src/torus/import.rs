@@ -0,0 +1,103 @@
+//! Reads initial conditions from an image, so that they can be drawn in an image editor.
+//!
+//! An image with exactly one pixel per cell is read pixel by pixel; for a hexagonal tiling the shift of the even rows
+//! is implied. An image of any other size is stretched over the torus, and each cell takes the shade of the pixel under
+//! its center. This way an image that was exported from a torus can be read back into a torus of the same shape.
+
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use image::GrayImage;
+use log::info;
+
+use crate::{
+    structure::{FromGrayScale, Generation, State},
+    torus::{Torus, geometry::Geometry},
+};
+
+pub struct Picture {
+    img: GrayImage,
+}
+
+impl Picture {
+    /// Opens a PNG, PGM or any other image format that is supported by the `image` crate, and converts it to gray.
+    pub fn open(path: &Path) -> Result<Picture> {
+        let img = image::open(path)
+            .map_err(|e| anyhow!("Could not read image: {path:?}: {e}"))?
+            .into_luma8();
+        if img.width() == 0 || img.height() == 0 {
+            return Err(anyhow!("Image has no pixels: {path:?}"));
+        }
+        info!("Image: {path:?}: ({} x {})", img.width(), img.height());
+        Ok(Picture { img })
+    }
+
+    pub fn width(&self) -> usize {
+        self.img.width() as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.img.height() as usize
+    }
+
+    /// The shade of gray of the cell at the given coordinates of a torus with the given geometry.
+    pub fn gray_value(&self, geometry: &Geometry, x: usize, y: usize) -> u8 {
+        let (width, height) = geometry.size();
+        let (px, py) = if width == self.width() && height == self.height() {
+            (x as u32, y as u32)
+        } else {
+            let (cx, cy) = geometry.center(x, y);
+            let (ex, ey) = geometry.extent();
+            let px = (cx / ex * self.img.width() as f64) as u32;
+            let py = (cy / ey * self.img.height() as f64) as u32;
+            (px.min(self.img.width() - 1), py.min(self.img.height() - 1))
+        };
+        self.img.get_pixel(px, py).0[0]
+    }
+
+    /// Sets the state of each cell of the torus to the state that corresponds to its shade of gray.
+    pub fn initialize<T, S, Gen>(
+        &self,
+        torus: &mut T,
+        generation: &Gen,
+        context: &<S as FromGrayScale>::Context,
+    ) -> Result<()>
+    where
+        T: Torus<S, Gen>,
+        S: State<Gen> + FromGrayScale,
+        Gen: Generation,
+    {
+        let dimensions = torus.dimensions();
+        if dimensions.len() != 2 {
+            return Err(anyhow!("Torus should be two-dimensional"));
+        }
+        let geometry = Geometry::new(torus.tiling(), dimensions[0], dimensions[1]);
+        for y in 0..dimensions[1] {
+            for x in 0..dimensions[0] {
+                let state = S::from_gray_value(self.gray_value(&geometry, x, y), context);
+                torus.adjust(generation, x, y, state)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_an_empty_image() {
+        let path = std::env::temp_dir().join(format!("empty-{}.pgm", std::process::id()));
+        std::fs::write(&path, b"P5\n0 0\n255\n").unwrap();
+        let picture = Picture::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            picture
+                .err()
+                .unwrap()
+                .to_string()
+                .starts_with("Image has no pixels")
+        );
+    }
+}
@@ -1,15 +1,26 @@
+pub mod animation;
+pub mod color;
+pub mod geometry;
 pub mod grayscale;
+pub mod import;
+pub mod render;
+pub mod svg;
 pub mod utils;
 
 use std::path::PathBuf;
 
 pub use utils::get_index;
 
-use crate::structure::{Generation, GrayScale, Space, State};
+use crate::{
+    structure::{Generation, GrayScale, Rgb, Run, Space, State},
+    torus::render::View,
+};
 use anyhow::Result;
+use clap::ValueEnum;
+use image::{GrayImage, RgbImage};
 
-#[allow(dead_code)]
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+#[non_exhaustive]
 pub enum Tiling {
     Orthogonal,
     OrthogonalAndDiagonal,
@@ -23,8 +34,9 @@ pub trait Torus<S: State<Gen>, Gen: Generation>: Sized {
     fn space(&self) -> &Self::Spc;
     fn space_mut(&mut self) -> &mut Self::Spc;
     fn info(&self, generation: &Gen);
-    fn update_all_cells(&mut self, generation: &Gen) -> Result<()>;
+    fn update_all_cells(&mut self, generation: &Gen, run: &Run<S::Parameters>) -> Result<()>;
     fn tiling(&self) -> Tiling;
+    /// The extent of the torus along each axis, starting with the width (x) and the height (y).
     fn dimensions(&self) -> Vec<usize>;
     fn adjust(&mut self, generation: &Gen, x: usize, y: usize, state: S) -> Result<()>;
     fn coordinates(
@@ -32,6 +44,16 @@ pub trait Torus<S: State<Gen>, Gen: Generation>: Sized {
         region: &<Self::Spc as Space<S, Gen>>::Reg,
         location: &<Self::Spc as Space<S, Gen>>::Loc,
     ) -> (usize, usize);
+
+    /// The position of a location along each axis, in the same order as `dimensions`.
+    fn co_ordinates(
+        &self,
+        region: &<Self::Spc as Space<S, Gen>>::Reg,
+        location: &<Self::Spc as Space<S, Gen>>::Loc,
+    ) -> Vec<usize> {
+        let (x, y) = self.coordinates(region, location);
+        vec![x, y]
+    }
 }
 
 pub trait GrayScaleTorus<S, Gen>: Torus<S, Gen>
@@ -43,6 +65,37 @@ where
         &self,
         generation: &Gen,
         context: &<S as GrayScale>::Context,
+        view: &View,
+        export_dir: Option<&PathBuf>,
+    ) -> Result<()>;
+
+    /// Draws the given generation of the torus in shades of gray.
+    fn render(
+        &self,
+        generation: &Gen,
+        context: &<S as GrayScale>::Context,
+        view: &View,
+    ) -> Result<GrayImage>;
+}
+
+pub trait ColorTorus<S, Gen>: Torus<S, Gen>
+where
+    S: State<Gen> + Rgb,
+    Gen: Generation,
+{
+    fn export_color(
+        &self,
+        generation: &Gen,
+        context: &<S as Rgb>::Context,
+        view: &View,
         export_dir: Option<&PathBuf>,
     ) -> Result<()>;
+
+    /// Draws the given generation of the torus in colour.
+    fn render_color(
+        &self,
+        generation: &Gen,
+        context: &<S as Rgb>::Context,
+        view: &View,
+    ) -> Result<RgbImage>;
 }
@@ -0,0 +1,293 @@
+//! Draws a generation of a torus into an image. The pixel type, and with it the colour of each state, is chosen by the caller.
+//!
+//! Two-dimensional tori are drawn whole, in the shape of their tiling. Three-dimensional tori are drawn as a slice
+//! across one of the axes, or as a maximum-intensity projection along one of the axes: each pixel shows the brightest
+//! cell on the line of sight.
+
+use anyhow::{Result, anyhow};
+use clap::Args;
+use image::{ImageBuffer, Pixel};
+use log::{debug, info};
+use std::{fs::OpenOptions, path::Path};
+
+use crate::{
+    structure::{Generation, Space, State},
+    torus::{Tiling, Torus},
+};
+
+pub type Image<P> = ImageBuffer<P, Vec<u8>>;
+
+/// Width and height in pixels of a cell of an orthogonal tiling.
+const SQUARE: u32 = 4;
+
+/// Width of the base and height in pixels of a cell of a triangular tiling. Adjacent triangles overlap by half the base.
+const TRIANGLE_BASE: u32 = 8;
+const TRIANGLE_HEIGHT: u32 = 7;
+
+/// What part of a torus to draw.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum View {
+    /// The whole torus. Only for two-dimensional tori.
+    #[default]
+    Plane,
+    /// The cells with the given co-ordinate along the axis.
+    Slice { axis: usize, index: usize },
+    /// The brightest cell along the axis.
+    Projection { axis: usize },
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ViewOptions {
+    #[arg(
+        help = "export the slice with this co-ordinate along the axis (3-D)",
+        long
+    )]
+    pub slice: Option<usize>,
+
+    #[arg(
+        help = "export a maximum-intensity projection along the axis (3-D)",
+        long
+    )]
+    pub projection: bool,
+
+    #[arg(
+        help = "axis for slices and projections: 0 (x), 1 (y) or 2 (z)",
+        long,
+        default_value_t = 2
+    )]
+    pub axis: usize,
+}
+
+impl ViewOptions {
+    pub fn view(&self) -> Result<View> {
+        match (self.slice, self.projection) {
+            (Some(_), true) => Err(anyhow!("Choose either a slice or a projection")),
+            (Some(index), false) => Ok(View::Slice {
+                axis: self.axis,
+                index,
+            }),
+            (None, true) => Ok(View::Projection { axis: self.axis }),
+            (None, false) => Ok(View::Plane),
+        }
+    }
+}
+
+pub fn render<T, S, Gen, P, F>(
+    torus: &T,
+    generation: &Gen,
+    view: &View,
+    pixel: F,
+) -> Result<Image<P>>
+where
+    T: Torus<S, Gen>,
+    S: State<Gen>,
+    Gen: Generation,
+    P: Pixel<Subpixel = u8>,
+    F: Fn(Option<S>) -> P,
+{
+    let dimensionality = torus.dimensions().len();
+    match (dimensionality, view) {
+        (2, View::Plane) => match torus.tiling() {
+            Tiling::Hexagons => render_hexagons(torus, generation, pixel),
+            Tiling::Orthogonal | Tiling::OrthogonalAndDiagonal => {
+                render_squares(torus, generation, view, pixel)
+            }
+            Tiling::AdjacentTriangles | Tiling::TouchingTriangles => {
+                render_triangles(torus, generation, pixel)
+            }
+        },
+        (3, View::Slice { .. } | View::Projection { .. }) => match torus.tiling() {
+            Tiling::Orthogonal | Tiling::OrthogonalAndDiagonal => {
+                render_squares(torus, generation, view, pixel)
+            }
+            tiling => Err(anyhow!("Tiling is not three-dimensional: [{tiling:?}]")),
+        },
+        (3, View::Plane) => Err(anyhow!(
+            "Three-dimensional torus needs a slice or a projection"
+        )),
+        (2, _) => Err(anyhow!(
+            "Slices and projections need a three-dimensional torus"
+        )),
+        _ => Err(anyhow!(
+            "Torus should be two- or three-dimensional: [{dimensionality}]"
+        )),
+    }
+}
+
+/// Each hexagon is drawn four pixels wide and four pixels high, overlapping one pixel with the hexagons in adjacent rows.
+fn render_hexagons<T, S, Gen, P, F>(torus: &T, generation: &Gen, pixel: F) -> Result<Image<P>>
+where
+    T: Torus<S, Gen>,
+    S: State<Gen>,
+    Gen: Generation,
+    P: Pixel<Subpixel = u8>,
+    F: Fn(Option<S>) -> P,
+{
+    let dimensions = torus.dimensions();
+    let width = dimensions[0];
+    let height = dimensions[1];
+    let mut img = Image::<P>::new((width * 4 + 2) as u32, (height * 3 + 1) as u32);
+
+    let space = torus.space();
+    for region in space.regions(generation) {
+        info!("Exporting region [{region:?}]");
+        for loc in space.locations(&region) {
+            let (x, y) = torus.coordinates(&region, &loc);
+            let xs = if (y % 2) == 0 { 2 } else { 0 };
+            let state = space.state(generation, &loc);
+            debug!("Coordinates: ({x}, {y}) -> [{state:?}]");
+            let color = pixel(state);
+            let xo = (xs + 4 * x) as u32;
+            let yo = 3 * y as u32;
+            for xp in [1, 2] {
+                for yp in 0..=3 {
+                    img.put_pixel(xo + xp, yo + yp, color);
+                }
+            }
+            for xp in [0, 3] {
+                for yp in [1, 2] {
+                    img.put_pixel(xo + xp, yo + yp, color);
+                }
+            }
+        }
+    }
+
+    Ok(img)
+}
+
+/// Each cell is drawn as a square. For a three-dimensional torus the two axes that remain after taking a slice or a
+/// projection become the horizontal and the vertical axis of the image, in that order.
+fn render_squares<T, S, Gen, P, F>(
+    torus: &T,
+    generation: &Gen,
+    view: &View,
+    pixel: F,
+) -> Result<Image<P>>
+where
+    T: Torus<S, Gen>,
+    S: State<Gen>,
+    Gen: Generation,
+    P: Pixel<Subpixel = u8>,
+    F: Fn(Option<S>) -> P,
+{
+    let dimensions = torus.dimensions();
+    let (axis, index) = match *view {
+        View::Plane => (None, None),
+        View::Slice { axis, index } => (Some(axis), Some(index)),
+        View::Projection { axis } => (Some(axis), None),
+    };
+    if let Some(axis) = axis {
+        if axis >= dimensions.len() {
+            return Err(anyhow!("No such axis: [{axis}]: {dimensions:?}"));
+        }
+        if let Some(index) = index
+            && index >= dimensions[axis]
+        {
+            return Err(anyhow!(
+                "Slice outside of the torus: [{index}]: {dimensions:?}"
+            ));
+        }
+    }
+    let axes = (0..dimensions.len())
+        .filter(|a| Some(*a) != axis)
+        .collect::<Vec<_>>();
+    let width = dimensions[axes[0]];
+    let height = dimensions[axes[1]];
+
+    let mut pixels: Vec<Option<P>> = vec![None; width * height];
+    let space = torus.space();
+    for region in space.regions(generation) {
+        info!("Exporting region [{region:?}]");
+        for loc in space.locations(&region) {
+            let co_ordinates = torus.co_ordinates(&region, &loc);
+            if let (Some(axis), Some(index)) = (axis, index)
+                && co_ordinates[axis] != index
+            {
+                continue;
+            }
+            let state = space.state(generation, &loc);
+            debug!("Co-ordinates: {co_ordinates:?} -> [{state:?}]");
+            let color = pixel(state);
+            let target = &mut pixels[co_ordinates[axes[1]] * width + co_ordinates[axes[0]]];
+            let brighter = target.is_none_or(|c| color.to_luma().0[0] > c.to_luma().0[0]);
+            if brighter {
+                *target = Some(color);
+            }
+        }
+    }
+
+    let mut img = Image::<P>::new(width as u32 * SQUARE, height as u32 * SQUARE);
+    for (i, color) in pixels.into_iter().enumerate() {
+        let Some(color) = color else {
+            continue;
+        };
+        let xo = (i % width) as u32 * SQUARE;
+        let yo = (i / width) as u32 * SQUARE;
+        for xp in 0..SQUARE {
+            for yp in 0..SQUARE {
+                img.put_pixel(xo + xp, yo + yp, color);
+            }
+        }
+    }
+
+    Ok(img)
+}
+
+/// Triangles point up where the sum of the co-ordinates is even and down where it is odd, so that each row alternates.
+fn render_triangles<T, S, Gen, P, F>(torus: &T, generation: &Gen, pixel: F) -> Result<Image<P>>
+where
+    T: Torus<S, Gen>,
+    S: State<Gen>,
+    Gen: Generation,
+    P: Pixel<Subpixel = u8>,
+    F: Fn(Option<S>) -> P,
+{
+    let dimensions = torus.dimensions();
+    let width = dimensions[0] as u32;
+    let height = dimensions[1] as u32;
+    let half_base = TRIANGLE_BASE / 2;
+    let mut img = Image::<P>::new(width * half_base + half_base, height * TRIANGLE_HEIGHT);
+
+    let space = torus.space();
+    for region in space.regions(generation) {
+        info!("Exporting region [{region:?}]");
+        for loc in space.locations(&region) {
+            let (x, y) = torus.coordinates(&region, &loc);
+            let up = (x + y).is_multiple_of(2);
+            let state = space.state(generation, &loc);
+            debug!("Coordinates: ({x}, {y}) -> [{state:?}]");
+            let color = pixel(state);
+            let xo = x as u32 * half_base;
+            let yo = y as u32 * TRIANGLE_HEIGHT;
+            let center = half_base as f64;
+            for yp in 0..TRIANGLE_HEIGHT {
+                let depth = (yp as f64 + 0.5) / TRIANGLE_HEIGHT as f64;
+                let spread = if up { depth } else { 1.0 - depth };
+                for xp in 0..TRIANGLE_BASE {
+                    if (xp as f64 + 0.5 - center).abs() < spread * center {
+                        img.put_pixel(xo + xp, yo + yp, color);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(img)
+}
+
+pub fn write_png<Gen, P>(img: &Image<P>, generation: &Gen, dir: &Path) -> Result<()>
+where
+    Gen: Generation,
+    P: Pixel<Subpixel = u8> + image::PixelWithColorType,
+{
+    let mut file_path = dir.to_path_buf();
+    file_path.push(format!("gen-{generation:?}.png"));
+    let mut writer = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(file_path)?;
+    img.write_to(&mut writer, image::ImageFormat::Png)?;
+    Ok(())
+}
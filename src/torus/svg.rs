@@ -0,0 +1,182 @@
+//! Draws a generation of a two-dimensional torus as an SVG image. Unlike the PNG renderer, each cell is drawn as the
+//! polygon of its tiling, and the drawing can show the structure of the space: the links between cells and their
+//! effectors, and the boundaries between regions (the patches of a `PatchTorus`).
+//!
+//! Coordinates are in units of the distance between the centers of adjacent squares or hexagons. A link is drawn from
+//! the center of a cell halfway to the center of its effector, so that links that wrap around the torus stay short.
+//! Links that pass through a copy of a cell, like the halo of a patch, are drawn dashed.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    fs::{File, create_dir_all},
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use clap::Args;
+use log::info;
+
+use crate::{
+    structure::{Generation, Location, Space, State},
+    torus::{Torus, geometry::Geometry},
+};
+
+/// Size in pixels of a unit of the drawing.
+const SCALE: f64 = 20.0;
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct SvgOptions {
+    #[arg(help = "directory to export SVG files", long)]
+    pub svg_dir: Option<PathBuf>,
+
+    #[arg(help = "draw the links between cells and their effectors (SVG)", long)]
+    pub links: bool,
+
+    #[arg(help = "draw the boundaries of patches (SVG)", long)]
+    pub patches: bool,
+}
+
+/// Writes `gen-<generation>.svg` to the SVG directory of the options, if there is one.
+pub fn export_svg<T, S, Gen, F>(
+    torus: &T,
+    generation: &Gen,
+    options: &SvgOptions,
+    fill: F,
+) -> Result<()>
+where
+    T: Torus<S, Gen>,
+    S: State<Gen>,
+    Gen: Generation,
+    <T::Spc as Space<S, Gen>>::Loc: Clone + PartialEq,
+    F: Fn(Option<S>) -> [u8; 3],
+{
+    if let Some(dir) = options.svg_dir.as_ref() {
+        create_dir_all(dir)?;
+        info!("Exporting generation as SVG [{generation:?}]");
+        let svg = render_svg(torus, generation, options, fill)?;
+        write_svg(&svg, generation, dir)?;
+    }
+    Ok(())
+}
+
+pub fn render_svg<T, S, Gen, F>(
+    torus: &T,
+    generation: &Gen,
+    options: &SvgOptions,
+    fill: F,
+) -> Result<String>
+where
+    T: Torus<S, Gen>,
+    S: State<Gen>,
+    Gen: Generation,
+    <T::Spc as Space<S, Gen>>::Loc: Clone + PartialEq,
+    F: Fn(Option<S>) -> [u8; 3],
+{
+    let dimensions = torus.dimensions();
+    if dimensions.len() != 2 {
+        return Err(anyhow!("Torus should be two-dimensional"));
+    }
+    let geometry = Geometry::new(torus.tiling(), dimensions[0], dimensions[1]);
+    let space = torus.space();
+
+    let mut cells = Vec::new();
+    let mut regions = HashMap::new();
+    for (r, region) in space.regions(generation).into_iter().enumerate() {
+        for location in space.locations(&region) {
+            let (x, y) = torus.coordinates(&region, &location);
+            regions.insert((x, y), r);
+            cells.push((location, (x, y)));
+        }
+    }
+
+    let mut polygons = String::new();
+    let mut links = String::new();
+    let mut boundaries = String::new();
+    for (location, (x, y)) in cells.iter() {
+        let [red, green, blue] = fill(space.state(generation, location));
+        let points = geometry
+            .polygon(*x, *y)
+            .iter()
+            .map(|(px, py)| format!("{px:.4},{py:.4}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(
+            polygons,
+            r#"<polygon points="{points}" fill="rgb({red},{green},{blue})"/>"#
+        )?;
+        if !options.links && !options.patches {
+            continue;
+        }
+        let (cx, cy) = geometry.center(*x, *y);
+        for (effector, weight) in location.weighted_effectors(space)? {
+            let canonical = effector.canonical(space);
+            let Some((ex, ey)) = space
+                .region(generation, &canonical)
+                .map(|region| torus.coordinates(&region, &canonical))
+            else {
+                continue;
+            };
+            let (dx, dy) = geometry.delta((cx, cy), geometry.center(ex, ey));
+            if options.links {
+                let dash = if canonical != effector {
+                    r#" stroke-dasharray="0.08,0.06""#
+                } else {
+                    ""
+                };
+                writeln!(
+                    links,
+                    r#"<line x1="{cx:.4}" y1="{cy:.4}" x2="{:.4}" y2="{:.4}" stroke-width="{:.4}"{dash}/>"#,
+                    cx + dx / 2.0,
+                    cy + dy / 2.0,
+                    0.04 * weight.abs().min(4.0)
+                )?;
+            }
+            if options.patches
+                && regions.get(&(*x, *y)) != regions.get(&(ex, ey))
+                && let Some(((x1, y1), (x2, y2))) = geometry.shared_edge((cx, cy), (dx, dy))
+            {
+                writeln!(
+                    boundaries,
+                    r#"<line x1="{x1:.4}" y1="{y1:.4}" x2="{x2:.4}" y2="{y2:.4}"/>"#
+                )?;
+            }
+        }
+    }
+
+    let (width, height) = geometry.extent();
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="0 0 {width:.4} {height:.4}">"#,
+        width * SCALE,
+        height * SCALE
+    )?;
+    writeln!(svg, r##"<g stroke="#808080" stroke-width="0.02">"##)?;
+    svg.push_str(&polygons);
+    writeln!(svg, "</g>")?;
+    if options.links {
+        writeln!(svg, r##"<g stroke="#202020" stroke-linecap="round">"##)?;
+        svg.push_str(&links);
+        writeln!(svg, "</g>")?;
+    }
+    if options.patches {
+        writeln!(
+            svg,
+            r##"<g stroke="#e08000" stroke-width="0.12" stroke-linecap="round">"##
+        )?;
+        svg.push_str(&boundaries);
+        writeln!(svg, "</g>")?;
+    }
+    writeln!(svg, "</svg>")?;
+    Ok(svg)
+}
+
+pub fn write_svg<Gen: Generation>(svg: &str, generation: &Gen, dir: &Path) -> Result<()> {
+    let mut file_path = dir.to_path_buf();
+    file_path.push(format!("gen-{generation:?}.svg"));
+    let mut file = File::create(file_path)?;
+    file.write_all(svg.as_bytes())?;
+    Ok(())
+}
@@ -10,10 +10,8 @@ pub fn get_index(co_ordinates: &[usize], dimensions: &[usize]) -> Result<usize>
         ));
     }
     let mut result = co_ordinates[0];
-    if dimensionality > 1 {
-        for offset in 0..dimensionality - 1 {
-            result = result * dimensions[offset] + co_ordinates[offset + 1];
-        }
+    for offset in 1..dimensionality {
+        result = result * dimensions[offset] + co_ordinates[offset];
     }
     Ok(result)
 }
@@ -0,0 +1,326 @@
+//! Outer-totalistic cellular automata, given by a rule string in B/S notation.
+//!
+//! A rule like `B3/S23` says that a dead cell is born if it has three live neighbours, and that a live cell survives if
+//! it has two or three live neighbours. Rules in the legacy S/B notation, like `23/3`, are accepted as well.
+//!
+//! Generations rules add a number of states, like `B2/S/C3` or `/2/3`: a live cell that does not survive does not die
+//! at once, but passes through the dying states 2, 3, ... before it becomes dead. Dying cells do not count as live
+//! neighbours, and they cannot be born again until they are dead.
+//!
+//! Counts from 10 to 15, for tilings with more than nine neighbours like `Tiling::TouchingTriangles`, are written as the
+//! hexadecimal digits `A` to `F`: `B3A/S2C` says that a dead cell with three or ten live neighbours is born. Only the
+//! B/S notation allows them, because in the legacy notation a leading `B` or `C` would be taken for a prefix.
+//!
+//! A suffix `H` (hexagonal) or `V` (von Neumann) chooses the neighbourhood that the rule was written for. Without a
+//! suffix the rule is meant for the Moore neighbourhood of `Tiling::OrthogonalAndDiagonal`.
+//! See <https://conwaylife.com/wiki/Rulestring>.
+
+use std::{
+    fmt::{Display, Write},
+    str::FromStr,
+};
+
+use anyhow::{Result, anyhow};
+use log::trace;
+
+use crate::{
+    structure::{
+        FromGrayScale, Generation, GrayScale, Location, Region, Space, State, UpdateContext,
+    },
+    torus::Tiling,
+};
+
+/// The largest number of neighbours that a rule can refer to.
+pub const MAX_NEIGHBOURS: u8 = 15;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Rule {
+    /// Bit `n` is set if a dead cell with `n` live neighbours is born.
+    birth: u16,
+    /// Bit `n` is set if a live cell with `n` live neighbours survives.
+    survival: u16,
+    /// The number of states, including dead and alive. Two for Life-like rules.
+    states: u8,
+    /// The neighbourhood that the rule was written for, if the rule string says so.
+    neighbourhood: Option<Tiling>,
+}
+
+impl Rule {
+    pub fn neighbourhood(&self) -> Option<Tiling> {
+        self.neighbourhood
+    }
+
+    fn born(&self, count: u8) -> bool {
+        count <= MAX_NEIGHBOURS && self.birth & (1 << count) != 0
+    }
+
+    fn survives(&self, count: u8) -> bool {
+        count <= MAX_NEIGHBOURS && self.survival & (1 << count) != 0
+    }
+}
+
+impl Default for Rule {
+    /// Conway's Life: `B3/S23`.
+    fn default() -> Self {
+        Rule {
+            birth: 1 << 3,
+            survival: (1 << 2) | (1 << 3),
+            states: 2,
+            neighbourhood: None,
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut text = s
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_uppercase();
+        let neighbourhood = match text.chars().last() {
+            Some('H') => Some(Tiling::Hexagons),
+            Some('V') => Some(Tiling::Orthogonal),
+            _ => None,
+        };
+        if neighbourhood.is_some() {
+            text.pop();
+        }
+        let parts = text.split('/').collect::<Vec<_>>();
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err(anyhow!("Rule should have two or three parts: [{s}]"));
+        }
+        let counts = |digits: &str| -> Result<u16> {
+            let mut mask = 0u16;
+            for c in digits.chars() {
+                let n = c
+                    .to_digit(16)
+                    .ok_or_else(|| anyhow!("Unexpected character in rule: [{c}]: [{s}]"))?;
+                mask |= 1 << n;
+            }
+            Ok(mask)
+        };
+        let mut birth = None;
+        let mut survival = None;
+        let mut states = None;
+        for (i, part) in parts.iter().enumerate() {
+            if let Some(digits) = part.strip_prefix('B') {
+                birth = Some(counts(digits)?);
+            } else if let Some(digits) = part.strip_prefix('S') {
+                survival = Some(counts(digits)?);
+            } else if let Some(number) = part.strip_prefix('C').or_else(|| part.strip_prefix('G')) {
+                states = Some(number.parse::<u8>()?);
+            } else if i == 2 {
+                states = Some(part.parse::<u8>()?);
+            } else if i == 0 {
+                // Legacy notation: survival before birth.
+                survival = Some(counts(part)?);
+            } else {
+                birth = Some(counts(part)?);
+            }
+        }
+        let states = states.unwrap_or(2);
+        if states < 2 {
+            return Err(anyhow!("Rule should have at least two states: [{s}]"));
+        }
+        Ok(Rule {
+            birth: birth.ok_or_else(|| anyhow!("Rule without birth counts: [{s}]"))?,
+            survival: survival.ok_or_else(|| anyhow!("Rule without survival counts: [{s}]"))?,
+            states,
+            neighbourhood,
+        })
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = |mask: u16| {
+            (0..=MAX_NEIGHBOURS as u32)
+                .filter(|n| mask & (1 << n) != 0)
+                .filter_map(|n| char::from_digit(n, 16))
+                .map(|c| c.to_ascii_uppercase())
+                .collect::<String>()
+        };
+        write!(f, "B{}/S{}", digits(self.birth), digits(self.survival))?;
+        if self.states > 2 {
+            write!(f, "/C{}", self.states)?;
+        }
+        match self.neighbourhood {
+            Some(Tiling::Hexagons) => f.write_char('H'),
+            Some(Tiling::Orthogonal) => f.write_char('V'),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A cell of an outer-totalistic automaton. Each cell carries the rule rather than taking it from the parameters of the
+/// run, because the gray values of the dying states depend on it as well.
+#[derive(Clone, Copy, Debug)]
+pub struct Totalistic {
+    pub rule: Rule,
+    /// Zero for dead, one for alive, and higher values for the dying states of Generations rules.
+    pub value: u8,
+}
+
+impl Totalistic {
+    pub fn new(rule: Rule, alive: bool) -> Totalistic {
+        Totalistic {
+            rule,
+            value: if alive { 1 } else { 0 },
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.value == 1
+    }
+}
+
+impl<Gen: Generation> State<Gen> for Totalistic {
+    type Parameters = ();
+
+    fn update<Spc: Space<Self, Gen>>(
+        space: &Spc,
+        region: &Spc::Reg,
+        location: &Spc::Loc,
+        _context: &UpdateContext<(), Gen>,
+    ) -> Result<Self> {
+        trace!("Update: [{}]", location.id(space));
+        let this_state = (region.state(location) as Option<Self>)
+            .ok_or_else(|| anyhow!("Cell without state: [{}]", location.id(space)))?;
+        let rule = this_state.rule;
+        let mut count = 0u8;
+        for effector in location.effectors(space)? {
+            if let Some(state) = region.state(&effector) as Option<Self>
+                && state.is_alive()
+            {
+                count = count.saturating_add(1);
+            }
+        }
+        let value = match this_state.value {
+            0 if rule.born(count) => 1,
+            0 => 0,
+            1 if rule.survives(count) => 1,
+            v if v + 1 < rule.states => v + 1,
+            _ => 0,
+        };
+        Ok(Totalistic { rule, value })
+    }
+}
+
+impl Display for Totalistic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_char(match self.value {
+            0 => ' ',
+            1 => '#',
+            _ => '+',
+        })
+    }
+}
+
+/// Live cells are white and dead cells are black. Dying cells fade from white to black.
+impl GrayScale for Totalistic {
+    type Context = ();
+
+    fn gray_value(&self, _context: &()) -> u8 {
+        if self.value == 0 {
+            return 0;
+        }
+        let steps = (self.rule.states - 1) as u32;
+        (255 * (self.rule.states as u32 - self.value as u32) / steps) as u8
+    }
+}
+
+/// Reads back the shades of `GrayScale`: each shade becomes the state with the nearest gray value.
+impl FromGrayScale for Totalistic {
+    type Context = Rule;
+
+    fn from_gray_value(value: u8, rule: &Rule) -> Self {
+        let steps = (rule.states - 1) as u32;
+        let fade = (value as u32 * steps + 127) / 255;
+        let value = rule.states as u32 - fade;
+        Totalistic {
+            rule: *rule,
+            value: if value >= rule.states as u32 {
+                0
+            } else {
+                value as u8
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Rule {
+        text.parse()
+            .unwrap_or_else(|e| panic!("Could not parse [{text}]: {e}"))
+    }
+
+    #[test]
+    fn display_then_parse() {
+        for text in [
+            "B3/S23",
+            "B36/S23",
+            "B2/S34H",
+            "B2/S/C3",
+            "B1357/S1357V",
+            "B/S012345678",
+            "B3A/S2C",
+            "B0123456789ABCDEF/S/C4",
+        ] {
+            let rule = parse(text);
+            assert_eq!(rule.to_string(), text);
+            assert_eq!(parse(&rule.to_string()), rule);
+        }
+    }
+
+    #[test]
+    fn counts_above_nine() {
+        let rule = parse("b3a/s2cf");
+        assert_eq!(rule.birth, (1 << 3) | (1 << 10));
+        assert_eq!(rule.survival, (1 << 2) | (1 << 12) | (1 << 15));
+        assert!(rule.born(10) && !rule.born(11));
+        assert!(rule.survives(15));
+        assert_eq!(rule.to_string(), "B3A/S2CF");
+    }
+
+    #[test]
+    fn default_is_life() {
+        assert_eq!(parse("B3/S23"), Rule::default());
+        assert_eq!(parse("b3/s23"), Rule::default());
+        assert_eq!(parse(" B3 / S23 "), Rule::default());
+    }
+
+    #[test]
+    fn legacy_survival_before_birth() {
+        assert_eq!(parse("23/3"), Rule::default());
+        assert_eq!(parse("34/2H"), parse("B2/S34H"));
+    }
+
+    #[test]
+    fn legacy_generations() {
+        let rule = parse("/2/3");
+        assert_eq!(rule, parse("B2/S/C3"));
+        assert_eq!(rule.states, 3);
+        assert_eq!(rule.survival, 0);
+        assert_eq!(rule.birth, 1 << 2);
+    }
+
+    #[test]
+    fn neighbourhood_from_suffix() {
+        assert_eq!(parse("B2/S34H").neighbourhood(), Some(Tiling::Hexagons));
+        assert_eq!(parse("B2/S34V").neighbourhood(), Some(Tiling::Orthogonal));
+        assert_eq!(parse("B2/S34").neighbourhood(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        for text in ["B3", "B3/S23/C1", "B3/S23/C3/X", "B3/SX", "", "B3/B3"] {
+            assert!(text.parse::<Rule>().is_err(), "Accepted [{text}]");
+        }
+    }
+}
@@ -1,71 +1,213 @@
 use crate::{
     cell::new_cell_torus,
-    patch::new_hexagonal_torus,
-    structure::{Generation, GrayScale, Location, Region, Space, State},
-    torus::{GrayScaleTorus, Tiling, Torus, get_index},
+    palette::{ColorMap, Diverging, channels},
+    patch::{
+        AtMostSixEffectors, Effectors, Large, Medium, PatchSize, PatchSizeChoice, PatchTorus,
+        Small, TorusPatchLinks, new_patch_torus, plan_patch_size,
+    },
+    simulation::{Cadence, Simulation},
+    structure::{
+        FromGrayScale, Generation, GrayScale, Location, Region, Rgb, Space, State, UpdateContext,
+    },
+    torus::{
+        ColorTorus, GrayScaleTorus, Tiling, Torus,
+        animation::{Animation, AnimationOptions, Recorder},
+        geometry::Geometry,
+        get_index,
+        import::Picture,
+        render::View,
+        svg::{SvgOptions, export_svg},
+    },
 };
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
 use std::{
     cmp,
-    f64::{MAX, consts::PI},
-    fmt::{Display, Write},
+    f64::consts::PI,
+    fmt::{Debug, Display, Write},
+    marker::PhantomData,
     path::PathBuf,
 };
 // use log::debug;
 use log::{info, trace};
 
+/// A cell of a wave. The type parameter is the scheme that computes the next amplitude (see `Integrator`).
 #[derive(Copy, Clone, Debug, Default)]
-pub struct Wave {
+pub struct Wave<K: Integrator = VelocityScheme> {
     amplitude: f64,
     velocity: f64,
     is_center: bool,
     effector_count: Option<u8>,
+    _scheme: PhantomData<K>,
 }
 
-impl Wave {
-    pub fn new(amplitude: f64, is_center: bool) -> Wave {
+impl<K: Integrator> Wave<K> {
+    pub fn new(amplitude: f64, is_center: bool) -> Wave<K> {
         Wave {
             amplitude,
             velocity: 0.0,
             is_center,
             effector_count: None,
+            _scheme: PhantomData,
         }
     }
+
+    pub fn amplitude(&self) -> f64 {
+        self.amplitude
+    }
+
+    pub fn is_center(&self) -> bool {
+        self.is_center
+    }
+}
+
+/// How a cell computes its next amplitude, as chosen on the command line. Each scheme is a type that implements
+/// `Integrator`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+#[non_exhaustive]
+pub enum Scheme {
+    /// Each cell keeps its velocity: the differences with the effectors change the velocity, and the velocity changes
+    /// the amplitude.
+    #[default]
+    Velocity,
+    /// The next amplitude follows from the current and the previous amplitude (leapfrog): `a' = 2a - a⁻ + Δ`, where
+    /// `Δ` comes from the differences with the effectors. The velocity is only used to find the previous amplitude of
+    /// the first generation.
+    Leapfrog,
+}
+
+/// Computes the next amplitude and velocity of a cell that is not the center.
+pub trait Integrator: Copy + Debug + Default + 'static {
+    /// The number of generations before the current one that the scheme reads (see `State::LOOKBACK`).
+    const LOOKBACK: usize;
+
+    /// The value to which the differences with the effectors are added.
+    fn start(this_state: &Wave<Self>) -> f64;
+
+    /// Returns the next amplitude and velocity, given the sum of the start and the differences with the effectors.
+    fn step<Spc: Space<Wave<Self>, usize>>(
+        space: &Spc,
+        location: &Spc::Loc,
+        context: &UpdateContext<WaveParameters, usize>,
+        this_state: &Wave<Self>,
+        sum: f64,
+    ) -> (f64, f64);
+}
+
+/// See `Scheme::Velocity`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VelocityScheme;
+
+impl Integrator for VelocityScheme {
+    const LOOKBACK: usize = 0;
+
+    /// The differences with the effectors change the velocity.
+    fn start(this_state: &Wave<Self>) -> f64 {
+        this_state.velocity
+    }
+
+    fn step<Spc: Space<Wave<Self>, usize>>(
+        _space: &Spc,
+        _location: &Spc::Loc,
+        _context: &UpdateContext<WaveParameters, usize>,
+        this_state: &Wave<Self>,
+        sum: f64,
+    ) -> (f64, f64) {
+        (this_state.amplitude + this_state.velocity, sum)
+    }
+}
+
+/// See `Scheme::Leapfrog`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LeapfrogScheme;
+
+impl Integrator for LeapfrogScheme {
+    /// The scheme reads the previous amplitude.
+    const LOOKBACK: usize = 1;
+
+    fn start(_this_state: &Wave<Self>) -> f64 {
+        0.0
+    }
+
+    fn step<Spc: Space<Wave<Self>, usize>>(
+        space: &Spc,
+        location: &Spc::Loc,
+        context: &UpdateContext<WaveParameters, usize>,
+        this_state: &Wave<Self>,
+        sum: f64,
+    ) -> (f64, f64) {
+        let previous_amplitude = context
+            .generation()
+            .checked_sub(1)
+            .and_then(|previous| space.state(&previous, location) as Option<Wave<Self>>)
+            .map(|state| state.amplitude)
+            .unwrap_or(this_state.amplitude - this_state.velocity);
+        let next_amplitude = 2.0 * this_state.amplitude - previous_amplitude + sum;
+        (next_amplitude, next_amplitude - this_state.amplitude)
+    }
 }
 
-impl State<usize> for Wave {
+/// The constants of the wave.
+#[derive(Clone, Copy, Debug)]
+pub struct WaveParameters {
+    /// How strongly a difference in amplitude with an effector changes the velocity of a cell.
+    pub coupling: f64,
+    /// The amplitude of the oscillation of the center.
+    pub drive_amplitude: f64,
+    /// The number of generations per radian of the oscillation of the center.
+    pub drive_period: f64,
+}
+
+impl Default for WaveParameters {
+    fn default() -> WaveParameters {
+        WaveParameters {
+            coupling: 0.005,
+            drive_amplitude: 30.0,
+            drive_period: 40.0,
+        }
+    }
+}
+
+impl<K: Integrator> State<usize> for Wave<K> {
+    type Parameters = WaveParameters;
+
+    const LOOKBACK: usize = K::LOOKBACK;
+
     fn update<Spc: Space<Self, usize>>(
         space: &Spc,
         region: &Spc::Reg,
         location: &Spc::Loc,
+        context: &UpdateContext<WaveParameters, usize>,
     ) -> Result<Self> {
+        let parameters = context.parameters();
         trace!("Update: [{}]", location.id(space));
         let this_state: Self = region.state(location).unwrap_or_default();
         trace!("This state: [{this_state:?}]");
-        let effectors = location.effectors(space)?;
+        let effectors = location.weighted_effectors(space)?;
         let mut next_amplitude = this_state.amplitude;
         let mut next_velocity = this_state.velocity;
-        next_amplitude += next_velocity;
+        let mut sum = K::start(&this_state);
         let mut count = 0;
         let mut err = 0;
         if this_state.is_center {
-            let generation = region.generation();
-            let angle = (generation as f64) / 40.0;
-            next_amplitude = angle.sin() * 30.0;
+            let angle = (*context.generation() as f64) / parameters.drive_period;
+            next_amplitude = angle.sin() * parameters.drive_amplitude;
             next_velocity = angle.cos();
             for _ in effectors {
                 count += 1;
             }
         } else if let Some(this_c) = this_state.effector_count {
-            for effector in effectors {
-                trace!("Effector: [{}]", effector.id(space));
-                if let Some(other_state) = region.state(&effector) as Option<Wave> {
+            for (effector, weight) in effectors {
+                trace!("Effector: [{}]: {weight}", effector.id(space));
+                if let Some(other_state) = region.state(&effector) as Option<Wave<K>> {
                     trace!("Effector state: [{:?}]", other_state);
                     if let Some(c) = other_state.effector_count {
                         let max_c = cmp::max(this_c, c);
-                        let delta =
-                            (other_state.amplitude - this_state.amplitude) * 0.005 / (max_c as f64);
-                        next_velocity += delta;
+                        let delta = (other_state.amplitude - this_state.amplitude)
+                            * parameters.coupling
+                            / (max_c as f64)
+                            * weight;
+                        sum += delta;
                     }
                     count += 1;
                 } else {
@@ -83,18 +225,22 @@ impl State<usize> for Wave {
             count,
             err
         );
+        if !this_state.is_center {
+            (next_amplitude, next_velocity) = K::step(space, location, context, &this_state, sum);
+        }
         let new_count = if count > 0 { Some(count) } else { None };
         let result = Wave {
             amplitude: next_amplitude,
             velocity: next_velocity,
             is_center: this_state.is_center,
             effector_count: new_count,
+            _scheme: PhantomData,
         };
         Ok(result)
     }
 }
 
-impl Display for Wave {
+impl<K: Integrator> Display for Wave<K> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // let s = format!("{:5.2}", self.amplitude);
         // f.write_str(&s)?;
@@ -112,7 +258,7 @@ impl Display for Wave {
     }
 }
 
-impl GrayScale for Wave {
+impl<K: Integrator> GrayScale for Wave<K> {
     type Context = f64;
 
     fn gray_value(&self, smallest_local_maximum: &f64) -> u8 {
@@ -122,74 +268,323 @@ impl GrayScale for Wave {
     }
 }
 
-pub fn example(
+/// The inverse of the gray scale: a shade of gray is read back as the amplitude it would have been exported from.
+impl<K: Integrator> FromGrayScale for Wave<K> {
+    type Context = f64;
+
+    fn from_gray_value(value: u8, smallest_local_maximum: &f64) -> Self {
+        let value = ((value as f64 - 128.0) / 127.0).clamp(-0.99, 0.99);
+        let magnitude = (value * PI / 2.0).tan();
+        Wave::new(magnitude * smallest_local_maximum, false)
+    }
+}
+
+/// The ways to show a wave in colour.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+#[non_exhaustive]
+pub enum WavePalette {
+    /// Blue for troughs, white for rest, red for crests.
+    Diverging,
+    /// Crests in the red channel, troughs in the blue channel and speed in the green channel.
+    Channels,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WaveColors {
+    pub scale: f64,
+    pub palette: WavePalette,
+}
+
+impl<K: Integrator> Rgb for Wave<K> {
+    type Context = WaveColors;
+
+    fn rgb_value(&self, colors: &WaveColors) -> [u8; 3] {
+        let magnitude = (self.amplitude / colors.scale).atan() * 2.0 / PI;
+        match colors.palette {
+            WavePalette::Diverging => Diverging::default().color(magnitude),
+            WavePalette::Channels => {
+                let speed = (self.velocity / colors.scale).abs().atan() * 2.0 / PI;
+                channels(magnitude, speed * 4.0, -magnitude)
+            }
+        }
+    }
+}
+
+pub(crate) fn example(
     patched: bool,
+    patch_size: Option<PatchSizeChoice>,
+    obstacles: Obstacles,
     size: usize,
     height: Option<usize>,
-    export_dir: Option<&PathBuf>,
+    start: &Start,
+    output: &Output,
+) -> Result<()> {
+    match start.scheme {
+        Scheme::Velocity => scheme_example::<VelocityScheme>(
+            patched, patch_size, obstacles, size, height, start, output,
+        ),
+        Scheme::Leapfrog => scheme_example::<LeapfrogScheme>(
+            patched, patch_size, obstacles, size, height, start, output,
+        ),
+    }
+}
+
+fn scheme_example<K: Integrator>(
+    patched: bool,
+    patch_size: Option<PatchSizeChoice>,
+    obstacles: Obstacles,
+    size: usize,
+    height: Option<usize>,
+    start: &Start,
+    output: &Output,
 ) -> Result<()> {
     if patched {
-        patched_example(size, height, export_dir)?
+        let width = size;
+        let height = height.unwrap_or(size);
+        let patch_size =
+            patch_size.unwrap_or_else(|| plan_patch_size::<Wave<K>>(width, height, MAX_EFFECTORS));
+        info!("Patch size: [{patch_size:?}]");
+        match patch_size {
+            PatchSizeChoice::Small => {
+                patched_example::<Small, K>(width, height, obstacles, start, output)?
+            }
+            PatchSizeChoice::Medium => {
+                patched_example::<Medium, K>(width, height, obstacles, start, output)?
+            }
+            PatchSizeChoice::Large => {
+                patched_example::<Large, K>(width, height, obstacles, start, output)?
+            }
+        }
+    } else if obstacles.double_slit || obstacles.lens || obstacles.medium.is_some() {
+        return Err(anyhow!("Obstacles require a PatchTorus"));
     } else {
-        cell_example(size, export_dir)?
+        cell_example::<K>(size, height.unwrap_or(size), start, output)?
     }
     Ok(())
 }
 
-fn patched_example(size: usize, height: Option<usize>, export_dir: Option<&PathBuf>) -> Result<()> {
-    let width = size;
-    let height = height.unwrap_or(size);
+/// The number of neighbours of a cell in a hexagonal tiling.
+pub const MAX_EFFECTORS: usize = 6;
+
+/// Things to put in the path of the wave.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Obstacles<'a> {
+    pub double_slit: bool,
+    pub lens: bool,
+    /// An image of the medium: black cells are barriers, and darker cells slow the wave down.
+    pub medium: Option<&'a Picture>,
+}
+
+/// The initial amplitudes and the constants of the wave.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Start<'a> {
+    pub initial: Option<&'a Picture>,
+    pub scheme: Scheme,
+    pub parameters: WaveParameters,
+}
+
+/// Where and how to show the run.
+pub(crate) struct Output<'a> {
+    pub export_dir: Option<&'a PathBuf>,
+    pub animation: &'a AnimationOptions,
+    /// Show the wave in colour instead of shades of gray.
+    pub color: Option<WavePalette>,
+    pub svg: &'a SvgOptions,
+}
+
+fn patched_example<P: PatchSize, K: Integrator>(
+    width: usize,
+    height: usize,
+    obstacles: Obstacles,
+    start: &Start,
+    output: &Output,
+) -> Result<()> {
     let generation = 0usize;
 
-    let init = Wave::new(0.0, false);
-    let torus = new_hexagonal_torus(init, generation.clone(), width, height)?;
+    let init = Wave::<K>::new(0.0, false);
+    let mut torus = new_patch_torus::<_, _, AtMostSixEffectors<P>>(
+        Tiling::Hexagons,
+        init,
+        generation,
+        width,
+        height,
+    )?;
+    if obstacles.double_slit {
+        add_double_slit(&mut torus, width, height)?;
+    }
+    if obstacles.lens {
+        add_lens(&mut torus, width, height)?;
+    }
+    if let Some(medium) = obstacles.medium {
+        add_medium(&mut torus, medium)?;
+    }
 
-    run_example(torus, generation, export_dir)
+    run_example(torus, generation, start, output)
 }
 
-fn cell_example(size: usize, export_dir: Option<&PathBuf>) -> Result<()> {
-    let width = size;
-    let height = size;
+/// Puts a wall of vacancies between the center and the right edge of the torus, with two narrow openings.
+fn add_double_slit<E: Effectors, K: Integrator>(
+    torus: &mut PatchTorus<Wave<K>, usize, TorusPatchLinks<E>>,
+    width: usize,
+    height: usize,
+) -> Result<()> {
+    let x = width * 3 / 4;
+    let cy = height / 2;
+    let distance = cmp::max(height / 8, 2);
+    for y in 0..height {
+        let d = y.abs_diff(cy);
+        if d + 1 < distance || d > distance + 1 {
+            torus.add_vacancy(x, y)?;
+        }
+    }
+    Ok(())
+}
+
+/// Puts a disc of slow medium between the center and the right edge of the torus.
+/// The links between cells in the disc have half the weight, so the wave travels slower and is refracted at the boundary.
+fn add_lens<E: Effectors, K: Integrator>(
+    torus: &mut PatchTorus<Wave<K>, usize, TorusPatchLinks<E>>,
+    width: usize,
+    height: usize,
+) -> Result<()> {
+    let lx = width * 3 / 4;
+    let ly = height / 2;
+    let radius = cmp::min(width, height) / 8;
+    if radius < 2 {
+        return Err(anyhow!(
+            "Torus is too small for a lens: ({width} x {height})"
+        ));
+    }
+    let inside = |x: usize, y: usize| {
+        let dx = x.abs_diff(lx);
+        let dy = y.abs_diff(ly);
+        dx * dx + dy * dy <= radius * radius
+    };
+    for y in (ly - radius)..=(ly + radius) {
+        for x in (lx - radius)..=(lx + radius) {
+            if inside(x, y) {
+                torus.set_weights(x, y, |ex, ey| if inside(ex, ey) { 0.5 } else { 1.0 })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Black cells of the image become vacancies. In other cells the wave travels slower in proportion to the shade of
+/// gray: the weight of a link is the brightness of the darker of the two cells, so white is an ordinary medium.
+fn add_medium<E: Effectors, K: Integrator>(
+    torus: &mut PatchTorus<Wave<K>, usize, TorusPatchLinks<E>>,
+    medium: &Picture,
+) -> Result<()> {
+    let dimensions = torus.dimensions();
+    let (width, height) = (dimensions[0], dimensions[1]);
+    let geometry = Geometry::new(Tiling::Hexagons, width, height);
+    let brightness = |x: usize, y: usize| medium.gray_value(&geometry, x, y);
+    let mut barriers = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let b = brightness(x, y);
+            if b < BARRIER {
+                barriers.push((x, y));
+            } else {
+                torus.set_weights(x, y, |ex, ey| {
+                    b.min(brightness(ex, ey)).max(BARRIER) as f64 / 255.0
+                })?;
+            }
+        }
+    }
+    info!("Barriers: {}", barriers.len());
+    for (x, y) in barriers {
+        torus.add_vacancy(x, y)?;
+    }
+    Ok(())
+}
+
+/// Cells of a medium that are darker than this are barriers.
+const BARRIER: u8 = 32;
+
+fn cell_example<K: Integrator>(
+    width: usize,
+    height: usize,
+    start: &Start,
+    output: &Output,
+) -> Result<()> {
     let generation = 0usize;
-    let init = Wave::new(0.0, false);
+    let init = Wave::<K>::new(0.0, false);
 
     let torus = new_cell_torus(
         Tiling::Hexagons,
         &[height, width],
-        generation.clone(),
+        generation,
         |_: &[usize]| init,
     )?;
 
-    run_example(torus, generation, export_dir)
+    run_example(torus, generation, start, output)
 }
 
-fn run_example<T: Torus<Wave, usize> + GrayScaleTorus<Wave, usize>>(
+fn run_example<T, K: Integrator>(
     torus: T,
     generation: usize,
-    export_dir: Option<&PathBuf>,
-) -> Result<()> {
-    let mut generation = generation;
+    start: &Start,
+    output: &Output,
+) -> Result<()>
+where
+    T: Torus<Wave<K>, usize> + GrayScaleTorus<Wave<K>, usize> + ColorTorus<Wave<K>, usize>,
+    <T::Spc as Space<Wave<K>, usize>>::Loc: Clone + PartialEq,
+{
     let width = torus.dimensions()[0];
     let height = torus.dimensions()[1];
     let mut torus = torus;
+    if let Some(picture) = start.initial {
+        picture.initialize(&mut torus, &generation, &1.0)?;
+    }
 
-    let center = Wave::new(0.0, true);
+    let center = Wave::<K>::new(0.0, true);
     let cx = width / 2;
     let cy = height / 2;
     torus.adjust(&generation, cx, cy, center)?;
 
-    // torus.info(&generation);
-    for i in 1..=(width * 10) {
-        torus.space_mut().update_all(&generation)?;
-        torus.space_mut().free(&generation)?;
-        generation = generation.successor();
-        // torus.info(&generation);
-        if i % width == 0 {
-            let m = smallest_local_maximum(torus.space(), &generation);
-            info!("Smallest local maximum: [{generation}]: [{m}]");
-            torus.export(&generation, &m, export_dir)?;
+    let export = |torus: &T, generation: &usize, _step: usize| {
+        let m = smallest_local_maximum(torus.space(), generation);
+        info!("Smallest local maximum: [{generation}]: [{m}]");
+        match output.color {
+            Some(palette) => {
+                let colors = WaveColors { scale: m, palette };
+                torus.export_color(generation, &colors, &View::Plane, output.export_dir)?
+            }
+            None => torus.export(generation, &m, &View::Plane, output.export_dir)?,
         }
-    }
+        export_svg(torus, generation, output.svg, |state: Option<Wave<K>>| {
+            let state = state.unwrap_or_default();
+            match output.color {
+                Some(palette) => state.rgb_value(&WaveColors { scale: m, palette }),
+                None => [state.gray_value(&m); 3],
+            }
+        })
+    };
+    let frame = |torus: &T, generation: &usize, animation: &mut Animation| {
+        let m = smallest_local_maximum(torus.space(), generation);
+        let label = generation.to_string();
+        match output.color {
+            Some(palette) => {
+                let colors = WaveColors { scale: m, palette };
+                animation.add_color_frame(
+                    &torus.render_color(generation, &colors, &View::Plane)?,
+                    &label,
+                )
+            }
+            None => animation.add_frame(&torus.render(generation, &m, &View::Plane)?, &label),
+        }
+    };
+    let mut simulation = Simulation::<T, Wave<K>, usize>::new(torus, generation)
+        .with_parameters(start.parameters)
+        .observe(Cadence::every(width).skip_start(), export)
+        .observe(
+            Cadence::every(1).skip_start(),
+            Recorder::new(Animation::create(output.animation)?, frame),
+        );
+    simulation.run(width * 10)?;
+    simulation.finish()?;
     Ok(())
 }
 
@@ -197,12 +592,15 @@ fn run_example<T: Torus<Wave, usize> + GrayScaleTorus<Wave, usize>>(
 struct Coords(usize, usize, usize);
 
 impl<Gen: Generation> State<Gen> for Coords {
+    type Parameters = ();
+
     fn update<Spc: Space<Self, Gen>>(
         _space: &Spc,
         region: &Spc::Reg,
         location: &Spc::Loc,
+        _context: &UpdateContext<(), Gen>,
     ) -> Result<Self> {
-        return Ok(region.state(location).unwrap_or_default());
+        Ok(region.state(location).unwrap_or_default())
     }
 }
 
@@ -214,7 +612,7 @@ impl Display for Coords {
     }
 }
 
-pub fn debug(size: usize) -> Result<()> {
+pub(crate) fn debug(size: usize) -> Result<()> {
     let width = size;
     let height = size;
     let dimensions = [height, width];
@@ -222,24 +620,18 @@ pub fn debug(size: usize) -> Result<()> {
     let torus = new_cell_torus(
         Tiling::Hexagons,
         &dimensions,
-        generation.clone(),
-        |v: &[usize]| {
-            Coords(
-                v[0],
-                v[1],
-                match get_index(v, &dimensions) {
-                    Ok(i) => i,
-                    _ => 0,
-                },
-            )
-        },
+        generation,
+        |v: &[usize]| Coords(v[0], v[1], get_index(v, &dimensions).unwrap_or_default()),
     )?;
     torus.info(&generation);
     Ok(())
 }
 
-fn smallest_local_maximum(torus: &impl Space<Wave, usize>, generation: &usize) -> f64 {
-    let result = torus.reduce(generation, MAX, |r, c, a| {
+fn smallest_local_maximum<K: Integrator>(
+    torus: &impl Space<Wave<K>, usize>,
+    generation: &usize,
+) -> f64 {
+    let result = torus.reduce(generation, f64::MAX, |r, c, a| {
         if let Ok(Some(amplitude)) = local_maximum(torus, r, c) {
             if amplitude < a { amplitude } else { a }
         } else {
@@ -249,21 +641,21 @@ fn smallest_local_maximum(torus: &impl Space<Wave, usize>, generation: &usize) -
     if result <= 0.0 { 1.0 } else { result }
 }
 
-fn local_maximum<Spc: Space<Wave, usize>>(
+fn local_maximum<K: Integrator, Spc: Space<Wave<K>, usize>>(
     space: &Spc,
     region: &Spc::Reg,
     location: &Spc::Loc,
 ) -> Result<Option<f64>> {
-    if let Some(this_state) = region.state(location) as Option<Wave> {
+    if let Some(this_state) = region.state(location) as Option<Wave<K>> {
         let amplitude = this_state.amplitude.abs();
         if amplitude <= 0.0 {
             return Ok(None);
         }
         for effector in location.effectors(space)? {
-            if let Some(other_state) = region.state(&effector) as Option<Wave> {
-                if other_state.amplitude.abs() > amplitude {
-                    return Ok(None);
-                }
+            if let Some(other_state) = region.state(&effector) as Option<Wave<K>>
+                && other_state.amplitude.abs() > amplitude
+            {
+                return Ok(None);
             }
         }
         Ok(Some(amplitude))